@@ -0,0 +1,30 @@
+#ifndef AYAKA_H
+#define AYAKA_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct AyakaContext AyakaContext;
+
+const char* ayaka_last_error(void);
+
+AyakaContext* ayaka_open(const char* config);
+void ayaka_free(AyakaContext* ctx);
+
+bool ayaka_set_locale(AyakaContext* ctx, const char* locale);
+bool ayaka_start_new(AyakaContext* ctx);
+bool ayaka_next_run(AyakaContext* ctx);
+char* ayaka_current_run(AyakaContext* ctx);
+bool ayaka_switch(AyakaContext* ctx, size_t index);
+
+void ayaka_string_free(char* s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,275 @@
+//! The C API of Ayaka runtime.
+//!
+//! A game is driven through an opaque [`AyakaContext`] handle,
+//! like the commands of the GUI.
+//! The actions are returned as the JSON strings of [`ActionJson`].
+//!
+//! The strings returned by the functions should be freed with [`ayaka_string_free`].
+//! The functions which fail return null or `false`,
+//! and the error message could be get by [`ayaka_last_error`].
+//! A panic inside the runtime is caught and reported as an error,
+//! instead of unwinding across the FFI boundary.
+
+use ayaka_runtime::{
+    anyhow::{anyhow, Result},
+    *,
+};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr::null_mut,
+};
+
+/// The opaque handle of a game.
+pub struct AyakaContext {
+    context: Context,
+    action: Option<Action>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(e: impl ToString) {
+    let msg = CString::new(e.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+}
+
+fn ffi_result<T>(f: impl FnOnce() -> Result<T>, fallback: T) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(res)) => res,
+        Ok(Err(e)) => {
+            set_last_error(e);
+            fallback
+        }
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic.".to_string());
+            set_last_error(format!("Panicked: {}", msg));
+            fallback
+        }
+    }
+}
+
+unsafe fn ffi_str<'a>(s: *const c_char) -> Result<&'a str> {
+    if s.is_null() {
+        return Err(anyhow!("Null string."));
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+unsafe fn ffi_context<'a>(ctx: *mut AyakaContext) -> Result<&'a mut AyakaContext> {
+    ctx.as_mut().ok_or_else(|| anyhow!("Null context."))
+}
+
+fn ffi_string(s: String) -> Result<*mut c_char> {
+    Ok(CString::new(s)?.into_raw())
+}
+
+fn open(config: &str) -> Result<AyakaContext> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let context = runtime.block_on(Context::open(config, FrontendType::Json))?;
+    Ok(AyakaContext {
+        context,
+        action: None,
+    })
+}
+
+/// Gets the message of the last error on current thread, or null if there's no error.
+///
+/// The string is owned by the library, and is valid until the next failed call.
+#[no_mangle]
+pub extern "C" fn ayaka_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map(|msg| msg.as_ptr())
+            .unwrap_or(std::ptr::null())
+    })
+}
+
+/// Opens a game from the config file or the game pack.
+///
+/// Returns null if failed.
+///
+/// # Safety
+/// `config` should be a valid null-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn ayaka_open(config: *const c_char) -> *mut AyakaContext {
+    ffi_result(
+        || {
+            ffi_str(config)
+                .and_then(open)
+                .map(|ctx| Box::into_raw(Box::new(ctx)))
+        },
+        null_mut(),
+    )
+}
+
+/// Frees the context returned by [`ayaka_open`], and shuts down the plugins.
+///
+/// # Safety
+/// `ctx` should be returned by [`ayaka_open`], or null.
+#[no_mangle]
+pub unsafe extern "C" fn ayaka_free(ctx: *mut AyakaContext) {
+    if !ctx.is_null() {
+        let ctx = Box::from_raw(ctx);
+        ffi_result(
+            move || {
+                ctx.context.shutdown();
+                Ok(())
+            },
+            (),
+        );
+    }
+}
+
+/// Sets the current locale, e.g., `en` or `zh-Hans`.
+///
+/// # Safety
+/// `ctx` should be returned by [`ayaka_open`].
+/// `locale` should be a valid null-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn ayaka_set_locale(ctx: *mut AyakaContext, locale: *const c_char) -> bool {
+    ffi_result(
+        || {
+            let ctx = ffi_context(ctx)?;
+            let locale = ffi_str(locale)?.parse::<Locale>()?;
+            ctx.context.set_locale(locale);
+            Ok(true)
+        },
+        false,
+    )
+}
+
+/// Starts a new game.
+///
+/// # Safety
+/// `ctx` should be returned by [`ayaka_open`].
+#[no_mangle]
+pub unsafe extern "C" fn ayaka_start_new(ctx: *mut AyakaContext) -> bool {
+    ffi_result(
+        || {
+            ffi_context(ctx).map(|ctx| {
+                ctx.context.init_new();
+                ctx.action = None;
+                true
+            })
+        },
+        false,
+    )
+}
+
+/// Steps to the next action.
+///
+/// Returns `false` if there's no action left, or failed.
+///
+/// # Safety
+/// `ctx` should be returned by [`ayaka_open`].
+#[no_mangle]
+pub unsafe extern "C" fn ayaka_next_run(ctx: *mut AyakaContext) -> bool {
+    ffi_result(
+        || {
+            ffi_context(ctx).map(|ctx| {
+                ctx.action = ctx.context.next_run();
+                ctx.action.is_some()
+            })
+        },
+        false,
+    )
+}
+
+/// Gets the current action as JSON, or null if there's no action.
+///
+/// The string should be freed with [`ayaka_string_free`].
+///
+/// # Safety
+/// `ctx` should be returned by [`ayaka_open`].
+#[no_mangle]
+pub unsafe extern "C" fn ayaka_current_run(ctx: *mut AyakaContext) -> *mut c_char {
+    ffi_result(
+        || {
+            ffi_context(ctx).and_then(|ctx| match &ctx.action {
+                Some(action) => ffi_string(serde_json::to_string(&ActionJson::from(action))?),
+                None => Ok(null_mut()),
+            })
+        },
+        null_mut(),
+    )
+}
+
+/// Chooses the switch of the current action by index.
+///
+/// # Safety
+/// `ctx` should be returned by [`ayaka_open`].
+#[no_mangle]
+pub unsafe extern "C" fn ayaka_switch(ctx: *mut AyakaContext, index: usize) -> bool {
+    ffi_result(
+        || {
+            let ctx = ffi_context(ctx)?;
+            let action = ctx
+                .action
+                .as_ref()
+                .ok_or_else(|| anyhow!("Action not initialized."))?;
+            ctx.context.switch(action, index)?;
+            Ok(true)
+        },
+        false,
+    )
+}
+
+/// Frees the string returned by the library.
+///
+/// # Safety
+/// `s` should be returned by the library, or null.
+#[no_mangle]
+pub unsafe extern "C" fn ayaka_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run() {
+        let config = CString::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../examples/Fibonacci/config.yaml"
+        ))
+        .unwrap();
+        unsafe {
+            let ctx = ayaka_open(config.as_ptr());
+            assert!(!ctx.is_null());
+            assert!(ayaka_start_new(ctx));
+            assert!(ayaka_current_run(ctx).is_null());
+            assert!(ayaka_next_run(ctx));
+            let json = ayaka_current_run(ctx);
+            let action: serde_json::Value =
+                serde_json::from_str(CStr::from_ptr(json).to_str().unwrap()).unwrap();
+            assert!(action["line"].is_array());
+            ayaka_string_free(json);
+
+            assert!(!ayaka_switch(ctx, 0));
+            assert!(!ayaka_last_error().is_null());
+            ayaka_free(ctx);
+        }
+        assert!(unsafe { ayaka_open(std::ptr::null()) }.is_null());
+    }
+
+    #[test]
+    fn catch_panic() {
+        assert!(!ffi_result(|| -> Result<bool> { panic!("boom") }, false));
+        let msg = unsafe { CStr::from_ptr(ayaka_last_error()) };
+        assert_eq!(msg.to_str().unwrap(), "Panicked: boom");
+    }
+}
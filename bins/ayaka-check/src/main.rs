@@ -1,5 +1,5 @@
 use ayaka_runtime::{
-    anyhow::{bail, Result},
+    anyhow::{anyhow, bail, Result},
     log::LevelFilter,
     *,
 };
@@ -13,12 +13,19 @@ use std::{
 #[clap(about, version, author)]
 pub struct Options {
     input: OsString,
+    /// Check the game statically and exit, without running it.
     #[clap(long)]
     check: bool,
+    /// Print the paragraph flow graph in Graphviz DOT format and exit.
+    #[clap(long)]
+    graph: bool,
     #[clap(long)]
     auto: bool,
     #[clap(short, long)]
     locale: Option<Locale>,
+    /// Print the actions, or the check messages, as JSON lines.
+    #[clap(long)]
+    json: bool,
 }
 
 fn read_line() -> Result<String> {
@@ -43,52 +50,103 @@ async fn main() -> Result<()> {
     env_logger::Builder::from_default_env()
         .filter_module("wasmer", LevelFilter::Warn)
         .try_init()?;
-    let context = Context::open(&opts.input, FrontendType::Text);
+    let frontend = if opts.json {
+        FrontendType::Json
+    } else {
+        FrontendType::Text
+    };
+    let context = Context::open(&opts.input, frontend);
     pin_mut!(context);
     while let Some(status) = context.next().await {
-        match status {
-            OpenStatus::LoadProfile => println!("Loading profile..."),
-            OpenStatus::CreateRuntime => println!("Creating runtime..."),
+        let msg = match status {
+            OpenStatus::LoadProfile => "Loading profile...".to_string(),
+            OpenStatus::CreateRuntime => "Creating runtime...".to_string(),
             OpenStatus::LoadPlugin(name, i, len) => {
-                println!("Loading plugin {} ({}/{})", name, i + 1, len)
+                format!("Loading plugin {} ({}/{})", name, i + 1, len)
             }
+            OpenStatus::PluginFailed(name, msg) => {
+                eprintln!("Failed to load plugin {}: {}", name, msg);
+                continue;
+            }
+        };
+        // Keep stdout clean for the JSON lines.
+        if opts.json {
+            eprintln!("{}", msg);
+        } else {
+            println!("{}", msg);
         }
     }
     let mut ctx = context.await?;
-    if opts.check && !ctx.check() {
-        bail!("Check failed.");
+    if opts.graph {
+        let locale = opts.locale.unwrap_or_else(Locale::current);
+        println!("digraph {{");
+        for (tag, targets) in ctx.game.paragraph_graph(&locale) {
+            println!("    {:?};", tag);
+            for target in targets {
+                println!("    {:?} -> {:?};", tag, target);
+            }
+        }
+        println!("}}");
+        return Ok(());
+    }
+    if opts.check {
+        let messages = ctx.diagnose();
+        for msg in &messages {
+            if opts.json {
+                println!("{}", serde_json::to_string(msg)?);
+            } else {
+                println!("{}", msg);
+            }
+        }
+        let errors = messages
+            .iter()
+            .filter(|msg| msg.severity == Severity::Error)
+            .count();
+        if errors > 0 {
+            bail!("Check failed with {} error(s).", errors);
+        }
+        return Ok(());
+    }
+    for (name, msg) in ctx.game_diagnostics() {
+        eprintln!("Plugin {}: {}", name, msg);
     }
     ctx.init_new();
     ctx.set_locale(opts.locale.unwrap_or_else(Locale::current));
     while let Some(action) = ctx.next_run() {
-        if let Some(name) = &action.character {
-            print!("_{}_", name);
-        }
-        for s in action.line {
-            print!("{}", s.as_str());
+        if opts.json {
+            println!("{}", serde_json::to_string(&ActionJson::from(&action))?);
+        } else {
+            if let Some(name) = &action.character {
+                print!("_{}_", name);
+            }
+            for s in action.line.iter() {
+                print!("{}", s.as_str());
+            }
         }
         if !action.switches.is_empty() {
-            for (i, s) in action.switches.iter().enumerate() {
-                if s.enabled {
-                    print!("\n-{}- {}", i + 1, s.text);
-                } else {
-                    print!("\n-x- {}", s.text);
+            if !opts.json {
+                for (i, s) in action.switches.iter().enumerate() {
+                    if s.enabled {
+                        print!("\n-{}- {}", i + 1, s.text);
+                    } else {
+                        print!("\n-x- {}", s.text);
+                    }
                 }
+                println!();
             }
-            println!();
             loop {
                 let s = read_line()?;
-                if let Ok(i) = s.trim().parse::<usize>() {
-                    let valid =
-                        i > 0 && i <= action.switches.len() && action.switches[i - 1].enabled;
-                    if valid {
-                        ctx.call(&action.switches[i - 1].action);
-                        break;
-                    }
+                let res = match s.trim().parse::<usize>() {
+                    Ok(i) if i > 0 => ctx.switch(&action, i - 1).map(|_| ()),
+                    _ => Err(anyhow!("Cannot parse switch \"{}\".", s.trim())),
+                };
+                match res {
+                    Ok(()) => break,
+                    Err(e) if opts.json => eprintln!("{} Enter again!", e),
+                    Err(e) => println!("{} Enter again!", e),
                 }
-                println!("Invalid switch, enter again!");
             }
-        } else {
+        } else if !opts.json {
             pause(opts.auto)?;
         }
     }
@@ -1,402 +1,996 @@
-#![cfg_attr(
-    all(not(debug_assertions), target_os = "windows"),
-    windows_subsystem = "windows"
-)]
-
-use ayaka_runtime::{
-    anyhow::{self, anyhow, Result},
-    log::{debug, info, warn},
-    *,
-};
-use flexi_logger::{FileSpec, LogSpecification, Logger};
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt::Display};
-use tauri::{async_runtime::Mutex, command, AppHandle, Manager, State};
-
-type CommandResult<T> = std::result::Result<T, CommandError>;
-
-#[derive(Debug, Default, Serialize)]
-struct CommandError {
-    msg: String,
-}
-
-impl<E: Into<anyhow::Error>> From<E> for CommandError {
-    fn from(e: E) -> Self {
-        Self {
-            msg: e.into().to_string(),
-        }
-    }
-}
-
-impl Display for CommandError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.msg)
-    }
-}
-
-#[command]
-fn ayaka_version() -> &'static str {
-    ayaka_runtime::version()
-}
-
-#[derive(Debug, Serialize)]
-struct FullSettings {
-    settings: Settings,
-    contexts: Vec<RawContext>,
-}
-
-#[derive(Debug, Clone, Serialize)]
-#[serde(tag = "t", content = "data")]
-enum OpenGameStatus {
-    LoadSettings,
-    LoadProfile(String),
-    CreateRuntime,
-    LoadPlugin(String, usize, usize),
-    LoadGlobalRecords,
-    LoadRecords,
-    Loaded,
-}
-
-fn emit_open_status(
-    handle: &AppHandle,
-    status: OpenGameStatus,
-) -> std::result::Result<(), tauri::Error> {
-    handle.emit_all("ayaka://open_status", status)
-}
-
-#[command]
-async fn open_game(handle: AppHandle, storage: State<'_, Storage>) -> CommandResult<()> {
-    let config = &storage.config;
-    let context = Context::open(config, FrontendType::Html);
-    pin_mut!(context);
-    while let Some(status) = context.next().await {
-        match status {
-            OpenStatus::LoadProfile => {
-                emit_open_status(&handle, OpenGameStatus::LoadProfile(config.clone()))?
-            }
-            OpenStatus::CreateRuntime => emit_open_status(&handle, OpenGameStatus::CreateRuntime)?,
-            OpenStatus::LoadPlugin(name, i, len) => {
-                emit_open_status(&handle, OpenGameStatus::LoadPlugin(name, i, len))?
-            }
-        }
-    }
-    let mut ctx = context.await?;
-
-    let window = handle.get_window("main").unwrap();
-    window.set_title(&ctx.game.title)?;
-    let settings = {
-        emit_open_status(&handle, OpenGameStatus::LoadSettings)?;
-        load_settings(&storage.ident).await.unwrap_or_else(|e| {
-            warn!("Load settings failed: {}", e);
-            Settings::new()
-        })
-    };
-    ctx.set_settings(settings);
-
-    emit_open_status(&handle, OpenGameStatus::LoadGlobalRecords)?;
-    ctx.set_global_record(
-        load_global_record(&storage.ident, &ctx.game.title)
-            .await
-            .unwrap_or_else(|e| {
-                warn!("Load global records failed: {}", e);
-                Default::default()
-            }),
-    );
-
-    emit_open_status(&handle, OpenGameStatus::LoadRecords)?;
-    *storage.records.lock().await = load_records(&storage.ident, &ctx.game.title)
-        .await
-        .unwrap_or_else(|e| {
-            warn!("Load records failed: {}", e);
-            Default::default()
-        });
-    *storage.context.lock().await = Some(ctx);
-
-    emit_open_status(&handle, OpenGameStatus::Loaded)?;
-    Ok(())
-}
-
-#[command]
-async fn get_settings(storage: State<'_, Storage>) -> CommandResult<Option<Settings>> {
-    Ok(storage
-        .context
-        .lock()
-        .await
-        .as_ref()
-        .map(|ctx| ctx.settings())
-        .cloned())
-}
-
-#[command]
-async fn set_settings(settings: Settings, storage: State<'_, Storage>) -> CommandResult<()> {
-    if let Some(context) = storage.context.lock().await.as_mut() {
-        context.set_settings(settings);
-    }
-    Ok(())
-}
-
-#[command]
-async fn get_records(storage: State<'_, Storage>) -> CommandResult<Vec<ActionRecord>> {
-    Ok(storage.records.lock().await.clone())
-}
-
-#[command]
-async fn save_record_to(index: usize, storage: State<'_, Storage>) -> CommandResult<()> {
-    let mut records = storage.records.lock().await;
-    if let Some(record) = storage
-        .context
-        .lock()
-        .await
-        .as_ref()
-        .map(|ctx| ctx.record.clone())
-    {
-        if index >= records.len() {
-            records.push(record);
-        } else {
-            records[index] = record;
-        }
-    }
-    Ok(())
-}
-
-#[command]
-async fn save_all(storage: State<'_, Storage>) -> CommandResult<()> {
-    if let Some(context) = storage.context.lock().await.as_ref() {
-        let game = &context.game.title;
-        save_settings(&storage.ident, context.settings()).await?;
-        save_global_record(&storage.ident, game, context.global_record()).await?;
-        save_records(&storage.ident, game, &storage.records.lock().await).await?;
-    }
-    Ok(())
-}
-
-#[command]
-fn choose_locale(locales: Vec<Locale>) -> CommandResult<Option<Locale>> {
-    let current = Locale::current();
-    debug!("Choose {} from {:?}", current, locales);
-    Ok(current.choose_from(&locales).cloned())
-}
-
-#[derive(Default)]
-struct Storage {
-    ident: String,
-    config: String,
-    records: Mutex<Vec<ActionRecord>>,
-    context: Mutex<Option<Context>>,
-    action: Mutex<Option<Action>>,
-}
-
-impl Storage {
-    pub fn new(ident: impl Into<String>, config: impl Into<String>) -> Self {
-        Self {
-            ident: ident.into(),
-            config: config.into(),
-            ..Default::default()
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct GameInfo {
-    pub title: String,
-    pub author: String,
-    pub props: HashMap<String, String>,
-}
-
-impl GameInfo {
-    pub fn new(game: &Game) -> Self {
-        Self {
-            title: game.title.clone(),
-            author: game.author.clone(),
-            props: game.props.clone(),
-        }
-    }
-}
-
-#[command]
-async fn info(storage: State<'_, Storage>) -> CommandResult<Option<GameInfo>> {
-    let ctx = storage.context.lock().await;
-    if let Some(ctx) = ctx.as_ref() {
-        Ok(Some(GameInfo::new(&ctx.game)))
-    } else {
-        warn!("Game hasn't been loaded.");
-        Ok(None)
-    }
-}
-
-#[command]
-async fn start_new(locale: Locale, storage: State<'_, Storage>) -> CommandResult<()> {
-    if let Some(ctx) = storage.context.lock().await.as_mut() {
-        ctx.init_new();
-        info!("Init new context with locale {}.", locale);
-    } else {
-        warn!("Game hasn't been loaded.")
-    }
-    Ok(())
-}
-
-#[command]
-async fn start_record(
-    locale: Locale,
-    index: usize,
-    storage: State<'_, Storage>,
-) -> CommandResult<()> {
-    if let Some(ctx) = storage.context.lock().await.as_mut() {
-        let raw_ctx = storage.records.lock().await[index].clone();
-        let last_line = raw_ctx.history.last().unwrap();
-        *storage.action.lock().await = Some(last_line.clone());
-        ctx.init_context(raw_ctx);
-        info!("Init new context with locale {}.", locale);
-    } else {
-        warn!("Game hasn't been loaded.")
-    }
-    Ok(())
-}
-
-#[command]
-async fn next_run(storage: State<'_, Storage>) -> CommandResult<bool> {
-    let mut context = storage.context.lock().await;
-    let action = context.as_mut().and_then(|context| context.next_run());
-    if let Some(action) = action {
-        debug!("Next action: {:?}", action);
-        *storage.action.lock().await = Some(action);
-        Ok(true)
-    } else {
-        debug!("No action left.");
-        *storage.action.lock().await = None;
-        Ok(false)
-    }
-}
-
-#[command]
-async fn next_back_run(storage: State<'_, Storage>) -> CommandResult<bool> {
-    let mut context = storage.context.lock().await;
-    let action = context.as_mut().and_then(|context| context.next_back_run());
-    if let Some(action) = action {
-        debug!("Last action: {:?}", action);
-        *storage.action.lock().await = Some(action);
-        Ok(true)
-    } else {
-        debug!("No action in the history.");
-        Ok(false)
-    }
-}
-
-#[command]
-async fn current_visited(storage: State<'_, Storage>) -> CommandResult<bool> {
-    let action = storage.action.lock().await;
-    let visited = if let Some(action) = action.as_ref() {
-        let context = storage.context.lock().await;
-        context
-            .as_ref()
-            .map(|context| context.visited(action))
-            .unwrap_or_default()
-    } else {
-        false
-    };
-    Ok(visited)
-}
-
-#[command]
-async fn current_run(storage: State<'_, Storage>) -> CommandResult<Option<Action>> {
-    Ok(storage.action.lock().await.as_ref().cloned())
-}
-
-#[command]
-async fn switch(i: usize, storage: State<'_, Storage>) -> CommandResult<RawValue> {
-    debug!("Switch {}", i);
-    let mut context = storage.context.lock().await;
-    let context = context
-        .as_mut()
-        .ok_or_else(|| anyhow!("Context not initialized."))?;
-    let action = storage.action.lock().await;
-    let switch = action
-        .as_ref()
-        .and_then(|action| action.switches.get(i))
-        .ok_or_else(|| anyhow!("Index error: {}", i))?;
-    Ok(context.call(&switch.action))
-}
-
-#[command]
-async fn history(storage: State<'_, Storage>) -> CommandResult<Vec<Action>> {
-    let mut hs = storage
-        .context
-        .lock()
-        .await
-        .as_ref()
-        .map(|context| context.record.history.clone())
-        .unwrap_or_default();
-    hs.reverse();
-    debug!("Get history {:?}", hs);
-    Ok(hs)
-}
-
-fn main() -> Result<()> {
-    let port =
-        portpicker::pick_unused_port().ok_or_else(|| anyhow!("failed to find unused port"))?;
-    info!("Picked port {}", port);
-    tauri::Builder::default()
-        .plugin(tauri_plugin_localhost::Builder::new(port).build())
-        .setup(|app| {
-            let ident = app.config().tauri.bundle.identifier.clone();
-            let log_handle = if cfg!(debug_assertions) {
-                Logger::with(LogSpecification::parse("warn,ayaka=debug,ayalog=debug")?)
-                    .log_to_stdout()
-                    .set_palette("b1;3;2;4;6".to_string())
-                    .use_utc()
-                    .start()?
-            } else {
-                Logger::with(LogSpecification::parse("info,wasmer=warn")?)
-                    .log_to_file(
-                        FileSpec::default()
-                            .directory(app.path_resolver().log_dir().unwrap())
-                            .basename("ayaka-gui"),
-                    )
-                    .use_utc()
-                    .start()?
-            };
-            app.manage(log_handle);
-            #[cfg(debug_assertions)]
-            {
-                let window = app.get_window("main").unwrap();
-                window.open_devtools();
-            }
-            let matches = app.get_cli_matches()?;
-            let config = matches.args["config"]
-                .value
-                .as_str()
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| {
-                    std::env::current_exe()
-                        .unwrap()
-                        .parent()
-                        .unwrap()
-                        .join("config.yaml")
-                        .to_string_lossy()
-                        .into_owned()
-                });
-            app.manage(Storage::new(ident, config));
-            Ok(())
-        })
-        .invoke_handler(tauri::generate_handler![
-            ayaka_version,
-            open_game,
-            get_settings,
-            set_settings,
-            get_records,
-            save_record_to,
-            save_all,
-            choose_locale,
-            info,
-            start_new,
-            start_record,
-            next_run,
-            next_back_run,
-            current_run,
-            current_visited,
-            switch,
-            history,
-        ])
-        .run(tauri::generate_context!())?;
-    Ok(())
-}
+#![cfg_attr(
+    all(not(debug_assertions), target_os = "windows"),
+    windows_subsystem = "windows"
+)]
+
+use ayaka_runtime::{
+    anyhow::{self, anyhow, Result},
+    log::{debug, info, warn, LevelFilter},
+    *,
+};
+use flexi_logger::{FileSpec, LogSpecification, Logger};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use tauri::{async_runtime::Mutex, command, AppHandle, Manager, State};
+
+type CommandResult<T> = std::result::Result<T, CommandError>;
+
+#[derive(Debug, Default, Serialize)]
+struct CommandError {
+    msg: String,
+}
+
+impl<E: Into<anyhow::Error>> From<E> for CommandError {
+    fn from(e: E) -> Self {
+        Self {
+            msg: e.into().to_string(),
+        }
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+#[command]
+fn ayaka_version() -> &'static str {
+    ayaka_runtime::version()
+}
+
+#[derive(Debug, Serialize)]
+struct FullSettings {
+    settings: Settings,
+    contexts: Vec<RawContext>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "t", content = "data")]
+enum OpenGameStatus {
+    LoadSettings,
+    LoadProfile(String),
+    CreateRuntime,
+    LoadPlugin(String, usize, usize),
+    PluginFailed(String, String),
+    LoadGlobalRecords,
+    LoadRecords,
+    Loaded,
+}
+
+/// The progress when starting to load plugins.
+const LOAD_PLUGIN_PROGRESS_START: f32 = 0.1;
+/// The slice of total progress occupied by loading plugins.
+const LOAD_PLUGIN_PROGRESS_SLICE: f32 = 0.6;
+
+impl OpenGameStatus {
+    fn progress(&self) -> Option<f32> {
+        let plugin_end = LOAD_PLUGIN_PROGRESS_START + LOAD_PLUGIN_PROGRESS_SLICE;
+        let step = (1.0 - plugin_end) / 4.0;
+        match self {
+            Self::LoadProfile(_) => Some(0.0),
+            Self::CreateRuntime => Some(LOAD_PLUGIN_PROGRESS_START / 2.0),
+            Self::LoadPlugin(_, i, len) => Some(
+                LOAD_PLUGIN_PROGRESS_START
+                    + LOAD_PLUGIN_PROGRESS_SLICE * (*i as f32) / (*len as f32),
+            ),
+            Self::PluginFailed(_, _) => None,
+            Self::LoadSettings => Some(plugin_end + step),
+            Self::LoadGlobalRecords => Some(plugin_end + step * 2.0),
+            Self::LoadRecords => Some(plugin_end + step * 3.0),
+            Self::Loaded => Some(1.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OpenGameStatusEvent {
+    #[serde(flatten)]
+    status: OpenGameStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<f32>,
+}
+
+fn emit_open_status(
+    handle: &AppHandle,
+    status: OpenGameStatus,
+) -> std::result::Result<(), tauri::Error> {
+    let progress = status.progress();
+    handle.emit_all(
+        "ayaka://open_status",
+        OpenGameStatusEvent { status, progress },
+    )
+}
+
+/// The count of evaluated expressions between the `ayaka://script_progress` events.
+const SCRIPT_PROGRESS_INTERVAL: usize = 100_000;
+
+async fn open_context(
+    handle: &AppHandle,
+    config: &str,
+    cancel: &AtomicBool,
+) -> CommandResult<Context> {
+    cancel.store(false, Ordering::Release);
+    let context = Context::open_with_cancel(config, FrontendType::Html, cancel);
+    pin_mut!(context);
+    while let Some(status) = context.next().await {
+        match status {
+            OpenStatus::LoadProfile => {
+                emit_open_status(handle, OpenGameStatus::LoadProfile(config.to_string()))?
+            }
+            OpenStatus::CreateRuntime => emit_open_status(handle, OpenGameStatus::CreateRuntime)?,
+            OpenStatus::LoadPlugin(name, i, len) => {
+                emit_open_status(handle, OpenGameStatus::LoadPlugin(name, i, len))?
+            }
+            OpenStatus::PluginFailed(name, msg) => {
+                emit_open_status(handle, OpenGameStatus::PluginFailed(name, msg))?
+            }
+        }
+    }
+    let mut ctx = context.await?;
+    let progress_handle = handle.clone();
+    ctx.set_progress_hook(SCRIPT_PROGRESS_INTERVAL, move |count| {
+        if let Err(e) = progress_handle.emit_all("ayaka://script_progress", count) {
+            warn!("Cannot emit script progress: {}", e);
+        }
+    });
+    let event_handle = handle.clone();
+    ctx.set_event_handler(move |event| {
+        if let Err(e) = event_handle.emit_all("ayaka://plugin_event", event) {
+            warn!("Cannot emit plugin event: {}", e);
+        }
+    });
+
+    let window = handle.get_window("main").unwrap();
+    window.set_title(&ctx.game.title)?;
+    Ok(ctx)
+}
+
+#[command]
+async fn open_game(handle: AppHandle, storage: State<'_, Storage>) -> CommandResult<()> {
+    let mut ctx = open_context(&handle, &storage.config, &storage.cancel_open).await?;
+    let settings = {
+        emit_open_status(&handle, OpenGameStatus::LoadSettings)?;
+        load_settings(&storage.ident).await.unwrap_or_else(|e| {
+            warn!("Load settings failed: {}", e);
+            Settings::new()
+        })
+    };
+    ctx.set_settings(settings);
+
+    emit_open_status(&handle, OpenGameStatus::LoadGlobalRecords)?;
+    ctx.set_global_record(
+        load_global_record(&storage.ident, &ctx.game.title)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Load global records failed: {}", e);
+                Default::default()
+            }),
+    );
+
+    emit_open_status(&handle, OpenGameStatus::LoadRecords)?;
+    *storage.records.lock().await = load_records(&storage.ident, &ctx.game.title)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Load records failed: {}", e);
+            Default::default()
+        });
+    *storage.autosave.lock().await = load_autosave_record(&storage.ident, &ctx.game.title)
+        .await
+        .ok();
+    *storage.context.lock().await = Some(ctx);
+
+    emit_open_status(&handle, OpenGameStatus::Loaded)?;
+    Ok(())
+}
+
+#[command]
+fn cancel_open_game(storage: State<'_, Storage>) {
+    storage.cancel_open.store(true, Ordering::Release);
+}
+
+#[command]
+async fn reload_game(handle: AppHandle, storage: State<'_, Storage>) -> CommandResult<()> {
+    if !cfg!(debug_assertions) {
+        return Err(anyhow!("Reloading is only available in debug builds.").into());
+    }
+    let mut ctx = open_context(&handle, &storage.config, &storage.cancel_open).await?;
+    let mut context = storage.context.lock().await;
+    let mut action = None;
+    if let Some(mut old_ctx) = context.take() {
+        ctx.set_settings(old_ctx.settings().clone());
+        ctx.set_global_record(old_ctx.global_record().clone());
+        let record = std::mem::take(&mut old_ctx.record);
+        old_ctx.shutdown();
+        let para_exists = record
+            .last_ctx()
+            .map(|raw_ctx| {
+                ctx.game
+                    .find_para_fallback(ctx.locale(), &raw_ctx.cur_para)
+                    .is_some()
+            })
+            .unwrap_or_default();
+        if para_exists {
+            action = record.history.last().cloned();
+            ctx.init_context(record);
+        } else {
+            warn!(
+                "Cannot restore the current paragraph after reloading; start from the beginning."
+            );
+            ctx.init_new();
+            action = ctx.next_run();
+        }
+    }
+    *storage.action.lock().await = action;
+    *context = Some(ctx);
+
+    emit_open_status(&handle, OpenGameStatus::Loaded)?;
+    Ok(())
+}
+
+#[command]
+async fn get_settings(storage: State<'_, Storage>) -> CommandResult<Option<Settings>> {
+    Ok(storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|ctx| ctx.settings())
+        .cloned())
+}
+
+#[command]
+async fn set_settings(settings: Settings, storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.as_mut() {
+        context.set_settings(settings);
+        // The last action is computed again if the locale is changed.
+        let mut action = storage.action.lock().await;
+        if action.is_some() {
+            if let Some(last_action) = context.record.history.last() {
+                *action = Some(last_action.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[command]
+async fn get_locales(storage: State<'_, Storage>) -> CommandResult<Vec<Locale>> {
+    Ok(storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|ctx| ctx.available_locales())
+        .unwrap_or_default())
+}
+
+#[command]
+async fn set_autosave_interval(secs: u64, storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.as_mut() {
+        let mut settings = context.settings().clone();
+        settings.autosave_interval = secs;
+        save_settings(&storage.ident, &settings).await?;
+        context.set_settings(settings);
+    }
+    Ok(())
+}
+
+#[command]
+async fn set_compress_saves(compress: bool, storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.as_mut() {
+        let mut settings = context.settings().clone();
+        settings.compress_saves = compress;
+        save_settings(&storage.ident, &settings).await?;
+        context.set_settings(settings);
+    }
+    Ok(())
+}
+
+#[command]
+async fn set_plugin_log_level(
+    name: String,
+    level: Option<LevelFilter>,
+    storage: State<'_, Storage>,
+) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.as_mut() {
+        let mut settings = context.settings().clone();
+        match level {
+            Some(level) => settings.plugin_log_levels.insert(name, level),
+            None => settings.plugin_log_levels.remove(&name),
+        };
+        save_settings(&storage.ident, &settings).await?;
+        context.set_settings(settings);
+    }
+    Ok(())
+}
+
+#[command]
+async fn set_prop(key: String, value: String, storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.as_mut() {
+        context.set_prop(key, value);
+    }
+    Ok(())
+}
+
+#[command]
+async fn shutdown(storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.take() {
+        let stats = context.text_cache_stats();
+        info!(
+            "Parsed {} texts in {:?}, and saved {:?} with {} cache hits.",
+            stats.misses,
+            stats.parse_time,
+            stats.saved_time(),
+            stats.hits
+        );
+        context.shutdown();
+    }
+    Ok(())
+}
+
+#[command]
+async fn get_records(storage: State<'_, Storage>) -> CommandResult<Vec<ActionRecord>> {
+    Ok(storage.records.lock().await.clone())
+}
+
+#[command]
+async fn get_autosave_record(storage: State<'_, Storage>) -> CommandResult<Option<ActionRecord>> {
+    Ok(storage.autosave.lock().await.clone())
+}
+
+#[command]
+async fn get_record_metadata(storage: State<'_, Storage>) -> CommandResult<Vec<RecordMetadata>> {
+    Ok(storage
+        .records
+        .lock()
+        .await
+        .iter()
+        .map(|record| record.metadata())
+        .collect())
+}
+
+#[command]
+async fn save_record_to(index: usize, storage: State<'_, Storage>) -> CommandResult<()> {
+    let mut records = storage.records.lock().await;
+    if let Some(mut record) = storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|ctx| ctx.record.clone())
+    {
+        record.update_save_time();
+        if index >= records.len() {
+            records.push(record);
+        } else {
+            records[index] = record;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+async fn delete_record(index: usize, storage: State<'_, Storage>) -> CommandResult<()> {
+    let context = storage.context.lock().await;
+    let mut records = storage.records.lock().await;
+    if index < records.len() {
+        records.remove(index);
+        if let Some(context) = context.as_ref() {
+            save_records(
+                &storage.ident,
+                &context.game.title,
+                &records,
+                context.settings().compress_saves,
+            )
+            .await?;
+        }
+    } else {
+        warn!("Record index {} out of range.", index);
+    }
+    Ok(())
+}
+
+#[command]
+async fn save_all(storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.as_ref() {
+        let game = &context.game.title;
+        save_settings(&storage.ident, context.settings()).await?;
+        save_global_record(&storage.ident, game, context.global_record()).await?;
+        save_records(
+            &storage.ident,
+            game,
+            &storage.records.lock().await,
+            context.settings().compress_saves,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[command]
+async fn export_saves(path: PathBuf, storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.as_ref() {
+        let bundle = SaveBundle {
+            game: context.game.title.clone(),
+            settings: context.settings().clone(),
+            global_record: context.global_record().clone(),
+            records: storage.records.lock().await.clone(),
+        };
+        tokio::fs::write(path, bundle.to_bytes()?).await?;
+    }
+    Ok(())
+}
+
+#[command]
+async fn import_saves(path: PathBuf, storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(context) = storage.context.lock().await.as_mut() {
+        let bytes = tokio::fs::read(path).await?;
+        let bundle =
+            ayaka_runtime::import_saves(&storage.ident, &context.game.title, &bytes).await?;
+        context.set_settings(bundle.settings);
+        let mut global_record = context.global_record().clone();
+        global_record.merge(&bundle.global_record);
+        context.set_global_record(global_record);
+        *storage.records.lock().await = bundle.records;
+    }
+    Ok(())
+}
+
+#[command]
+fn choose_locale(locales: Vec<Locale>) -> CommandResult<Option<Locale>> {
+    let current = Locale::current();
+    debug!("Choose {} from {:?}", current, locales);
+    Ok(current.choose_from(&locales).cloned())
+}
+
+const AUTOSAVE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+async fn autosave(handle: AppHandle) {
+    loop {
+        let storage = handle.state::<Storage>();
+        let interval = storage
+            .context
+            .lock()
+            .await
+            .as_ref()
+            .map(|ctx| ctx.settings().autosave_interval)
+            .unwrap_or_default();
+        if interval == 0 {
+            tokio::time::sleep(AUTOSAVE_POLL_INTERVAL).await;
+            continue;
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        let data = storage
+            .context
+            .lock()
+            .await
+            .as_ref()
+            .filter(|ctx| !ctx.record.history.is_empty())
+            .map(|ctx| {
+                (
+                    ctx.game.title.clone(),
+                    ctx.record.clone(),
+                    ctx.settings().compress_saves,
+                )
+            });
+        if let Some((game, mut record, compress)) = data {
+            record.update_save_time();
+            match save_autosave_record(&storage.ident, &game, &record, compress).await {
+                Ok(()) => {
+                    debug!("Autosaved.");
+                    *storage.autosave.lock().await = Some(record);
+                }
+                Err(e) => warn!("Autosave failed: {}", e),
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Storage {
+    ident: String,
+    config: String,
+    records: Mutex<Vec<ActionRecord>>,
+    autosave: Mutex<Option<ActionRecord>>,
+    quick_save: Mutex<Option<ContextSnapshot>>,
+    context: Mutex<Option<Context>>,
+    action: Mutex<Option<Action>>,
+    cancel_open: AtomicBool,
+}
+
+impl Storage {
+    pub fn new(ident: impl Into<String>, config: impl Into<String>) -> Self {
+        Self {
+            ident: ident.into(),
+            config: config.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GameInfo {
+    pub title: String,
+    pub author: String,
+    pub props: HashMap<String, String>,
+}
+
+impl GameInfo {
+    pub fn new(game: &Game) -> Self {
+        Self {
+            title: game.title.clone(),
+            author: game.author.clone(),
+            props: game.props.clone(),
+        }
+    }
+}
+
+#[command]
+async fn info(storage: State<'_, Storage>) -> CommandResult<Option<GameInfo>> {
+    let ctx = storage.context.lock().await;
+    if let Some(ctx) = ctx.as_ref() {
+        Ok(Some(GameInfo::new(&ctx.game)))
+    } else {
+        warn!("Game hasn't been loaded.");
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Endings {
+    pub all: BTreeSet<String>,
+    pub unlocked: BTreeSet<String>,
+}
+
+#[command]
+async fn get_endings(storage: State<'_, Storage>) -> CommandResult<Option<Endings>> {
+    let ctx = storage.context.lock().await;
+    if let Some(ctx) = ctx.as_ref() {
+        Ok(Some(Endings {
+            all: ctx.game.endings(),
+            unlocked: ctx.unlocked_endings().clone(),
+        }))
+    } else {
+        warn!("Game hasn't been loaded.");
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Gallery {
+    pub all: Vec<String>,
+    pub unlocked: BTreeSet<String>,
+}
+
+#[command]
+async fn get_gallery(storage: State<'_, Storage>) -> CommandResult<Option<Gallery>> {
+    let ctx = storage.context.lock().await;
+    if let Some(ctx) = ctx.as_ref() {
+        Ok(Some(Gallery {
+            all: ctx.game.gallery.clone(),
+            unlocked: ctx.unlocked_gallery().clone(),
+        }))
+    } else {
+        warn!("Game hasn't been loaded.");
+        Ok(None)
+    }
+}
+
+#[command]
+async fn resolve_locale(
+    locale: Locale,
+    storage: State<'_, Storage>,
+) -> CommandResult<Option<Locale>> {
+    Ok(storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|ctx| ctx.game.resolve_locale(&locale)))
+}
+
+#[command]
+async fn start_new(locale: Locale, storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(ctx) = storage.context.lock().await.as_mut() {
+        ctx.init_new();
+        info!("Init new context with locale {}.", locale);
+    } else {
+        warn!("Game hasn't been loaded.")
+    }
+    Ok(())
+}
+
+#[command]
+async fn start_record(
+    locale: Locale,
+    index: usize,
+    storage: State<'_, Storage>,
+) -> CommandResult<()> {
+    if let Some(ctx) = storage.context.lock().await.as_mut() {
+        let raw_ctx = storage.records.lock().await[index].clone();
+        let last_line = raw_ctx.history.last().unwrap();
+        *storage.action.lock().await = Some(last_line.clone());
+        ctx.init_context(raw_ctx);
+        info!("Init new context with locale {}.", locale);
+    } else {
+        warn!("Game hasn't been loaded.")
+    }
+    Ok(())
+}
+
+#[command]
+async fn start_autosave_record(locale: Locale, storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(ctx) = storage.context.lock().await.as_mut() {
+        let raw_ctx = storage
+            .autosave
+            .lock()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow!("There is no autosave record."))?;
+        let last_line = raw_ctx.history.last().unwrap();
+        *storage.action.lock().await = Some(last_line.clone());
+        ctx.init_context(raw_ctx);
+        info!("Init autosave context with locale {}.", locale);
+    } else {
+        warn!("Game hasn't been loaded.")
+    }
+    Ok(())
+}
+
+#[command]
+async fn quick_save(storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(ctx) = storage.context.lock().await.as_ref() {
+        *storage.quick_save.lock().await = Some(ctx.snapshot());
+        info!("Quick saved.");
+    } else {
+        warn!("Game hasn't been loaded.")
+    }
+    Ok(())
+}
+
+#[command]
+async fn quick_load(storage: State<'_, Storage>) -> CommandResult<()> {
+    if let Some(ctx) = storage.context.lock().await.as_mut() {
+        let snap = storage
+            .quick_save
+            .lock()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow!("There is no quick save."))?;
+        *storage.action.lock().await = snap.record.history.last().cloned();
+        ctx.restore(&snap);
+        info!("Quick loaded.");
+    } else {
+        warn!("Game hasn't been loaded.")
+    }
+    Ok(())
+}
+
+#[command]
+async fn next_run(storage: State<'_, Storage>) -> CommandResult<bool> {
+    let mut context = storage.context.lock().await;
+    let action = context.as_mut().and_then(|context| context.next_run());
+    if let Some(action) = action {
+        debug!("Next action: {:?}", action);
+        *storage.action.lock().await = Some(action);
+        Ok(true)
+    } else {
+        debug!("No action left.");
+        *storage.action.lock().await = None;
+        Ok(false)
+    }
+}
+
+#[command]
+async fn peek_next(storage: State<'_, Storage>) -> CommandResult<Option<Action>> {
+    let context = storage.context.lock().await;
+    Ok(context.as_ref().and_then(|context| context.peek_next()))
+}
+
+#[command]
+async fn skip_run(skip_all: bool, storage: State<'_, Storage>) -> CommandResult<Option<Action>> {
+    let mut context = storage.context.lock().await;
+    let action = context
+        .as_mut()
+        .and_then(|context| context.skip_run(skip_all));
+    debug!("Skip to action: {:?}", action);
+    *storage.action.lock().await = action.clone();
+    Ok(action)
+}
+
+#[command]
+async fn goto(tag: String, storage: State<'_, Storage>) -> CommandResult<bool> {
+    if !cfg!(debug_assertions) {
+        return Err(anyhow!("Goto is only available in debug builds.").into());
+    }
+    let mut context = storage.context.lock().await;
+    let context = context
+        .as_mut()
+        .ok_or_else(|| anyhow!("Context not initialized."))?;
+    context.goto(&tag)?;
+    let action = context.next_run();
+    debug!("Goto action: {:?}", action);
+    let has_action = action.is_some();
+    *storage.action.lock().await = action;
+    Ok(has_action)
+}
+
+#[command]
+async fn get_var(name: String, storage: State<'_, Storage>) -> CommandResult<Option<RawValue>> {
+    if !cfg!(debug_assertions) {
+        return Err(anyhow!("Variables are only available in debug builds.").into());
+    }
+    let context = storage.context.lock().await;
+    let context = context
+        .as_ref()
+        .ok_or_else(|| anyhow!("Context not initialized."))?;
+    Ok(context.get_var(&name))
+}
+
+#[command]
+async fn set_var(name: String, value: RawValue, storage: State<'_, Storage>) -> CommandResult<()> {
+    if !cfg!(debug_assertions) {
+        return Err(anyhow!("Variables are only available in debug builds.").into());
+    }
+    let mut context = storage.context.lock().await;
+    let context = context
+        .as_mut()
+        .ok_or_else(|| anyhow!("Context not initialized."))?;
+    debug!("Set variable {} = {:?}", name, value);
+    context.set_var(&name, value);
+    Ok(())
+}
+
+#[command]
+async fn next_back_run(storage: State<'_, Storage>) -> CommandResult<bool> {
+    let mut context = storage.context.lock().await;
+    let action = context.as_mut().and_then(|context| context.next_back_run());
+    if let Some(action) = action {
+        debug!("Last action: {:?}", action);
+        *storage.action.lock().await = Some(action);
+        Ok(true)
+    } else {
+        debug!("No action in the history.");
+        Ok(false)
+    }
+}
+
+#[command]
+async fn seek(index: usize, storage: State<'_, Storage>) -> CommandResult<()> {
+    let mut context = storage.context.lock().await;
+    if let Some(context) = context.as_mut() {
+        let action = context.seek(index)?;
+        debug!("Seek to action: {:?}", action);
+        *storage.action.lock().await = Some(action);
+    }
+    Ok(())
+}
+
+#[command]
+async fn current_visited(storage: State<'_, Storage>) -> CommandResult<bool> {
+    let action = storage.action.lock().await;
+    let visited = if let Some(action) = action.as_ref() {
+        let context = storage.context.lock().await;
+        context
+            .as_ref()
+            .map(|context| context.visited(action))
+            .unwrap_or_default()
+    } else {
+        false
+    };
+    Ok(visited)
+}
+
+#[command]
+async fn switch_visited(i: usize, storage: State<'_, Storage>) -> CommandResult<bool> {
+    let context = storage.context.lock().await;
+    let action = storage.action.lock().await;
+    let visited = match (context.as_ref(), action.as_ref()) {
+        (Some(context), Some(action)) => context.switch_visited(action, i),
+        _ => false,
+    };
+    Ok(visited)
+}
+
+#[command]
+async fn current_run(storage: State<'_, Storage>) -> CommandResult<Option<Action>> {
+    Ok(storage.action.lock().await.as_ref().cloned())
+}
+
+#[command]
+async fn end_reason(storage: State<'_, Storage>) -> CommandResult<Option<EndReason>> {
+    let context = storage.context.lock().await;
+    Ok(context
+        .as_ref()
+        .and_then(|context| context.end_reason().cloned()))
+}
+
+#[command]
+async fn switch(i: usize, storage: State<'_, Storage>) -> CommandResult<RawValue> {
+    debug!("Switch {}", i);
+    let mut context = storage.context.lock().await;
+    let context = context
+        .as_mut()
+        .ok_or_else(|| anyhow!("Context not initialized."))?;
+    let action = storage.action.lock().await;
+    let action = action
+        .as_ref()
+        .ok_or_else(|| anyhow!("Action not initialized."))?;
+    Ok(context.switch(action, i)?)
+}
+
+#[command]
+async fn choice_history(storage: State<'_, Storage>) -> CommandResult<Vec<Choice>> {
+    Ok(storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|context| context.choice_history().to_vec())
+        .unwrap_or_default())
+}
+
+#[command]
+async fn history(storage: State<'_, Storage>) -> CommandResult<Vec<Action>> {
+    let mut hs = storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|context| context.record.history.clone())
+        .unwrap_or_default();
+    hs.reverse();
+    debug!("Get history {:?}", hs);
+    Ok(hs)
+}
+
+#[command]
+async fn search_history(
+    query: String,
+    storage: State<'_, Storage>,
+) -> CommandResult<Vec<HistoryMatch>> {
+    let matches = storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|context| context.record.search_history(&query))
+        .unwrap_or_default();
+    debug!("Search history \"{}\": {:?}", query, matches);
+    Ok(matches)
+}
+
+#[command]
+async fn plugin_stats(
+    storage: State<'_, Storage>,
+) -> CommandResult<HashMap<String, plugin::PluginStats>> {
+    Ok(storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|context| context.plugin_stats())
+        .unwrap_or_default())
+}
+
+#[command]
+async fn plugin_capabilities(
+    storage: State<'_, Storage>,
+) -> CommandResult<HashMap<String, Vec<plugin::Capability>>> {
+    Ok(storage
+        .context
+        .lock()
+        .await
+        .as_ref()
+        .map(|context| context.plugin_capabilities().clone())
+        .unwrap_or_default())
+}
+
+fn main() -> Result<()> {
+    let port =
+        portpicker::pick_unused_port().ok_or_else(|| anyhow!("failed to find unused port"))?;
+    info!("Picked port {}", port);
+    tauri::Builder::default()
+        .plugin(tauri_plugin_localhost::Builder::new(port).build())
+        .setup(|app| {
+            let ident = app.config().tauri.bundle.identifier.clone();
+            let log_handle = if cfg!(debug_assertions) {
+                Logger::with(LogSpecification::parse("warn,ayaka=debug")?)
+                    .log_to_stdout()
+                    .set_palette("b1;3;2;4;6".to_string())
+                    .use_utc()
+                    .start()?
+            } else {
+                Logger::with(LogSpecification::parse("info,wasmer=warn")?)
+                    .log_to_file(
+                        FileSpec::default()
+                            .directory(app.path_resolver().log_dir().unwrap())
+                            .basename("ayaka-gui"),
+                    )
+                    .use_utc()
+                    .start()?
+            };
+            app.manage(log_handle);
+            #[cfg(debug_assertions)]
+            {
+                let window = app.get_window("main").unwrap();
+                window.open_devtools();
+            }
+            let matches = app.get_cli_matches()?;
+            let config = matches.args["config"]
+                .value
+                .as_str()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| {
+                    std::env::current_exe()
+                        .unwrap()
+                        .parent()
+                        .unwrap()
+                        .join("config.yaml")
+                        .to_string_lossy()
+                        .into_owned()
+                });
+            app.manage(Storage::new(ident, config));
+            tauri::async_runtime::spawn(autosave(app.handle()));
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            ayaka_version,
+            open_game,
+            cancel_open_game,
+            reload_game,
+            get_settings,
+            set_settings,
+            set_autosave_interval,
+            set_compress_saves,
+            set_plugin_log_level,
+            get_records,
+            get_record_metadata,
+            get_autosave_record,
+            save_record_to,
+            delete_record,
+            save_all,
+            export_saves,
+            import_saves,
+            choose_locale,
+            get_locales,
+            info,
+            resolve_locale,
+            start_new,
+            start_record,
+            start_autosave_record,
+            quick_save,
+            quick_load,
+            next_run,
+            skip_run,
+            goto,
+            get_var,
+            set_var,
+            next_back_run,
+            current_run,
+            end_reason,
+            get_endings,
+            get_gallery,
+            current_visited,
+            seek,
+            peek_next,
+            switch_visited,
+            switch,
+            choice_history,
+            history,
+            search_history,
+            plugin_stats,
+            plugin_capabilities,
+            set_prop,
+            shutdown,
+        ])
+        .run(tauri::generate_context!())?;
+    Ok(())
+}
@@ -11,6 +11,7 @@ export function conv_src(path?: string): string | undefined {
 export interface OpenGameStatus {
     t: keyof typeof OpenGameStatusType,
     data?: object,
+    progress?: number,
 }
 
 export enum OpenGameStatusType {
@@ -21,12 +22,20 @@ export enum OpenGameStatusType {
     LoadGlobalRecords,
     LoadRecords,
     Loaded,
+    PluginFailed,
 }
 
 export interface Settings {
     lang: Locale,
+    autosave_interval: number,
+    text_speed: number,
+    auto_advance_ms?: number,
+    compress_saves: boolean,
+    plugin_log_levels: { [name: string]: LogLevel },
 }
 
+export type LogLevel = "OFF" | "ERROR" | "WARN" | "INFO" | "DEBUG" | "TRACE"
+
 export interface RawContext {
     cur_para: string,
     cur_act: number,
@@ -48,6 +57,7 @@ export interface Action {
     ch_key?: string,
     character?: string,
     para_title?: string,
+    voice?: string,
     switches: Switch[],
     props: {
         bg?: string,
@@ -59,19 +69,25 @@ export interface Action {
     },
 }
 
-export interface ActionLine {
-    type: keyof typeof ActionLineType,
+export type ActionLine = {
+    type: "Chars" | "Block",
     data: string
+} | {
+    type: "Pause",
+    data: number
 }
 
 export enum ActionLineType {
     Chars,
     Block,
+    Pause,
 }
 
 export interface Switch {
     text: string,
     enabled: boolean,
+    default: boolean,
+    timeout_ms?: number,
 }
 
 export function ayaka_version(): Promise<string> {
@@ -82,6 +98,14 @@ export function open_game(): Promise<void> {
     return invoke("open_game")
 }
 
+export function cancel_open_game(): Promise<void> {
+    return invoke("cancel_open_game")
+}
+
+export function reload_game(): Promise<void> {
+    return invoke("reload_game")
+}
+
 export function get_settings(): Promise<Settings | undefined> {
     return invoke("get_settings")
 }
@@ -90,16 +114,54 @@ export function set_settings(settings: Settings): Promise<void> {
     return invoke("set_settings", { settings: settings })
 }
 
+export function set_autosave_interval(secs: number): Promise<void> {
+    return invoke("set_autosave_interval", { secs: secs })
+}
+
+export function export_saves(path: string): Promise<void> {
+    return invoke("export_saves", { path: path })
+}
+
+export function import_saves(path: string): Promise<void> {
+    return invoke("import_saves", { path: path })
+}
+
+export function set_compress_saves(compress: boolean): Promise<void> {
+    return invoke("set_compress_saves", { compress: compress })
+}
+
+export function set_plugin_log_level(name: string, level?: LogLevel): Promise<void> {
+    return invoke("set_plugin_log_level", { name: name, level: level })
+}
+
 export function get_records(): Promise<RawContext[]> {
     return invoke("get_records")
 }
 
+export interface RecordMetadata {
+    save_time: number,
+    para_title?: string,
+    last_text: string,
+}
+
+export function get_record_metadata(): Promise<RecordMetadata[]> {
+    return invoke("get_record_metadata")
+}
+
+export function get_autosave_record(): Promise<RawContext | undefined> {
+    return invoke("get_autosave_record")
+}
+
 export function save_record_to(index: number): Promise<void> {
     return invoke("save_record_to", { index: index })
 }
 
+export function delete_record(index: number): Promise<void> {
+    return invoke("delete_record", { index: index })
+}
+
 export async function set_locale(loc: Locale): Promise<void> {
-    let settings = await get_settings() ?? { lang: "" };
+    let settings = await get_settings() ?? { lang: "", autosave_interval: 60, text_speed: 1.0 };
     settings.lang = loc
     await set_settings(settings)
 }
@@ -108,6 +170,18 @@ export function save_all(): Promise<void> {
     return invoke("save_all")
 }
 
+export function set_prop(key: string, value: string): Promise<void> {
+    return invoke("set_prop", { key: key, value: value })
+}
+
+export function shutdown(): Promise<void> {
+    return invoke("shutdown")
+}
+
+export function get_locales(): Promise<Locale[]> {
+    return invoke("get_locales")
+}
+
 export function choose_locale(locales: Locale[]): Promise<Locale | undefined> {
     return invoke("choose_locale", { locales: locales })
 }
@@ -121,6 +195,10 @@ export async function info(): Promise<GameInfo> {
     return res ?? { title: "", author: "", props: {} }
 }
 
+export function resolve_locale(locale: Locale): Promise<Locale | undefined> {
+    return invoke("resolve_locale", { locale: locale })
+}
+
 export function start_new(locale: Locale): Promise<void> {
     return invoke("start_new", { locale: locale })
 }
@@ -129,10 +207,44 @@ export function start_record(locale: Locale, index: number): Promise<void> {
     return invoke("start_record", { locale: locale, index: index })
 }
 
+export function start_autosave_record(locale: Locale): Promise<void> {
+    return invoke("start_autosave_record", { locale: locale })
+}
+
+export function quick_save(): Promise<void> {
+    return invoke("quick_save")
+}
+
+export function quick_load(): Promise<void> {
+    return invoke("quick_load")
+}
+
 export function next_run(): Promise<boolean> {
     return invoke("next_run")
 }
 
+export function peek_next(): Promise<Action | undefined> {
+    return invoke("peek_next")
+}
+
+export function skip_run(skip_all: boolean): Promise<Action | undefined> {
+    return invoke("skip_run", { skipAll: skip_all })
+}
+
+export function goto(tag: string): Promise<boolean> {
+    return invoke("goto", { tag: tag })
+}
+
+export type RawValue = null | boolean | number | string | RawValue[]
+
+export function get_var(name: string): Promise<RawValue> {
+    return invoke("get_var", { name: name })
+}
+
+export function set_var(name: string, value: RawValue): Promise<void> {
+    return invoke("set_var", { name: name, value: value })
+}
+
 export function next_back_run(): Promise<boolean> {
     return invoke("next_back_run")
 }
@@ -141,22 +253,99 @@ export function current_run(): Promise<Action | undefined> {
     return invoke("current_run")
 }
 
+export type EndReason = { Ending: string } | "NoNext" | { Error: string }
+
+export function end_reason(): Promise<EndReason | undefined> {
+    return invoke("end_reason")
+}
+
+export interface Endings {
+    all: string[],
+    unlocked: string[],
+}
+
+export function get_endings(): Promise<Endings | undefined> {
+    return invoke("get_endings")
+}
+
+export interface Gallery {
+    all: string[],
+    unlocked: string[],
+}
+
+export function get_gallery(): Promise<Gallery | undefined> {
+    return invoke("get_gallery")
+}
+
 export async function current_visited(): Promise<boolean> {
     return invoke("current_visited")
 }
 
+export async function switch_visited(i: number): Promise<boolean> {
+    return invoke("switch_visited", { i: i })
+}
+
 export function switch_(i: number): Promise<void> {
     return invoke("switch", { i: i })
 }
 
+export interface Choice {
+    para: string,
+    act: number,
+    index: number,
+    text: string,
+}
+
+export function choice_history(): Promise<Choice[]> {
+    return invoke("choice_history")
+}
+
 export function history(): Promise<Action[]> {
     return invoke("history")
 }
 
+export function seek(index: number): Promise<void> {
+    return invoke("seek", { index: index })
+}
+
+export interface HistoryMatch {
+    index: number,
+    snippet: string,
+}
+
+export function search_history(query: string): Promise<HistoryMatch[]> {
+    return invoke("search_history", { query: query })
+}
+
+export interface PluginStats {
+    memory_bytes: number,
+    call_count: number,
+    total_call_time: { secs: number, nanos: number },
+}
+
+export function plugin_stats(): Promise<{ [name: string]: PluginStats }> {
+    return invoke("plugin_stats")
+}
+
+export type Capability = "Fs" | "Network" | "Random"
+
+export function plugin_capabilities(): Promise<{ [name: string]: Capability[] }> {
+    return invoke("plugin_capabilities")
+}
+
+// Payload of the "ayaka://plugin_event" event.
+export interface PluginEvent {
+    plugin: string,
+    name: string,
+    value: RawValue,
+}
+
 export function merge_lines(lines: ActionLine[]): string {
     let res = ""
     lines.forEach(s => {
-        res += s.data
+        if (s.type != "Pause") {
+            res += s.data
+        }
     })
     return res
 }
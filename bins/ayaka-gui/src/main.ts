@@ -4,13 +4,13 @@ import router from './router'
 import { createI18n } from 'vue-i18n'
 import messages from "./locale"
 import { library } from '@fortawesome/fontawesome-svg-core'
-import { faArrowLeft, faBackwardStep, faFileArrowDown, faFileArrowUp, faForward, faForwardStep, faGear, faHouse, faList, faPlay } from '@fortawesome/free-solid-svg-icons'
+import { faArrowLeft, faBackwardStep, faFileArrowDown, faFileArrowUp, faForward, faForwardStep, faGear, faHouse, faList, faPlay, faTrash } from '@fortawesome/free-solid-svg-icons'
 import { Ticker } from '@pixi/ticker'
 import { Live2DModel } from 'pixi-live2d-display'
 
 Live2DModel.registerTicker(Ticker)
 
-library.add(faArrowLeft, faFileArrowUp, faFileArrowDown, faList, faBackwardStep, faPlay, faForwardStep, faForward, faGear, faHouse)
+library.add(faArrowLeft, faFileArrowUp, faFileArrowDown, faList, faBackwardStep, faPlay, faForwardStep, faForward, faGear, faHouse, faTrash)
 
 const app = createApp(App)
 
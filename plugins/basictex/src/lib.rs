@@ -3,16 +3,25 @@ use ayaka_bindings::*;
 #[export]
 fn plugin_type() -> PluginType {
     PluginType::builder()
-        .text(["par", "textrm", "textsf", "texttt", "ruby"])
+        .text(["par", "textrm", "textsf", "texttt"])
         .build()
 }
 
+#[export]
+fn text_command_arity(name: String) -> Option<(usize, usize)> {
+    match name.as_str() {
+        "par" => Some((0, 0)),
+        "textrm" | "textsf" | "texttt" => Some((1, 1)),
+        _ => None,
+    }
+}
+
 #[export]
 fn par(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
     assert!(args.is_empty());
     let mut res = TextProcessResult::default();
     match ctx.frontend {
-        FrontendType::Text => res.line.push_back_chars("\n"),
+        FrontendType::Text | FrontendType::Json => res.line.push_back_chars("\n"),
         FrontendType::Html => res.line.push_back_chars("<br />"),
     }
     res
@@ -22,7 +31,7 @@ fn text_font(args: Vec<String>, ctx: TextProcessContext, fonts: &str) -> TextPro
     assert_eq!(args.len(), 1);
     let mut res = TextProcessResult::default();
     match ctx.frontend {
-        FrontendType::Text => res.line.push_back_chars(&args[0]),
+        FrontendType::Text | FrontendType::Json => res.line.push_back_chars(&args[0]),
         FrontendType::Html => {
             res.line
                 .push_back_block(format!("<font face=\"{}\">", fonts));
@@ -47,23 +56,3 @@ fn textsf(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
 fn texttt(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
     text_font(args, ctx, "Courier New")
 }
-
-#[export]
-fn ruby(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
-    assert_eq!(args.len(), 2);
-    let mut res = TextProcessResult::default();
-    match ctx.frontend {
-        FrontendType::Text => res
-            .line
-            .push_back_chars(format!("{}（{}）", args[0], args[1])),
-        FrontendType::Html => {
-            res.line.push_back_block("<ruby>");
-            res.line.push_back_chars(&args[0]);
-            res.line.push_back_block("<rp>（</rp><rt>");
-            res.line.push_back_chars(&args[1]);
-            res.line.push_back_block("</rt><rp>）</rp>");
-            res.line.push_back_block("</ruby>");
-        }
-    }
-    res
-}
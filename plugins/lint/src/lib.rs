@@ -0,0 +1,39 @@
+use ayaka_bindings::*;
+
+#[export]
+fn plugin_type() -> PluginType {
+    PluginType::builder().game().build()
+}
+
+fn referenced(key: &str, texts: &[String]) -> bool {
+    let res_cmd = format!("\\res{{{}}}", key);
+    let res_ref = format!("#{}", key);
+    texts.iter().any(|text| {
+        text.contains(&res_cmd)
+            || text.match_indices(&res_ref).any(|(i, _)| {
+                // Make sure it's not the prefix of a longer key.
+                !text[i + res_ref.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+            })
+    })
+}
+
+#[export]
+fn process_game(ctx: GameProcessContext) -> GameProcessResult {
+    let mut diagnostics = vec![];
+    for (loc, res) in &ctx.res {
+        for key in res.keys() {
+            // The character names are referenced by `\ch`.
+            if !key.starts_with("ch_") && !referenced(key, &ctx.texts) {
+                diagnostics.push(format!(
+                    "Resource \"{}\" of locale {} is not referenced.",
+                    key, loc
+                ));
+            }
+        }
+    }
+    diagnostics.sort();
+    GameProcessResult {
+        props: ctx.props,
+        diagnostics,
+    }
+}
@@ -13,6 +13,11 @@ fn plugin_type() -> PluginType {
         .build()
 }
 
+#[export]
+fn capabilities() -> Vec<Capability> {
+    vec![Capability::Fs]
+}
+
 const CH_DEFAULT: &str = "__ch_default__";
 const CH_ALL: &str = "__ch_all__";
 
@@ -66,7 +71,7 @@ fn hide(args: Vec<String>, _ctx: TextProcessContext) -> TextProcessResult {
 }
 
 #[export]
-fn process_action(mut ctx: ActionProcessContext) -> Action {
+fn process_action(mut ctx: ActionProcessContext) -> ActionProcessResult {
     let hide = ctx.action.props.remove("ch_hide");
     if hide.as_deref() == Some(CH_ALL) {
         ctx.action.props.remove("ch_models");
@@ -98,7 +103,7 @@ fn process_action(mut ctx: ActionProcessContext) -> Action {
             .collect::<Vec<_>>()
             .join(",");
     }
-    ctx.action
+    ctx.action.into()
 }
 
 #[export]
@@ -113,5 +118,8 @@ fn process_game(mut ctx: GameProcessContext) -> GameProcessResult {
             }
         }
     }
-    GameProcessResult { props: ctx.props }
+    GameProcessResult {
+        props: ctx.props,
+        ..Default::default()
+    }
 }
@@ -7,22 +7,46 @@ fn plugin_type() -> PluginType {
     PluginType::builder().action().build()
 }
 
+// The existing blocks and pauses are replaced by the characters in the private use area,
+// so that they are kept untouched by the parser, and restored when writing.
+const BLOCK_PLACEHOLDER_START: u32 = 0xE000;
+const BLOCK_PLACEHOLDER_END: u32 = 0xF8FF;
+
+fn placeholder_index(c: char) -> Option<usize> {
+    let c = c as u32;
+    if (BLOCK_PLACEHOLDER_START..=BLOCK_PLACEHOLDER_END).contains(&c) {
+        Some((c - BLOCK_PLACEHOLDER_START) as usize)
+    } else {
+        None
+    }
+}
+
 #[export]
-fn process_action(mut ctx: ActionProcessContext) -> Action {
-    let line = ctx
-        .action
-        .line
-        .into_iter()
-        .map(|s| s.into_string())
-        .collect::<Vec<_>>()
-        .concat();
+fn process_action(mut ctx: ActionProcessContext) -> ActionProcessResult {
+    if let FrontendType::Json = ctx.frontend {
+        return ctx.action.into();
+    }
+    let mut line = String::new();
+    let mut blocks = vec![];
+    for s in ctx.action.line {
+        match s {
+            ActionLine::Chars(s) => line.push_str(&s),
+            block => match char::from_u32(BLOCK_PLACEHOLDER_START + blocks.len() as u32) {
+                Some(c) if placeholder_index(c).is_some() => {
+                    line.push(c);
+                    blocks.push(block);
+                }
+                _ => line.push_str(block.as_str()),
+            },
+        }
+    }
     let parser = Parser::new(&line);
-    let writer = Writer::new(parser);
+    let writer = Writer::new(parser, blocks);
     ctx.action.line = match ctx.frontend {
         FrontendType::Html => writer.run_html().into_lines(),
-        FrontendType::Text => writer.run_text().into_lines(),
+        FrontendType::Text | FrontendType::Json => writer.run_text().into_lines(),
     };
-    ctx.action
+    ctx.action.into()
 }
 
 // The below code are modified from pulldown_cmark
@@ -45,13 +69,16 @@ struct Writer<'a, I> {
     table_alignments: Vec<Alignment>,
     table_cell_index: usize,
     numbers: HashMap<CowStr<'a>, usize>,
+    blocks: Vec<ActionLine>,
+    list_numbers: Vec<Option<u64>>,
+    pending_newline: bool,
 }
 
 impl<'a, I> Writer<'a, I>
 where
     I: Iterator<Item = Event<'a>>,
 {
-    fn new(iter: I) -> Self {
+    fn new(iter: I, blocks: Vec<ActionLine>) -> Self {
         Self {
             iter,
             writer: ActionLines::default(),
@@ -59,29 +86,151 @@ where
             table_alignments: vec![],
             table_cell_index: 0,
             numbers: HashMap::new(),
+            blocks,
+            list_numbers: vec![],
+            pending_newline: false,
         }
     }
 
     /// Writes a buffer, and tracks whether or not a newline was written.
     fn write_chars<'s>(&mut self, s: impl Into<Cow<'s, str>>) {
-        self.writer.push_back_chars(s);
+        self.write_restored(s.into(), false);
     }
 
     fn write_block<'s>(&mut self, s: impl Into<Cow<'s, str>>) {
-        self.writer.push_back_block(s);
+        self.write_restored(s.into(), true);
+    }
+
+    /// Writes a buffer, with the placeholders restored as the original blocks.
+    fn write_restored(&mut self, s: Cow<str>, is_block: bool) {
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            if let Some(block) = placeholder_index(c).and_then(|index| self.blocks.get(index)) {
+                let block = block.clone();
+                self.write_raw(&s[start..i], is_block);
+                match block {
+                    ActionLine::Block(block) => self.writer.push_back_block(block),
+                    block => self.writer.push_back(block),
+                }
+                start = i + c.len_utf8();
+            }
+        }
+        self.write_raw(&s[start..], is_block);
+    }
+
+    fn write_raw(&mut self, s: &str, is_block: bool) {
+        if s.is_empty() {
+            return;
+        }
+        if is_block {
+            self.writer.push_back_block(s);
+        } else {
+            self.writer.push_back_chars(s);
+        }
+    }
+
+    /// Writes plain text, with the pending newline written before.
+    fn write_text<'s>(&mut self, s: impl Into<Cow<'s, str>>) {
+        if self.pending_newline {
+            self.pending_newline = false;
+            self.write_chars("\n");
+        }
+        self.write_chars(s);
+    }
+
+    /// Ends a block-level element in plain text.
+    /// The newline is delayed until the next text, so that no trailing newline is written.
+    fn end_text_block(&mut self) {
+        if !self.writer.is_empty() {
+            self.pending_newline = true;
+        }
     }
 
     fn run_text(mut self) -> Self {
         while let Some(event) = self.iter.next() {
             match event {
-                Text(text) | Code(text) | Html(text) => self.write_chars(text.as_ref()),
-                SoftBreak | HardBreak | Rule => self.write_chars("\n"),
-                _ => {}
+                Start(tag) => self.start_text_tag(tag),
+                End(tag) => self.end_text_tag(tag),
+                Text(text) | Code(text) | Html(text) => self.write_text(text.into_string()),
+                SoftBreak | HardBreak => self.write_text("\n"),
+                Rule => {
+                    self.end_text_block();
+                    self.write_text("---");
+                    self.end_text_block();
+                }
+                FootnoteReference(name) => {
+                    let len = self.numbers.len() + 1;
+                    let number = *self.numbers.entry(name).or_insert(len);
+                    self.write_text(format!("[{}]", number));
+                }
+                TaskListMarker(true) => self.write_text("[x] "),
+                TaskListMarker(false) => self.write_text("[ ] "),
             }
         }
         self
     }
 
+    fn start_text_tag(&mut self, tag: Tag<'a>) {
+        match tag {
+            Tag::List(start) => {
+                self.end_text_block();
+                self.list_numbers.push(start);
+            }
+            Tag::Item => {
+                self.end_text_block();
+                let indent = "  ".repeat(self.list_numbers.len().saturating_sub(1));
+                let bullet = match self.list_numbers.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}{}. ", indent, *number - 1)
+                    }
+                    _ => format!("{}- ", indent),
+                };
+                self.write_text(bullet);
+            }
+            Tag::TableHead | Tag::TableRow => {
+                self.end_text_block();
+                self.table_cell_index = 0;
+            }
+            Tag::TableCell => {
+                if self.table_cell_index > 0 {
+                    self.write_text(" | ");
+                }
+            }
+            Tag::FootnoteDefinition(name) => {
+                self.end_text_block();
+                let len = self.numbers.len() + 1;
+                let number = *self.numbers.entry(name).or_insert(len);
+                self.write_text(format!("[{}]: ", number));
+            }
+            _ => {}
+        }
+    }
+
+    fn end_text_tag(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph
+            | Tag::Heading(_, _, _)
+            | Tag::BlockQuote
+            | Tag::CodeBlock(_)
+            | Tag::Table(_)
+            | Tag::TableHead
+            | Tag::TableRow
+            | Tag::Item
+            | Tag::FootnoteDefinition(_) => self.end_text_block(),
+            Tag::List(_) => {
+                self.list_numbers.pop();
+                self.end_text_block();
+            }
+            Tag::TableCell => self.table_cell_index += 1,
+            Tag::Link(LinkType::Autolink | LinkType::Email, _, _) => {}
+            Tag::Link(_, dest, _) | Tag::Image(_, dest, _) => {
+                self.write_text(format!(" ({})", dest));
+            }
+            _ => {}
+        }
+    }
+
     fn run_html(mut self) -> Self {
         while let Some(event) = self.iter.next() {
             match event {
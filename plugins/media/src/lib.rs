@@ -11,6 +11,16 @@ fn plugin_type() -> PluginType {
         .build()
 }
 
+#[export]
+fn capabilities() -> Vec<Capability> {
+    vec![Capability::Fs]
+}
+
+#[export]
+fn text_command_arity(_name: String) -> Option<(usize, usize)> {
+    Some((1, 1))
+}
+
 fn find_exists(name: &str, base_dir: impl AsRef<Path>, exs: &[&str]) -> Option<PathBuf> {
     let base_dir = base_dir.as_ref();
     exs.iter()
@@ -82,7 +92,7 @@ fn video(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
 }
 
 #[export]
-fn process_action(mut ctx: ActionProcessContext) -> Action {
+fn process_action(mut ctx: ActionProcessContext) -> ActionProcessResult {
     if let Some(last_action) = &ctx.last_action {
         for prop in ["bg", "bgm"] {
             if let Some(value) = last_action.props.get(prop) {
@@ -93,7 +103,11 @@ fn process_action(mut ctx: ActionProcessContext) -> Action {
             }
         }
     }
-    let voice_id = ctx.action.ctx.cur_act.to_string();
+    let voice_id = ctx
+        .action
+        .voice
+        .clone()
+        .unwrap_or_else(|| ctx.action.ctx.cur_act.to_string());
     let res = file(
         vec![voice_id],
         ctx.root_path
@@ -110,7 +124,7 @@ fn process_action(mut ctx: ActionProcessContext) -> Action {
     for (key, value) in res.props.into_iter() {
         ctx.action.props.insert(key, value);
     }
-    ctx.action
+    ctx.action.into()
 }
 
 #[export]
@@ -123,5 +137,8 @@ fn process_game(mut ctx: GameProcessContext) -> GameProcessResult {
             *bg = path.to_string_lossy().into_owned();
         }
     }
-    GameProcessResult { props: ctx.props }
+    GameProcessResult {
+        props: ctx.props,
+        ..Default::default()
+    }
 }
@@ -1,24 +1,48 @@
 #![feature(once_cell)]
 
 use ayaka_bindings::*;
-use log::error;
+use log::{error, warn};
 use rand::{rngs::StdRng, Rng, SeedableRng};
-use std::sync::{LazyLock, Mutex};
+use std::{
+    cmp::Ordering,
+    sync::{LazyLock, Mutex},
+};
 
 #[export]
 fn plugin_type() -> PluginType {
     PluginType::default()
 }
 
+#[export]
+fn capabilities() -> Vec<Capability> {
+    vec![Capability::Random]
+}
+
+#[export]
+fn global_methods() -> Vec<String> {
+    vec!["rnd".to_string(), "roll".to_string()]
+}
+
 static RNG: LazyLock<Mutex<StdRng>> = LazyLock::new(|| Mutex::new(StdRng::from_entropy()));
 
+fn gen_range(rng: &mut StdRng, lo: i64, hi: i64) -> i64 {
+    match lo.cmp(&hi) {
+        Ordering::Less => rng.gen_range(lo..hi),
+        Ordering::Equal => lo,
+        Ordering::Greater => {
+            warn!("Reversed range {}..{}, swapping the bounds.", lo, hi);
+            rng.gen_range(hi..lo)
+        }
+    }
+}
+
 #[export]
 fn rnd(args: Vec<RawValue>) -> RawValue {
     if let Ok(mut rng) = RNG.lock() {
         let res = match args.len() {
             0 => rng.gen(),
-            1 => rng.gen_range(0..args[0].get_num()),
-            _ => rng.gen_range(args[0].get_num()..args[1].get_num()),
+            1 => gen_range(&mut rng, 0, args[0].get_num()),
+            _ => gen_range(&mut rng, args[0].get_num(), args[1].get_num()),
         };
         RawValue::Num(res)
     } else {
@@ -26,3 +50,95 @@ fn rnd(args: Vec<RawValue>) -> RawValue {
         RawValue::Unit
     }
 }
+
+/// The maximum count of the dice in a term, to keep a roll fast.
+const MAX_DICE: i64 = 1000;
+
+enum DiceTerm {
+    Dice { count: i64, sides: i64 },
+    Const(i64),
+}
+
+fn parse_term(term: &str) -> Option<DiceTerm> {
+    if let Some((count, sides)) = term.split_once(['d', 'D']) {
+        let count = if count.is_empty() {
+            1
+        } else {
+            count.parse().ok()?
+        };
+        let sides = sides.parse().ok()?;
+        if count > MAX_DICE {
+            warn!("Too many dice {}, at most {} are allowed.", count, MAX_DICE);
+            None
+        } else if count > 0 && sides > 0 {
+            Some(DiceTerm::Dice { count, sides })
+        } else {
+            None
+        }
+    } else {
+        term.parse().ok().map(DiceTerm::Const)
+    }
+}
+
+fn parse_dice(notation: &str) -> Option<Vec<(bool, DiceTerm)>> {
+    let notation = notation
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let mut terms = vec![];
+    let mut negative = false;
+    let mut start = 0;
+    for (i, c) in notation.char_indices() {
+        if i == 0 && (c == '+' || c == '-') {
+            negative = c == '-';
+            start = 1;
+        } else if c == '+' || c == '-' {
+            terms.push((negative, parse_term(&notation[start..i])?));
+            negative = c == '-';
+            start = i + 1;
+        }
+    }
+    terms.push((negative, parse_term(&notation[start..])?));
+    Some(terms)
+}
+
+#[export]
+fn roll(args: Vec<RawValue>) -> RawValue {
+    let notation = args.first().map(|v| v.get_str()).unwrap_or_default();
+    let terms = match parse_dice(&notation) {
+        Some(terms) => terms,
+        None => {
+            error!("Invalid dice notation \"{}\".", notation);
+            return RawValue::Unit;
+        }
+    };
+    if let Ok(mut rng) = RNG.lock() {
+        let mut sum = 0;
+        for (negative, term) in terms {
+            let value = match term {
+                DiceTerm::Dice { count, sides } => (0..count)
+                    .map(|_| rng.gen_range(1..=sides))
+                    .fold(0i64, |sum, value| sum.saturating_add(value)),
+                DiceTerm::Const(value) => value,
+            };
+            sum = if negative {
+                sum.saturating_sub(value)
+            } else {
+                sum.saturating_add(value)
+            };
+        }
+        RawValue::Num(sum)
+    } else {
+        error!("Cannot get random engine.");
+        RawValue::Unit
+    }
+}
+
+#[export]
+fn seed(value: u64) {
+    if let Ok(mut rng) = RNG.lock() {
+        *rng = StdRng::seed_from_u64(value);
+    } else {
+        error!("Cannot get random engine.");
+    }
+}
@@ -0,0 +1,49 @@
+use ayaka_bindings::*;
+use log::warn;
+
+#[export]
+fn plugin_type() -> PluginType {
+    PluginType::builder().text(["ruby"]).build()
+}
+
+#[export]
+fn text_command_arity(name: String) -> Option<(usize, usize)> {
+    match name.as_str() {
+        "ruby" => Some((1, 2)),
+        _ => None,
+    }
+}
+
+#[export]
+fn ruby(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
+    let mut res = TextProcessResult::default();
+    let (base, reading) = match args.as_slice() {
+        [base, reading] => (base, reading),
+        [base, reading, ..] => {
+            warn!("Too many args for \\ruby, the extra ones are ignored.");
+            (base, reading)
+        }
+        [base] => {
+            warn!("Missing reading for \\ruby{{{}}}.", base);
+            res.line.push_back_chars(base);
+            return res;
+        }
+        [] => {
+            warn!("Missing base text for \\ruby.");
+            return res;
+        }
+    };
+    match ctx.frontend {
+        FrontendType::Text | FrontendType::Json => {
+            res.line.push_back_chars(format!("{}({})", base, reading))
+        }
+        FrontendType::Html => {
+            res.line.push_back_block("<ruby>");
+            res.line.push_back_chars(base);
+            res.line.push_back_block("<rp>(</rp><rt>");
+            res.line.push_back_chars(reading);
+            res.line.push_back_block("</rt><rp>)</rp></ruby>");
+        }
+    }
+    res
+}
@@ -1,11 +1,32 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, parse_str, Ident, ItemFn};
+use syn::{parse_macro_input, parse_str, Error, FnArg, Ident, ItemFn};
 
+/// Exports a function to the runtime.
+///
+/// The parameters are decoded from a MessagePack array, in the order of declaration,
+/// and the return value is encoded with MessagePack.
+/// Any type implementing `Serialize` could be returned,
+/// including tuples, which are encoded as MessagePack arrays:
+///
+/// ```ignore
+/// #[export]
+/// fn process(mut ctx: ActionProcessContext) -> (Action, Vec<String>) {
+///     let logs = vec!["processed".to_string()];
+///     (ctx.action, logs)
+/// }
+/// ```
+///
+/// The runtime could decode it with `Host::call::<_, (Action, Vec<String>)>`.
 #[proc_macro_attribute]
 pub fn export(_attr: TokenStream, input: TokenStream) -> TokenStream {
     let func = input.clone();
     let func = parse_macro_input!(func as ItemFn);
+    if let Some(FnArg::Receiver(recv)) = func.sig.inputs.first() {
+        return Error::new_spanned(recv, "methods are not supported in #[export]")
+            .to_compile_error()
+            .into();
+    }
     let name = func.sig.ident;
     let name_str = name.to_string();
     let expname = parse_str::<Ident>(&format!("__{}", name_str)).unwrap();
@@ -5,12 +5,15 @@
 
 use ayaka_script_types::{Program, RawValue};
 use fallback::{FallbackSpec, IsEmpty2};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use std::{
     borrow::Cow,
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
+    error::Error,
+    fmt::Display,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
 #[doc(hidden)]
@@ -102,6 +105,32 @@ impl PluginTypeBuilder {
     }
 }
 
+/// The capabilities a plugin requires from the host.
+///
+/// A plugin could provide a function `capabilities` to declare them.
+/// The runtime refuses to load a plugin which imports the host functions
+/// of an undeclared capability.
+///
+/// ```ignore
+/// use ayaka_bindings::*;
+///
+/// #[export]
+/// fn capabilities() -> Vec<Capability> {
+///     vec![Capability::Fs]
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Capability {
+    /// Access the file system.
+    /// The root directory is preopened only for the plugins declaring it.
+    Fs,
+    /// Access the network through the sockets.
+    Network,
+    /// Get random bytes from the host.
+    /// It is only for disclosure, because the standard library may require it.
+    Random,
+}
+
 /// The type of current frontend.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum FrontendType {
@@ -109,6 +138,9 @@ pub enum FrontendType {
     Text,
     /// The frontend renders HTML.
     Html,
+    /// The frontend consumes the actions as JSON, and renders its own UI.
+    /// The action plugins should leave the lines untouched.
+    Json,
 }
 
 /// The unit of one line in an action.
@@ -116,6 +148,8 @@ pub enum FrontendType {
 /// If a frontend supports animation,
 /// the characters in [`ActionLine::Chars`] should be printed one by one,
 /// while the characters in [`ActionLine::Block`] should be printed together.
+/// The [`ActionLine::Pause`] is a hint to pause the printing,
+/// and it is ignored if the frontend doesn't support animation.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum ActionLine {
@@ -125,6 +159,8 @@ pub enum ActionLine {
     /// Characters printed together.
     /// Usually they are HTML tags or other control characters.
     Block(String),
+    /// A pause in milliseconds before printing the following characters.
+    Pause(u64),
 }
 
 impl ActionLine {
@@ -139,16 +175,20 @@ impl ActionLine {
     }
 
     /// Gets a reference of [`str`].
+    /// It is empty for [`ActionLine::Pause`].
     pub fn as_str(&self) -> &str {
         match self {
             Self::Chars(s) | Self::Block(s) => s,
+            Self::Pause(_) => "",
         }
     }
 
     /// Gets the inner [`String`].
+    /// It is empty for [`ActionLine::Pause`].
     pub fn into_string(self) -> String {
         match self {
             Self::Chars(s) | Self::Block(s) => s,
+            Self::Pause(_) => String::new(),
         }
     }
 }
@@ -239,9 +279,19 @@ pub struct RawContext {
     /// Current text index.
     pub cur_act: usize,
     /// Current local variables.
+    #[serde(serialize_with = "serialize_sorted")]
     pub locals: VarMap,
 }
 
+/// Serializes a [`HashMap`] with the keys sorted,
+/// so that the same map is always serialized into the same bytes.
+fn serialize_sorted<K: Ord + Serialize, V: Serialize, S: Serializer>(
+    map: &HashMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
 /// The full action information in one line of config.
 /// It provides the full texts and other properties exacted from [`ayaka_script::Text`].
 #[derive(Debug, Default, Clone, Serialize, Deserialize, FallbackSpec)]
@@ -258,14 +308,24 @@ pub struct Action {
     pub character: Option<String>,
     /// The title of current paragraph.
     pub para_title: Option<String>,
+    /// The voice of current line, resolved from the resources if possible.
+    pub voice: Option<String>,
     /// The switches.
     pub switches: Vec<Switch>,
-    /// The other custom properties.
-    pub props: HashMap<String, String>,
+    /// The other custom properties, sorted by the keys.
+    pub props: BTreeMap<String, String>,
+}
+
+impl Action {
+    /// The index of the default switch, which is chosen when the player doesn't act.
+    pub fn default_switch(&self) -> Option<usize> {
+        self.switches.iter().position(|s| s.default)
+    }
 }
 
 /// One switch in the switches of an [`Action`].
 #[derive(Debug, Default, Clone, Serialize, Deserialize, FallbackSpec)]
+#[serde(default)]
 pub struct Switch {
     /// The switch text.
     pub text: String,
@@ -273,6 +333,11 @@ pub struct Switch {
     pub action: Program,
     /// Whether the switch is enabled.
     pub enabled: bool,
+    /// Whether the switch is chosen when the player doesn't act.
+    /// At most one switch of an [`Action`] is the default one.
+    pub default: bool,
+    /// The delay in milliseconds before the frontend chooses the default switch.
+    pub timeout_ms: Option<u64>,
 }
 
 /// The argument to action plugin.
@@ -282,9 +347,9 @@ pub struct Switch {
 /// use ayaka_bindings::*;
 ///
 /// #[export]
-/// fn process_action(mut ctx: ActionProcessContext) -> Action {
+/// fn process_action(mut ctx: ActionProcessContext) -> ActionProcessResult {
 ///     // Process the action...
-///     ctx.action
+///     ctx.action.into()
 /// }
 /// ```
 #[derive(Debug, Serialize, Deserialize)]
@@ -312,6 +377,30 @@ pub struct ActionProcessContextRef<'a> {
     pub action: &'a Action,
 }
 
+/// The result of action plugins.
+/// See examples at [`ActionProcessContext`].
+///
+/// The runtime also accepts an [`Action`] returned by the older plugins,
+/// as a result which is not terminal.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ActionProcessResult {
+    /// The processed action.
+    pub action: Action,
+    /// Whether the action is final.
+    /// If `true`, the downstream action plugins won't be called for this action.
+    #[serde(default)]
+    pub terminal: bool,
+}
+
+impl From<Action> for ActionProcessResult {
+    fn from(action: Action) -> Self {
+        Self {
+            action,
+            terminal: false,
+        }
+    }
+}
+
 /// The argument to text plugin.
 ///
 /// Every text plugin should implement `text_commands` and the specified function:
@@ -367,7 +456,10 @@ pub struct TextProcessResult {
 /// #[export]
 /// fn process_game(mut ctx: GameProcessContext) -> GameProcessResult {
 ///     // Process the game...
-///     GameProcessResult { props: ctx.props }
+///     GameProcessResult {
+///         props: ctx.props,
+///         ..Default::default()
+///     }
 /// }
 /// ```
 #[derive(Debug, Serialize, Deserialize)]
@@ -379,7 +471,15 @@ pub struct GameProcessContext {
     /// The root path of the game profile.
     pub root_path: PathBuf,
     /// The global properties of the game.
+    /// They are the ones updated by the former game plugins.
     pub props: HashMap<String, String>,
+    /// The resources, indexed by locale.
+    #[serde(default)]
+    pub res: HashMap<String, VarMap>,
+    /// All texts of the paragraphs in all locales,
+    /// including the `next` of the paragraphs.
+    #[serde(default)]
+    pub texts: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -389,12 +489,99 @@ pub struct GameProcessContextRef<'a> {
     pub author: &'a str,
     pub root_path: &'a Path,
     pub props: &'a HashMap<String, String>,
+    pub res: HashMap<String, &'a VarMap>,
+    pub texts: Vec<&'a str>,
 }
 
 /// The result of game plugins.
 /// See examples at [`GameProcessContext`].
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// When there are several game plugins, they run in the order of loading.
+/// The returned `props` replace the properties of the game,
+/// so a plugin could also remove properties,
+/// and the next plugin sees the updated ones.
+/// The diagnostics of all plugins are combined.
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GameProcessResult {
     /// The updated properties.
     pub props: HashMap<String, String>,
+    /// The validation messages of the properties, e.g., invalid values.
+    /// They are reported by the runtime when opening the game.
+    #[serde(default)]
+    pub diagnostics: Vec<String>,
+}
+
+/// The error when parsing a property into a typed value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropError {
+    /// The key of the property.
+    pub key: String,
+    /// The raw value of the property.
+    pub value: String,
+    /// The expected type.
+    pub expected: &'static str,
+}
+
+impl Display for PropError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid value \"{}\" of property \"{}\", expected {}.",
+            self.value, self.key, self.expected
+        )
+    }
+}
+
+impl Error for PropError {}
+
+/// Typed accessors of the string properties.
+///
+/// [`None`] is returned if the property doesn't exist,
+/// and [`PropError`] if it cannot be parsed.
+/// ```
+/// # use ayaka_bindings_types::*;
+/// # use std::collections::HashMap;
+/// let props = HashMap::from([
+///     ("count".to_string(), "3".to_string()),
+///     ("loop".to_string(), "true".to_string()),
+///     ("volume".to_string(), "loud".to_string()),
+/// ]);
+/// assert_eq!(props.get_num("count"), Ok(Some(3)));
+/// assert_eq!(props.get_bool("loop"), Ok(Some(true)));
+/// assert_eq!(props.get_num("speed"), Ok(None));
+/// assert!(props.get_num("volume").is_err());
+/// ```
+pub trait PropsExt {
+    /// Gets a property as a number.
+    fn get_num(&self, key: &str) -> Result<Option<i64>, PropError>;
+
+    /// Gets a property as a boolean, `true` or `false`.
+    fn get_bool(&self, key: &str) -> Result<Option<bool>, PropError>;
+}
+
+fn parse_prop<T: FromStr>(
+    props: &HashMap<String, String>,
+    key: &str,
+    expected: &'static str,
+) -> Result<Option<T>, PropError> {
+    props
+        .get(key)
+        .map(|value| {
+            value.trim().parse().map_err(|_| PropError {
+                key: key.to_string(),
+                value: value.clone(),
+                expected,
+            })
+        })
+        .transpose()
+}
+
+impl PropsExt for HashMap<String, String> {
+    fn get_num(&self, key: &str) -> Result<Option<i64>, PropError> {
+        parse_prop(self, key, "a number")
+    }
+
+    fn get_bool(&self, key: &str) -> Result<Option<bool>, PropError> {
+        parse_prop(self, key, "a boolean")
+    }
 }
@@ -0,0 +1,19 @@
+use ayaka_script_types::RawValue;
+
+#[cfg(target_arch = "wasm32")]
+#[link(wasm_import_module = "event")]
+extern "C" {
+    fn __emit_event(len: usize, data: *const u8);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn __emit_event(_len: usize, _data: *const u8) {}
+
+/// Emits a custom event to the frontend.
+///
+/// The event is forwarded with the plugin name by the runtime,
+/// and what to do with it is up to the frontend.
+pub fn emit_event(name: &str, value: RawValue) {
+    let data = rmp_serde::to_vec(&(name, value)).unwrap();
+    unsafe { __emit_event(data.len(), data.as_ptr()) }
+}
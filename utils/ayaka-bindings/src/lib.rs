@@ -8,8 +8,11 @@ pub use ayaka_script_types::{RawValue, ValueType};
 #[doc(no_inline)]
 pub use log;
 
+mod event;
 mod logger;
 
+pub use event::emit_event;
+
 use serde::{de::DeserializeOwned, Serialize};
 use std::alloc::{self, Layout};
 
@@ -51,7 +54,12 @@ pub unsafe fn __export<Params: DeserializeOwned, Res: Serialize>(
     f: impl FnOnce<Params, Output = Res>,
 ) -> u64 {
     logger::PluginLogger::init();
-    let data = std::slice::from_raw_parts(data, len);
+    // An empty payload is passed as `(0, 0)`.
+    let data = if len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data, len)
+    };
     let data = rmp_serde::from_slice(data).unwrap();
     let res = f.call_once(data);
     let data = rmp_serde::to_vec(&res).unwrap();
@@ -59,4 +67,31 @@ pub unsafe fn __export<Params: DeserializeOwned, Res: Serialize>(
     ((len as u64) << 32) | (ptr as u64)
 }
 
+/// Handles a chunk passed by `Host::call_streaming` of the runtime.
+///
+/// `f` is called with the chunk, or [`None`] after the input ends,
+/// and returns the output of the chunk.
+/// After the input ends, it is called until it returns an empty output.
+///
+/// # Safety
+/// The arguments should be the ones passed to the exported method.
+pub unsafe fn export_chunk(
+    len: usize,
+    data: *const u8,
+    f: impl FnOnce(Option<&[u8]>) -> Vec<u8>,
+) -> u64 {
+    logger::PluginLogger::init();
+    let chunk = if len == 0 {
+        None
+    } else {
+        Some(std::slice::from_raw_parts(data, len))
+    };
+    let res = f(chunk);
+    if res.is_empty() {
+        return 0;
+    }
+    let (ptr, len) = __abi_alloc_from(&res);
+    ((len as u64) << 32) | (ptr as u64)
+}
+
 pub use ayaka_bindings_impl::export;
@@ -0,0 +1,327 @@
+use crate::{plugin::Runtime, script::analyze, *};
+use ayaka_script::TextParser;
+use ayaka_script_types::{Command, Expr, Line, Program, Ref, Text};
+use serde::Serialize;
+use std::fmt::Display;
+
+/// The severity of a [`CheckMessage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The game could run, but probably not as expected.
+    Warning,
+    /// The game is broken.
+    Error,
+}
+
+/// A message reported by [`check_game`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckMessage {
+    /// The severity.
+    pub severity: Severity,
+    /// The locale of the paragraph.
+    pub locale: Option<String>,
+    /// The tag of the paragraph.
+    pub para: Option<String>,
+    /// The index of the text in the paragraph, or [`None`] for `next`.
+    pub act: Option<usize>,
+    /// The byte range in the text, if known.
+    pub loc: Option<(usize, usize)>,
+    /// The message.
+    pub message: String,
+}
+
+impl Display for CheckMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning")?,
+            Severity::Error => write!(f, "error")?,
+        }
+        if let Some(locale) = &self.locale {
+            write!(f, " [{}]", locale)?;
+        }
+        if let Some(para) = &self.para {
+            write!(f, " paragraph \"{}\"", para.escape_default())?;
+            match self.act {
+                Some(act) => write!(f, ", act {}", act + 1)?,
+                None => write!(f, ", next")?,
+            }
+        }
+        if let Some((start, end)) = self.loc {
+            write!(f, ", at {}..{}", start, end)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+struct Checker<'a> {
+    game: &'a Game,
+    runtime: &'a Runtime,
+    locale: &'a Locale,
+    res: VarMap,
+    para: &'a str,
+    act: Option<usize>,
+    messages: Vec<CheckMessage>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, loc: Option<(usize, usize)>, message: String) {
+        self.messages.push(CheckMessage {
+            severity: Severity::Error,
+            locale: Some(self.locale.to_string()),
+            para: Some(self.para.to_string()),
+            act: self.act,
+            loc,
+            message,
+        });
+    }
+
+    fn parse(&mut self, text: &str) -> Option<Text> {
+        match TextParser::new(text).parse() {
+            Ok(t) => Some(t),
+            Err(e) => {
+                self.report(Some((e.loc().0, e.loc().1)), e.to_string());
+                None
+            }
+        }
+    }
+
+    fn check_res(&mut self, key: &str) {
+        if !self.res.contains_key(key) {
+            self.report(None, format!("Cannot find resource `{}`.", key));
+        }
+    }
+
+    fn check_program(&mut self, program: &Program) {
+        for diag in analyze(program, self.runtime) {
+            self.report(None, diag.to_string());
+        }
+        let mut keys = vec![];
+        for expr in &program.0 {
+            res_refs(expr, &mut keys);
+        }
+        for key in keys {
+            self.check_res(key);
+        }
+    }
+
+    fn check_text(&mut self, text: &Text) {
+        let mut defaults = 0;
+        for line in &text.0 {
+            if let Line::Cmd(cmd) = line {
+                match cmd {
+                    Command::Character(key, alter) => {
+                        if alter.is_empty() {
+                            self.check_res(&format!("ch_{}", key));
+                        }
+                    }
+                    Command::Voice(_) | Command::Wait(_) => {}
+                    Command::Exec(p) | Command::When(p) => self.check_program(p),
+                    Command::Var(r, _) => {
+                        if let Ref::Res(key) = r {
+                            self.check_res(key);
+                        }
+                    }
+                    Command::Switch {
+                        action,
+                        enabled,
+                        default,
+                        ..
+                    } => {
+                        if *default {
+                            defaults += 1;
+                        }
+                        self.check_program(action);
+                        if let Some(enabled) = enabled {
+                            self.check_program(enabled);
+                        }
+                    }
+                    Command::Other(name, args) => {
+                        if !self.runtime.text_modules.contains_key(name) {
+                            self.report(None, format!("Invalid command {}", name));
+                        } else if let Some(&(min, max)) = self.runtime.text_arity.get(name) {
+                            if !(min..=max).contains(&args.len()) {
+                                let expected = if min == max {
+                                    min.to_string()
+                                } else {
+                                    format!("{} to {}", min, max)
+                                };
+                                self.report(
+                                    None,
+                                    format!(
+                                        "Command \\{} expects {} args, got {}",
+                                        name,
+                                        expected,
+                                        args.len()
+                                    ),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if defaults > 1 {
+            self.report(
+                None,
+                format!("Only one default switch is allowed, found {}.", defaults),
+            );
+        }
+    }
+
+    fn check_next(&mut self, next: &Text) {
+        // Only the static tags could be resolved without executing.
+        let mut tag = String::new();
+        for line in &next.0 {
+            match line {
+                Line::Str(s) => tag.push_str(s),
+                Line::Cmd(_) => return,
+            }
+        }
+        let tag = tag.trim();
+        if !tag.is_empty() && self.game.find_para_fallback(self.locale, tag).is_none() {
+            self.report(None, format!("Cannot find paragraph \"{}\".", tag));
+        }
+    }
+}
+
+fn res_refs<'a>(expr: &'a Expr, keys: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Ref(Ref::Res(n)) => keys.push(n),
+        Expr::Ref(_) | Expr::Const(_) => {}
+        Expr::Unary(_, e) => res_refs(e, keys),
+        Expr::Binary(lhs, _, rhs) => {
+            res_refs(lhs, keys);
+            res_refs(rhs, keys);
+        }
+        Expr::Call(_, _, args) => {
+            for arg in args {
+                res_refs(arg, keys);
+            }
+        }
+    }
+}
+
+/// Check the [`Game`] statically, without executing anything.
+///
+/// For the paragraphs of every locale, it reports
+/// * the parse errors of the texts and `next`,
+/// * the diagnostics of [`analyze`] on the scripts,
+/// * the unknown text commands and the ones with wrong argument count,
+/// * more than one default switch in a text,
+/// * the missing resource keys, resolved with [`Game::resolve_res`],
+/// * the `next` tags which cannot be found, if they are static,
+/// * the paragraphs which cannot be reached, as warnings,
+///   see [`Game::unreachable_paragraphs`].
+///
+/// The messages are sorted by locale, paragraph and act.
+pub fn check_game(game: &Game, runtime: &Runtime) -> Vec<CheckMessage> {
+    let mut messages = vec![];
+    for (locale, paras) in &game.paras {
+        let mut checker = Checker {
+            game,
+            runtime,
+            locale,
+            res: game.resolve_res(locale),
+            para: "",
+            act: None,
+            messages: vec![],
+        };
+        for para in paras {
+            checker.para = &para.tag;
+            for (index, text) in para.texts.iter().enumerate() {
+                if text.is_empty() || text == "~" {
+                    continue;
+                }
+                checker.act = Some(index);
+                if let Some(text) = checker.parse(text) {
+                    checker.check_text(&text);
+                }
+            }
+            if let Some(next) = &para.next {
+                checker.act = None;
+                if let Some(next) = checker.parse(next) {
+                    checker.check_text(&next);
+                    checker.check_next(&next);
+                }
+            }
+        }
+        messages.append(&mut checker.messages);
+        for tag in game.unreachable_paragraphs(locale) {
+            if paras.iter().any(|p| p.tag == tag) {
+                messages.push(CheckMessage {
+                    severity: Severity::Warning,
+                    locale: Some(locale.to_string()),
+                    para: None,
+                    act: None,
+                    loc: None,
+                    message: format!("Paragraph \"{}\" is unreachable.", tag.escape_default()),
+                });
+            }
+        }
+    }
+    messages.sort_by(|a, b| (&a.locale, &a.para, a.act).cmp(&(&b.locale, &b.para, b.act)));
+    messages
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{plugin::Runtime, *};
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn check_game() {
+        let json = r#"{
+            "title": "",
+            "base_lang": "en",
+            "paras": {
+                "en": [
+                    { "tag": "init", "texts": ["\\ch{foo}#hello", "\\exec{#bye}", "\\exec{a / 0}", "\\bad{"], "next": "end" },
+                    { "tag": "end", "texts": ["\\foo{}"], "next": "nowhere" },
+                    { "tag": "orphan", "texts": [] }
+                ]
+            },
+            "res": { "en": { "ch_foo": "Foo", "hello": "Hello" } }
+        }"#;
+        let game = Game::from_slice(json.as_bytes(), Path::new("config.json")).unwrap();
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["format"],
+        )
+        .await
+        .unwrap();
+        let messages = super::check_game(&game, &runtime);
+        let summary = messages
+            .iter()
+            .map(|m| {
+                (
+                    m.para.as_deref().unwrap_or_default(),
+                    m.act,
+                    m.loc.is_some(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("", None, false),
+                ("end", None, false),
+                ("end", Some(0), false),
+                ("init", Some(1), false),
+                ("init", Some(2), false),
+                ("init", Some(3), true),
+            ]
+        );
+        assert!(messages.iter().all(|m| m.locale.as_deref() == Some("en")));
+        assert_eq!(
+            messages[0].to_string(),
+            "warning [en]: Paragraph \"orphan\" is unreachable."
+        );
+        assert!(messages[1..].iter().all(|m| m.severity == Severity::Error));
+        assert_eq!(
+            messages[3].to_string(),
+            "error [en] paragraph \"init\", act 2: Cannot find resource `bye`."
+        );
+    }
+}
@@ -4,8 +4,15 @@ pub use ayaka_bindings_types::{Action, Switch};
 pub use fallback::Fallback;
 
 use crate::*;
+use anyhow::{bail, Result};
+use ayaka_script::TextParser;
+use ayaka_script_types::{Command, Expr, Line};
+use log::warn;
 use serde::Deserialize;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 /// The paragraph in a game config.
 #[derive(Debug, Deserialize)]
@@ -19,13 +26,22 @@ pub struct Paragraph {
     /// The texts.
     /// They will be parsed into [`ayaka_script::Text`] later.
     pub texts: Vec<String>,
+    /// The voices, parallel to `texts`.
+    /// They are overridden by `\voice` commands in the texts,
+    /// and an empty one means no voice.
+    #[serde(default)]
+    pub voices: Vec<String>,
     /// The next paragraph.
     /// If [`None`], the game meets the end.
     pub next: Option<String>,
+    /// The name of the ending, if the game ends after this paragraph.
+    /// See [`EndReason::Ending`].
+    #[serde(default)]
+    pub ending: Option<String>,
 }
 
 /// The ayaka-game config.
-/// It should be deserialized from a YAML file.
+/// It should be deserialized from a YAML, JSON or TOML file.
 #[derive(Debug, Default, Deserialize)]
 pub struct Game {
     /// The title of the game.
@@ -44,12 +60,22 @@ pub struct Game {
     /// The resources, indexed by locale.
     #[serde(default)]
     pub res: HashMap<Locale, VarMap>,
+    /// The gallery items, in the order to show.
+    ///
+    /// They are the names passed to the resource commands, e.g., `\bg{cg_sunset}`,
+    /// see [`Context::unlocked_gallery`].
+    #[serde(default)]
+    pub gallery: Vec<String>,
     /// The base language.
     /// If the runtime fails to choose a best match,
     /// it fallbacks to this one.
     pub base_lang: Locale,
 }
 
+/// The target in [`Game::paragraph_graph`] of a `next`
+/// which cannot be resolved without executing.
+pub const UNKNOWN_PARA: &str = "?";
+
 /// The plugin config.
 #[derive(Debug, Default, Deserialize)]
 pub struct PluginConfig {
@@ -61,6 +87,57 @@ pub struct PluginConfig {
 }
 
 impl Game {
+    /// Deserialize the config from the file content.
+    /// The format is determined by the extension of the path:
+    /// `yaml` or `yml` for YAML, `json` for JSON, and `toml` for TOML.
+    pub fn from_slice(buf: &[u8], path: &Path) -> Result<Self> {
+        let ext = path
+            .extension()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "yaml" | "yml" => Ok(serde_yaml::from_slice(buf)?),
+            "json" => Ok(serde_json::from_slice(buf)?),
+            "toml" => Ok(toml::from_slice(buf)?),
+            _ => bail!(
+                "Unsupported config format \"{}\" of {}",
+                ext,
+                path.display()
+            ),
+        }
+    }
+
+    /// Validate the locales of the config.
+    ///
+    /// The `base_lang` should be a key in `paras`, and in `res` if `res` is not empty.
+    /// Warnings are emitted for the locales only in one of `paras` and `res`.
+    pub fn validate(&self) -> Result<()> {
+        if !self.paras.contains_key(&self.base_lang) {
+            bail!("The base language {} has no paragraphs.", self.base_lang);
+        }
+        if !self.res.is_empty() {
+            if !self.res.contains_key(&self.base_lang) {
+                bail!("The base language {} has no resources.", self.base_lang);
+            }
+            for loc in self.paras.keys() {
+                if !self.res.contains_key(loc) {
+                    warn!("The locale {} has paragraphs but no resources.", loc);
+                }
+            }
+            for loc in self.res.keys() {
+                if !self.paras.contains_key(loc) {
+                    warn!("The locale {} has resources but no paragraphs.", loc);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve the locale of the paragraphs actually used for the specified locale.
+    pub fn resolve_locale(&self, loc: &Locale) -> Locale {
+        self.choose_from_keys(loc, &self.paras).clone()
+    }
+
     fn choose_from_keys<'a, V>(&'a self, loc: &Locale, map: &'a HashMap<Locale, V>) -> &'a Locale {
         loc.choose_from(map.keys()).unwrap_or(&self.base_lang)
     }
@@ -90,6 +167,31 @@ impl Game {
         )
     }
 
+    /// Find the ending of a paragraph by tag, with specified locale.
+    ///
+    /// The ending in the base language takes precedence,
+    /// so that the names of the endings are the same across locales.
+    pub fn ending_of(&self, loc: &Locale, tag: &str) -> Option<&str> {
+        let (para, base_para) = self.find_para_fallback(loc, tag).unzip();
+        base_para
+            .and_then(|p| p.ending.as_deref())
+            .or_else(|| para.and_then(|p| p.ending.as_deref()))
+    }
+
+    /// All endings declared in the paragraphs of all locales,
+    /// resolved by [`Game::ending_of`].
+    pub fn endings(&self) -> BTreeSet<String> {
+        self.paras
+            .iter()
+            .flat_map(|(loc, paras)| {
+                paras
+                    .iter()
+                    .filter_map(move |p| self.ending_of(loc, &p.tag))
+            })
+            .map(|ending| ending.to_string())
+            .collect()
+    }
+
     fn find_res(&self, loc: &Locale) -> Option<&VarMap> {
         self.res.get(loc)
     }
@@ -107,4 +209,315 @@ impl Game {
             self.find_res(base_key),
         )
     }
+
+    /// Resolve the merged resource map with specified locale.
+    ///
+    /// The values of the base language are overlaid by the values of the
+    /// best matched locale key-by-key, so that a translation only needs
+    /// to override the resources that differ.
+    pub fn resolve_res(&self, loc: &Locale) -> VarMap {
+        let (map, base_map) = self.find_res_fallback(loc).unzip();
+        let mut merged = base_map.cloned().unwrap_or_default();
+        if let Some(map) = map {
+            merged.extend(map.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        merged
+    }
+
+    /// Get the story flow graph with specified locale.
+    ///
+    /// Returns the tags of the paragraphs in the base language,
+    /// each with the tags it could transition to through `next`.
+    /// The switches only set variables, so their effects are resolved
+    /// from the `next` scripts: a constant tag or the branches of `if`.
+    /// Any other target is reported as [`UNKNOWN_PARA`].
+    pub fn paragraph_graph(&self, loc: &Locale) -> Vec<(String, Vec<String>)> {
+        let base_key = self.choose_from_keys(&self.base_lang, &self.paras);
+        self.paras
+            .get(base_key)
+            .into_iter()
+            .flatten()
+            .map(|p| {
+                let next = self
+                    .find_para_fallback(loc, &p.tag)
+                    .and_then(|p| p.next.as_ref());
+                let targets = next.map(|next| next_targets(next)).unwrap_or_default();
+                (p.tag.clone(), targets)
+            })
+            .collect()
+    }
+
+    /// Get the tags of the paragraphs which could not be reached
+    /// from the start paragraph with specified locale.
+    ///
+    /// The paragraphs are walked through the edges of [`Game::paragraph_graph`],
+    /// falling back to the base language like the runtime.
+    /// If an [`UNKNOWN_PARA`] target is reached, any paragraph may be reached,
+    /// and nothing is reported.
+    pub fn unreachable_paragraphs(&self, loc: &Locale) -> Vec<String> {
+        let key = self.choose_from_keys(loc, &self.paras);
+        let base_key = self.choose_from_keys(&self.base_lang, &self.paras);
+        let mut tags: Vec<&str> = vec![];
+        for paras in [self.paras.get(base_key), self.paras.get(key)]
+            .into_iter()
+            .flatten()
+        {
+            for p in paras {
+                if !tags.contains(&p.tag.as_str()) {
+                    tags.push(&p.tag);
+                }
+            }
+        }
+        let mut reached = HashSet::new();
+        let mut stack = tags
+            .first()
+            .map(|tag| vec![tag.to_string()])
+            .unwrap_or_default();
+        while let Some(tag) = stack.pop() {
+            let next = self
+                .find_para_fallback(loc, &tag)
+                .and_then(|p| p.next.as_ref());
+            if !reached.insert(tag) {
+                continue;
+            }
+            for target in next.map(|next| next_targets(next)).unwrap_or_default() {
+                if target == UNKNOWN_PARA {
+                    return vec![];
+                }
+                stack.push(target);
+            }
+        }
+        tags.into_iter()
+            .filter(|tag| !reached.contains(*tag))
+            .map(|tag| tag.to_string())
+            .collect()
+    }
+}
+
+fn next_targets(next: &str) -> Vec<String> {
+    let unknown = || vec![UNKNOWN_PARA.to_string()];
+    let text = match TextParser::new(next).parse() {
+        Ok(text) => text,
+        Err(_) => return unknown(),
+    };
+    let mut tag = String::new();
+    let mut program = None;
+    for line in text.0 {
+        match line {
+            Line::Str(s) => tag.push_str(&s),
+            Line::Cmd(Command::Exec(p)) if program.is_none() => program = Some(p),
+            Line::Cmd(_) => return unknown(),
+        }
+    }
+    let tag = tag.trim();
+    let mut targets = vec![];
+    match program {
+        None => {
+            if !tag.is_empty() {
+                targets.push(tag.to_string());
+            }
+        }
+        Some(program) => {
+            if !tag.is_empty() {
+                return unknown();
+            }
+            if let Some(expr) = program.0.last() {
+                if !expr_targets(expr, &mut targets) {
+                    targets.push(UNKNOWN_PARA.to_string());
+                }
+            }
+        }
+    }
+    targets
+}
+
+// Returns false if some of the targets are unknown.
+fn expr_targets(expr: &Expr, targets: &mut Vec<String>) -> bool {
+    match expr {
+        Expr::Const(RawValue::Unit) => true,
+        Expr::Const(RawValue::Str(s)) => {
+            if !s.is_empty() && !targets.contains(s) {
+                targets.push(s.clone());
+            }
+            true
+        }
+        Expr::Call(ns, name, args) if ns.is_empty() && name == "if" => {
+            let then_known = args.get(1).map_or(true, |e| expr_targets(e, targets));
+            let else_known = args.get(2).map_or(true, |e| expr_targets(e, targets));
+            then_known && else_known
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn formats() {
+        let yaml = "title: Hello\nbase_lang: en\nparas:\n  en:\n    - tag: init\n      texts:\n        - Hello\n";
+        let json = r#"{"title":"Hello","base_lang":"en","paras":{"en":[{"tag":"init","texts":["Hello"]}]}}"#;
+        let toml = "title = \"Hello\"\nbase_lang = \"en\"\n[[paras.en]]\ntag = \"init\"\ntexts = [\"Hello\"]\n";
+        for (buf, path) in [
+            (yaml, "config.yaml"),
+            (json, "config.json"),
+            (toml, "config.toml"),
+        ] {
+            let game = Game::from_slice(buf.as_bytes(), Path::new(path)).unwrap();
+            assert_eq!(game.title, "Hello");
+            assert_eq!(game.paras[&locale!("en")][0].texts, ["Hello"]);
+        }
+        assert!(Game::from_slice(yaml.as_bytes(), Path::new("config.txt")).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let game = |base_lang: &str, res: &str| {
+            let json = format!(
+                r#"{{"title":"","base_lang":"{}","paras":{{"en":[],"ja":[]}},"res":{{{}}}}}"#,
+                base_lang, res
+            );
+            Game::from_slice(json.as_bytes(), Path::new("config.json")).unwrap()
+        };
+        assert!(game("en", "").validate().is_ok());
+        assert!(game("en", r#""en":{}"#).validate().is_ok());
+        assert!(game("zh", "").validate().is_err());
+        assert!(game("ja", r#""en":{}"#).validate().is_err());
+    }
+
+    #[test]
+    fn resolve_locale() {
+        let json = r#"{"title":"","base_lang":"en","paras":{"en":[],"zh-Hans":[]}}"#;
+        let game = Game::from_slice(json.as_bytes(), Path::new("config.json")).unwrap();
+        assert_eq!(game.resolve_locale(&locale!("zh-CN")), locale!("zh-Hans"));
+        assert_eq!(game.resolve_locale(&locale!("en-US")), locale!("en"));
+        assert_eq!(game.resolve_locale(&locale!("ja")), locale!("en"));
+    }
+
+    #[test]
+    fn resolve_res() {
+        let json = r#"{"title":"","base_lang":"en","paras":{"en":[],"ja":[]},"res":{"en":{"hello":"Hello","bye":"Bye"},"ja":{"hello":"こんにちは","extra":"追加"}}}"#;
+        let game = Game::from_slice(json.as_bytes(), Path::new("config.json")).unwrap();
+        let res = game.resolve_res(&locale!("ja"));
+        assert_eq!(res.len(), 3);
+        assert_eq!(res["hello"].get_str(), "こんにちは");
+        assert_eq!(res["bye"].get_str(), "Bye");
+        assert_eq!(res["extra"].get_str(), "追加");
+        let res = game.resolve_res(&locale!("en"));
+        assert_eq!(res.len(), 2);
+        assert_eq!(res["hello"].get_str(), "Hello");
+        assert!(game.resolve_res(&locale!("fr")).get("extra").is_none());
+    }
+
+    #[test]
+    fn paragraph_graph() {
+        let yaml = r#"
+title: ""
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts: []
+      next: loop
+    - tag: loop
+      texts: []
+      next: \exec{$i = $i + 1; if($i < 10, "loop", "end")}
+    - tag: end
+      texts: []
+      next: \exec{if($i > 5, "init")}
+    - tag: dynamic
+      texts: []
+      next: \exec{$next}
+  ja:
+    - tag: init
+      texts: []
+      next: end
+"#;
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        let graph = game.paragraph_graph(&locale!("en"));
+        assert_eq!(
+            graph,
+            [
+                ("init".to_string(), vec!["loop".to_string()]),
+                (
+                    "loop".to_string(),
+                    vec!["loop".to_string(), "end".to_string()]
+                ),
+                ("end".to_string(), vec!["init".to_string()]),
+                ("dynamic".to_string(), vec![UNKNOWN_PARA.to_string()]),
+            ]
+        );
+        let graph = game.paragraph_graph(&locale!("ja"));
+        assert_eq!(graph[0], ("init".to_string(), vec!["end".to_string()]));
+        assert_eq!(graph[1].1, ["loop", "end"]);
+    }
+
+    #[test]
+    fn unreachable_paragraphs() {
+        let yaml = r#"
+title: ""
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts: []
+      next: \exec{if($a, "a", "b")}
+    - tag: a
+      texts: []
+    - tag: b
+      texts: []
+    - tag: orphan
+      texts: []
+      next: a
+  ja:
+    - tag: init
+      texts: []
+      next: a
+    - tag: extra
+      texts: []
+      next: \exec{$next}
+"#;
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        assert_eq!(game.unreachable_paragraphs(&locale!("en")), ["orphan"]);
+        assert_eq!(
+            game.unreachable_paragraphs(&locale!("ja")),
+            ["b", "orphan", "extra"]
+        );
+        let yaml = yaml.replace(r#"if($a, "a", "b")"#, "$next");
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        assert!(game.unreachable_paragraphs(&locale!("en")).is_empty());
+    }
+
+    #[test]
+    fn endings() {
+        let yaml = r#"
+title: ""
+base_lang: en
+paras:
+  en:
+    - tag: good
+      texts: []
+      ending: Good
+    - tag: bad
+      texts: []
+  ja:
+    - tag: good
+      texts: []
+      ending: 良い
+    - tag: bad
+      texts: []
+      ending: 悪い
+"#;
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        assert_eq!(game.ending_of(&locale!("ja"), "good"), Some("Good"));
+        assert_eq!(game.ending_of(&locale!("ja"), "bad"), Some("悪い"));
+        assert_eq!(game.ending_of(&locale!("en"), "bad"), None);
+        assert_eq!(game.ending_of(&locale!("en"), "nowhere"), None);
+        assert_eq!(
+            game.endings().into_iter().collect::<Vec<_>>(),
+            ["Good", "悪い"]
+        );
+    }
 }
@@ -2,7 +2,10 @@
 pub use ayaka_bindings_types::{FrontendType, RawContext};
 
 use crate::{
-    plugin::{LoadStatus, Runtime},
+    plugin::{
+        check_cancel, Capability, LoadStatus, PluginCall, PluginEvent, PluginStats, Runtime,
+        NEVER_CANCEL,
+    },
     *,
 };
 use anyhow::{anyhow, bail, Result};
@@ -11,13 +14,17 @@ use ayaka_bindings_types::{
 };
 use ayaka_script::{Loc, ParseError, TextParser};
 use ayaka_script_types::{Command, Line, Program, Text};
-use log::error;
+use log::{error, warn};
 use script::*;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::{Duration, Instant},
 };
 use stream_future::stream;
+use tempfile::TempDir;
 use unicode_width::UnicodeWidthStr;
 
 /// The game running context.
@@ -28,11 +35,77 @@ pub struct Context {
     root_path: PathBuf,
     runtime: Runtime,
     settings: Settings,
+    res: VarMap,
     global_record: GlobalRecord,
     /// The inner raw context.
     pub ctx: RawContext,
     /// The inner record.
     pub record: ActionRecord,
+    game_diagnostics: Vec<(String, String)>,
+    progress: Option<(usize, ProgressHook)>,
+    text_cache: Mutex<TextCache>,
+    end_reason: Option<EndReason>,
+    // The script method calls of the last action in the history, with the length of the history,
+    // to render it again without side effects.
+    line_calls: (usize, Vec<PluginCall>),
+    // The resources extracted from a game pack, removed on drop.
+    pack_dir: Option<TempDir>,
+}
+
+type ProgressHook = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// The parsed texts by the locale of the paragraphs, the paragraph tag and the text index.
+#[derive(Default)]
+struct TextCache {
+    texts: HashMap<(Locale, String, usize), Text>,
+    stats: TextCacheStats,
+}
+
+/// The statistics of the parsed text cache of a [`Context`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TextCacheStats {
+    /// The count of the texts found in the cache.
+    pub hits: u64,
+    /// The count of the texts parsed.
+    pub misses: u64,
+    /// The total time spent in parsing.
+    pub parse_time: Duration,
+}
+
+impl TextCacheStats {
+    /// Estimate the parse time saved by the cache, with the average parse time.
+    pub fn saved_time(&self) -> Duration {
+        if self.misses == 0 {
+            Duration::ZERO
+        } else {
+            self.parse_time
+                .mul_f64(self.hits as f64 / self.misses as f64)
+        }
+    }
+}
+
+/// The reason why the game ends, see [`Context::end_reason`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EndReason {
+    /// The last paragraph declares an ending, with the name.
+    Ending(String),
+    /// The last paragraph has no `next`, and doesn't declare an ending.
+    NoNext,
+    /// The paragraph with the tag cannot be found.
+    Error(String),
+}
+
+/// A snapshot of the playthrough state of a [`Context`],
+/// taken by [`Context::snapshot`].
+///
+/// It is kept in memory for quick save and quick load,
+/// and could be serialized if it needs to be persisted.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct ContextSnapshot {
+    /// The record, with the history.
+    pub record: ActionRecord,
+    /// The position and the locals.
+    pub ctx: RawContext,
 }
 
 /// The open status when creating [`Context`].
@@ -44,33 +117,132 @@ pub enum OpenStatus {
     CreateRuntime,
     /// Loading the plugin.
     LoadPlugin(String, usize, usize),
+    /// The plugin failed to load, with the error message.
+    PluginFailed(String, String),
+}
+
+impl From<LoadStatus> for OpenStatus {
+    fn from(status: LoadStatus) -> Self {
+        match status {
+            LoadStatus::CreateEngine => Self::CreateRuntime,
+            LoadStatus::LoadPlugin(name, i, len) => Self::LoadPlugin(name, i, len),
+            LoadStatus::PluginFailed(name, msg) => Self::PluginFailed(name, msg),
+        }
+    }
 }
 
 impl Context {
     /// Open a config file with frontend type.
+    ///
+    /// If the extension is `ayapack`, the file is opened with [`Context::open_pack`].
     #[stream(OpenStatus, lifetime = "'a")]
     pub async fn open<'a>(path: impl AsRef<Path> + 'a, frontend: FrontendType) -> Result<Self> {
+        let context = Self::open_with_cancel(path, frontend, &NEVER_CANCEL);
+        pin_mut!(context);
+        while let Some(status) = context.next().await {
+            yield status;
+        }
+        context.await
+    }
+
+    /// Open a config file as [`Context::open`], and stop if `cancel` is set.
+    ///
+    /// The flag is checked before loading each plugin.
+    /// It fails with [`plugin::Cancelled`], and the partially loaded plugins are dropped.
+    #[stream(OpenStatus, lifetime = "'a")]
+    pub async fn open_with_cancel<'a>(
+        path: impl AsRef<Path> + 'a,
+        frontend: FrontendType,
+        cancel: &'a AtomicBool,
+    ) -> Result<Self> {
+        if path.as_ref().extension().map(|ex| ex == "ayapack") == Some(true) {
+            let context = Self::open_pack_with_cancel(path, frontend, cancel);
+            pin_mut!(context);
+            while let Some(status) = context.next().await {
+                yield status;
+            }
+            return context.await;
+        }
         yield OpenStatus::LoadProfile;
         let file = tokio::fs::read(&path).await?;
-        let mut game: Game = serde_yaml::from_slice(&file)?;
+        let game = Game::from_slice(&file, path.as_ref())?;
+        game.validate()?;
         let root_path = path
             .as_ref()
             .parent()
             .ok_or_else(|| anyhow!("Cannot get parent from input path."))?;
         let root_path = std::path::absolute(root_path)?;
+        check_cancel(cancel)?;
         let runtime = {
-            let runtime = Runtime::load(&game.plugins.dir, &root_path, &game.plugins.modules);
+            let runtime = Runtime::load_with_cancel(
+                &game.plugins.dir,
+                &root_path,
+                &game.plugins.modules,
+                cancel,
+            );
             pin_mut!(runtime);
             while let Some(load_status) = runtime.next().await {
-                match load_status {
-                    LoadStatus::CreateEngine => yield OpenStatus::CreateRuntime,
-                    LoadStatus::LoadPlugin(name, i, len) => {
-                        yield OpenStatus::LoadPlugin(name, i, len)
-                    }
-                };
+                yield load_status.into();
+            }
+            runtime.await?
+        };
+        Self::create(game, frontend, root_path, runtime)
+    }
+
+    /// Open a game pack with frontend type.
+    ///
+    /// The plugins are loaded from memory,
+    /// and the other files are extracted to a temp directory as the root path.
+    /// The directory is unique to the context, and is removed when the context is dropped.
+    #[stream(OpenStatus, lifetime = "'a")]
+    pub async fn open_pack<'a>(
+        path: impl AsRef<Path> + 'a,
+        frontend: FrontendType,
+    ) -> Result<Self> {
+        let context = Self::open_pack_with_cancel(path, frontend, &NEVER_CANCEL);
+        pin_mut!(context);
+        while let Some(status) = context.next().await {
+            yield status;
+        }
+        context.await
+    }
+
+    /// Open a game pack as [`Context::open_pack`], and stop if `cancel` is set.
+    ///
+    /// See [`Context::open_with_cancel`].
+    #[stream(OpenStatus, lifetime = "'a")]
+    pub async fn open_pack_with_cancel<'a>(
+        path: impl AsRef<Path> + 'a,
+        frontend: FrontendType,
+        cancel: &'a AtomicBool,
+    ) -> Result<Self> {
+        yield OpenStatus::LoadProfile;
+        let mut pack = Pack::open(&path).await?;
+        let game = pack.game()?;
+        game.validate()?;
+        let dir = tempfile::Builder::new().prefix("ayaka-pack-").tempdir()?;
+        let root_path = pack.extract_resources(&game, dir.path()).await?;
+        check_cancel(cancel)?;
+        let runtime = {
+            let runtime = Runtime::load_from_bytes_with_cancel(pack.plugins(&game)?, cancel);
+            pin_mut!(runtime);
+            while let Some(load_status) = runtime.next().await {
+                yield load_status.into();
             }
             runtime.await?
         };
+        let mut context = Self::create(game, frontend, root_path, runtime)?;
+        context.pack_dir = Some(dir);
+        Ok(context)
+    }
+
+    fn create(
+        mut game: Game,
+        frontend: FrontendType,
+        root_path: PathBuf,
+        runtime: Runtime,
+    ) -> Result<Self> {
+        let mut game_diagnostics = vec![];
         for m in &runtime.game_modules {
             let module = &runtime.modules[m];
             let ctx = GameProcessContextRef {
@@ -78,33 +250,84 @@ impl Context {
                 author: &game.author,
                 root_path: &root_path,
                 props: &game.props,
+                res: game
+                    .res
+                    .iter()
+                    .map(|(loc, res)| (loc.to_string(), res))
+                    .collect(),
+                texts: game
+                    .paras
+                    .values()
+                    .flatten()
+                    .flat_map(|p| p.texts.iter().chain(p.next.iter()))
+                    .map(|s| s.as_str())
+                    .collect(),
             };
             let res = module.process_game(ctx)?;
-            for (key, value) in res.props {
-                game.props.insert(key, value);
+            game.props = res.props;
+            for msg in res.diagnostics {
+                log::warn!("Plugin {}: {}", m, msg);
+                game_diagnostics.push((m.clone(), msg));
             }
         }
+        let settings = Settings::new();
+        let res = game.resolve_res(&settings.lang);
         Ok(Self {
             game,
             frontend,
             root_path,
             runtime,
-            settings: Settings::new(),
+            settings,
+            res,
             global_record: GlobalRecord::default(),
             ctx: RawContext::default(),
             record: ActionRecord::default(),
+            game_diagnostics,
+            progress: None,
+            text_cache: Mutex::default(),
+            end_reason: None,
+            line_calls: Default::default(),
+            pack_dir: None,
         })
     }
 
+    /// The diagnostics reported by the game plugins when opening,
+    /// with the names of the plugins.
+    pub fn game_diagnostics(&self) -> &[(String, String)] {
+        &self.game_diagnostics
+    }
+
     /// Initialize the [`RawContext`] to the start of the game.
     pub fn init_new(&mut self) {
-        self.init_context(ActionRecord { history: vec![] })
+        self.init_context(ActionRecord::default())
+    }
+
+    /// Initialize the [`RawContext`] to the start of the game,
+    /// with the random engines seeded.
+    ///
+    /// The seed is stored in the record,
+    /// so that [`Context::reset`] and [`Context::init_replay`] reproduce the playthrough.
+    pub fn init_new_seeded(&mut self, seed: u64) {
+        self.init_context(ActionRecord {
+            seed: Some(seed),
+            ..Default::default()
+        });
+        self.seed(seed);
     }
 
     /// Initialize the [`ActionRecord`] with given record.
+    ///
+    /// The random engines are seeded by entropy, even if the record has a seed,
+    /// because the state of the engines when the record was saved is not stored.
+    /// Only the playthroughs from the start reproduce the random numbers.
     pub fn init_context(&mut self, record: ActionRecord) {
+        if let Err(e) = self.runtime.seed_from_entropy() {
+            error!("{}", e);
+        }
         self.ctx = record.last_ctx_with_game(&self.game);
         self.record = record;
+        self.end_reason = None;
+        self.line_calls = Default::default();
         if !self.record.history.is_empty() {
             // If the record is not empty,
             // we need to set current context to the next one.
@@ -112,23 +335,161 @@ impl Context {
         }
     }
 
+    /// Reset to the start of the game, keeping the loaded [`Game`] and plugins.
+    ///
+    /// The per-playthrough state, i.e., the history, the locals and the position,
+    /// is cleared, and so are the pending replayed and the recorded plugin calls.
+    /// If the current record has a seed, the random engines are seeded with it again,
+    /// so that the playthrough reproduces.
+    /// The global record and the settings are kept.
+    pub fn reset(&mut self) {
+        self.runtime.replay_calls(std::iter::empty());
+        self.runtime.take_calls();
+        let seed = self.record.seed;
+        self.init_context(ActionRecord {
+            seed,
+            ..Default::default()
+        });
+        if let Some(seed) = seed {
+            self.seed(seed);
+        }
+    }
+
+    /// Initialize a new game to replay the record.
+    ///
+    /// The random engines are seeded with the seed of the record,
+    /// and the plugin calls in the record are fed back instead of invoking the plugins,
+    /// so that the record reproduces even if the plugins changed.
+    /// See [`Runtime::replay_calls`].
+    pub fn init_replay(&mut self, record: &ActionRecord) {
+        self.init_context(ActionRecord {
+            seed: record.seed,
+            ..Default::default()
+        });
+        if let Some(seed) = record.seed {
+            self.seed(seed);
+        }
+        self.runtime.replay_calls(record.calls.iter().cloned());
+    }
+
+    fn seed(&self, seed: u64) {
+        if let Err(e) = self.runtime.seed(seed) {
+            error!("{}", e);
+        }
+    }
+
+    /// Takes a [`ContextSnapshot`] of the record, the position and the locals.
+    pub fn snapshot(&self) -> ContextSnapshot {
+        ContextSnapshot {
+            record: self.record.clone(),
+            ctx: self.ctx.clone(),
+        }
+    }
+
+    /// Restores the state taken by [`Context::snapshot`].
+    ///
+    /// Unlike [`Context::init_context`], the random engines are not seeded again,
+    /// and the position is restored as is.
+    pub fn restore(&mut self, snap: &ContextSnapshot) {
+        self.record = snap.record.clone();
+        self.ctx = snap.ctx.clone();
+        self.end_reason = None;
+        self.line_calls = Default::default();
+    }
+
+    /// Gets the resource usage of the plugins by name.
+    pub fn plugin_stats(&self) -> HashMap<String, PluginStats> {
+        self.runtime.stats()
+    }
+
+    /// Sets the handler of the custom events emitted by the plugins.
+    ///
+    /// See [`Runtime::set_event_handler`].
+    pub fn set_event_handler(&self, handler: impl Fn(PluginEvent) + Send + Sync + 'static) {
+        self.runtime.set_event_handler(handler);
+    }
+
+    /// Gets the statistics of the parsed text cache.
+    pub fn text_cache_stats(&self) -> TextCacheStats {
+        self.text_cache.lock().unwrap().stats
+    }
+
+    /// Clear the parsed text cache.
+    ///
+    /// The texts are parsed once and cached until the context is dropped,
+    /// so it should be called if the paragraphs in [`Context::game`] are modified.
+    pub fn clear_text_cache(&self) {
+        let mut cache = self.text_cache.lock().unwrap();
+        cache.texts.clear();
+        cache.stats = TextCacheStats::default();
+    }
+
+    /// Gets the capabilities declared by the plugins by name.
+    pub fn plugin_capabilities(&self) -> &HashMap<String, Vec<Capability>> {
+        &self.runtime.capabilities
+    }
+
+    /// Shuts down the plugin runtime. See [`Runtime::shutdown`].
+    pub fn shutdown(self) {
+        self.runtime.shutdown()
+    }
+
+    /// Enables or disables recording the plugin calls into the record.
+    ///
+    /// See [`Runtime::set_record_calls`].
+    pub fn set_record_calls(&mut self, record_calls: bool) {
+        self.runtime.set_record_calls(record_calls);
+    }
+
+    fn take_calls(&mut self) {
+        self.record.calls.append(&mut self.runtime.take_calls());
+    }
+
+    /// Sets a hook invoked every `interval` evaluated expressions in scripts.
+    ///
+    /// See [`VarTable::set_progress`].
+    pub fn set_progress_hook(
+        &mut self,
+        interval: usize,
+        hook: impl Fn(usize) + Send + Sync + 'static,
+    ) {
+        self.progress = Some((interval, Arc::new(hook)));
+    }
+
     fn table(&mut self) -> VarTable {
-        VarTable::new(
+        let mut table = VarTable::new(
             &self.runtime,
-            self.game.find_res_fallback(self.locale()),
+            Fallback::new(Some(&self.settings.lang), Some(&self.game.base_lang)),
+            &self.res,
             &mut self.ctx.locals,
-        )
+        );
+        if let Some((interval, hook)) = &self.progress {
+            table.set_progress(*interval, hook.as_ref());
+        }
+        table
+    }
+
+    fn table_with<'a>(&'a self, locals: &'a mut VarMap) -> VarTable<'a> {
+        let mut table = VarTable::new(
+            &self.runtime,
+            Fallback::new(Some(&self.settings.lang), Some(&self.game.base_lang)),
+            &self.res,
+            locals,
+        );
+        if let Some((interval, hook)) = &self.progress {
+            table.set_progress(*interval, hook.as_ref());
+        }
+        table
     }
 
-    fn current_paragraph(&self) -> Fallback<&Paragraph> {
-        self.game
-            .find_para_fallback(self.locale(), &self.ctx.cur_para)
+    fn paragraph_of(&self, ctx: &RawContext) -> Fallback<&Paragraph> {
+        self.game.find_para_fallback(self.locale(), &ctx.cur_para)
     }
 
-    fn current_text(&self) -> Fallback<&String> {
-        self.current_paragraph()
+    fn text_of(&self, ctx: &RawContext) -> Fallback<&String> {
+        self.paragraph_of(ctx)
             .map(|p| {
-                p.texts.get(self.ctx.cur_act).and_then(|s| {
+                p.texts.get(ctx.cur_act).and_then(|s| {
                     if s.is_empty() || s == "~" {
                         None
                     } else {
@@ -139,9 +500,86 @@ impl Context {
             .flatten()
     }
 
+    fn parsed_text_of(&self, ctx: &RawContext) -> Fallback<Text> {
+        let (text, base_text) = self.text_of(ctx).unzip();
+        Fallback::new(
+            text.map(|text| {
+                let loc = self.game.resolve_locale(self.locale());
+                self.parse_text_cached(ctx, loc, text)
+            }),
+            base_text.map(|text| {
+                let loc = self.game.resolve_locale(&self.game.base_lang);
+                self.parse_text_cached(ctx, loc, text)
+            }),
+        )
+    }
+
+    fn parse_text_cached(&self, ctx: &RawContext, loc: Locale, text: &str) -> Text {
+        let key = (loc, ctx.cur_para.clone(), ctx.cur_act);
+        {
+            let mut cache = self.text_cache.lock().unwrap();
+            if let Some(text) = cache.texts.get(&key).cloned() {
+                cache.stats.hits += 1;
+                return text;
+            }
+        }
+        let start = Instant::now();
+        let text = self.parse_text_rich_error(ctx, text);
+        let mut cache = self.text_cache.lock().unwrap();
+        cache.stats.misses += 1;
+        cache.stats.parse_time += start.elapsed();
+        cache.texts.insert(key, text.clone());
+        text
+    }
+
     /// Set the current locale.
     pub fn set_locale(&mut self, loc: impl Into<Locale>) {
-        self.settings.lang = loc.into();
+        let loc = loc.into();
+        let changed = self.settings.lang != loc;
+        self.settings.lang = loc;
+        self.res = self.game.resolve_res(&self.settings.lang);
+        if changed {
+            self.refresh_current_action();
+        }
+    }
+
+    /// Get the locales the game provides, in `paras` or `res`.
+    pub fn available_locales(&self) -> Vec<Locale> {
+        let mut locales = self
+            .game
+            .paras
+            .keys()
+            .chain(self.game.res.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        locales.sort_by_key(|loc| loc.to_string());
+        locales.dedup();
+        locales
+    }
+
+    /// Compute the last action in the history again, with current locale.
+    ///
+    /// The paragraph falls back to the base language if it doesn't exist in current locale,
+    /// and the action is kept if it cannot be computed.
+    /// The script methods return the same results as when the action was computed,
+    /// so the plugins are not advanced, see [`Runtime::rerender_line`].
+    /// If the action calls script methods but is not computed in this context,
+    /// e.g., it is loaded from a record, it is kept.
+    fn refresh_current_action(&mut self) {
+        if let Some(last_action) = self.record.history.pop() {
+            let (len, calls) = &self.line_calls;
+            let calls: &[PluginCall] = if *len == self.record.history.len() + 1 {
+                calls.as_slice()
+            } else {
+                &[]
+            };
+            let mut ctx = last_action.ctx.clone();
+            let action = self
+                .runtime
+                .rerender_line(calls, || self.next_action(&mut ctx))
+                .and_then(|res| res.ok());
+            self.record.history.push(action.unwrap_or(last_action));
+        }
     }
 
     /// Get the current locale.
@@ -150,8 +588,19 @@ impl Context {
     }
 
     /// Set all settings.
-    pub fn set_settings(&mut self, s: Settings) {
+    ///
+    /// The values are clamped into the valid ranges.
+    /// If the locale is changed, the last action in the history is computed again,
+    /// so that the current line is shown in the new locale.
+    pub fn set_settings(&mut self, mut s: Settings) {
+        s.validate();
+        self.runtime.set_log_levels(s.plugin_log_levels.clone());
+        let changed = self.settings.lang != s.lang;
         self.settings = s;
+        self.res = self.game.resolve_res(&self.settings.lang);
+        if changed {
+            self.refresh_current_action();
+        }
     }
 
     /// Get all settings.
@@ -159,6 +608,19 @@ impl Context {
         &self.settings
     }
 
+    /// Set a game prop, and notify the plugins subscribing to it,
+    /// see [`Runtime::prop_changed`].
+    ///
+    /// The plugins are not notified if the value is not changed.
+    pub fn set_prop(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
+        let value = value.into();
+        if self.game.props.get(&key) != Some(&value) {
+            self.runtime.prop_changed(&key, &value);
+            self.game.props.insert(key, value);
+        }
+    }
+
     /// Set global record.
     pub fn set_global_record(&mut self, r: GlobalRecord) {
         self.global_record = r;
@@ -180,12 +642,71 @@ impl Context {
         }
     }
 
+    /// Determine if the switch of the [`Action`] has been chosen,
+    /// in this or any earlier playthrough, i.e., the target of it has been visited.
+    ///
+    /// It is resolved from the recorded choices, and no script is run.
+    pub fn switch_visited(&self, action: &Action, index: usize) -> bool {
+        let (para, act) = (&action.ctx.cur_para, action.ctx.cur_act);
+        self.global_record
+            .choices
+            .contains(&(para.clone(), act, index))
+            || self
+                .record
+                .choices
+                .iter()
+                .any(|c| &c.para == para && c.act == act && c.index == index)
+    }
+
+    /// Choose the switch of the [`Action`] by index,
+    /// record the choice, and call the script of the switch.
+    ///
+    /// It fails if the switch is disabled.
+    pub fn switch(&mut self, action: &Action, index: usize) -> Result<RawValue> {
+        let switch = action
+            .switches
+            .get(index)
+            .ok_or_else(|| anyhow!("Index error: {}", index))?;
+        if !switch.enabled {
+            bail!("Switch {} is disabled.", index);
+        }
+        self.global_record
+            .choices
+            .insert((action.ctx.cur_para.clone(), action.ctx.cur_act, index));
+        self.record.choices.push(Choice {
+            para: action.ctx.cur_para.clone(),
+            act: action.ctx.cur_act,
+            index,
+            text: switch.text.clone(),
+        });
+        Ok(self.call(&switch.action))
+    }
+
+    /// The choices made by the player, in order.
+    pub fn choice_history(&self) -> &[Choice] {
+        &self.record.choices
+    }
+
+    /// Get a context variable, i.e., the `$`-prefixed variable in scripts.
+    pub fn get_var(&self, name: &str) -> Option<RawValue> {
+        self.ctx.locals.get(name).cloned()
+    }
+
+    /// Set a context variable, i.e., the `$`-prefixed variable in scripts.
+    ///
+    /// The subsequent scripts read the new value.
+    pub fn set_var(&mut self, name: &str, value: RawValue) {
+        self.ctx.locals.insert(name.to_string(), value);
+    }
+
     /// Call the part of script with this context.
     pub fn call(&mut self, expr: &impl Callable) -> RawValue {
-        self.table().call(expr)
+        let res = self.table().call(expr);
+        self.take_calls();
+        res
     }
 
-    fn rich_error(&self, text: &str, e: &ParseError) -> String {
+    fn rich_error(&self, ctx: &RawContext, text: &str, e: &ParseError) -> String {
         use std::iter::repeat;
         const FREE_LEN: usize = 20;
 
@@ -198,16 +719,17 @@ impl Context {
         let post = text.ceil_char_boundary(loc.1 + (text.len() - loc.1).min(FREE_LEN));
 
         let para_name = self
-            .current_paragraph()
+            .paragraph_of(ctx)
             .and_then(|p| p.title.as_ref())
             .map(|s| s.escape_default().to_string())
             .unwrap_or_default();
-        let act_num = self.ctx.cur_act + 1;
+        let para_tag = ctx.cur_para.escape_default();
+        let act_num = ctx.cur_act + 1;
         let show_code = &text[pre..post];
         let pre_code = &text[pre..loc.0];
         let error_code = &text[loc.0..loc.1];
         format!(
-            "Parse error on paragraph \"{para_name}\", act {act_num}:\n    {show_code}\n    {}\n{e}\n",
+            "Parse error on paragraph \"{para_tag}\" (\"{para_name}\"), act {act_num}:\n    {show_code}\n    {}\n{e}\n",
             repeat(' ')
                 .take(UnicodeWidthStr::width_cjk(pre_code))
                 .chain(repeat('^').take(UnicodeWidthStr::width_cjk(error_code)))
@@ -215,13 +737,19 @@ impl Context {
         )
     }
 
-    fn exact_text(&mut self, para_title: Option<String>, t: Text) -> Result<Action> {
+    fn exact_text(
+        &self,
+        ctx: &mut RawContext,
+        para_title: Option<String>,
+        mut voice: Option<String>,
+        t: Text,
+    ) -> Result<Action> {
         let mut action_line = ActionLines::default();
         let mut action_line_params = vec![];
         let mut chkey = None;
         let mut chname = None;
         let mut switches = vec![];
-        let mut props = HashMap::new();
+        let mut props = BTreeMap::new();
         for line in t.0.into_iter() {
             match line {
                 Line::Str(s) => action_line.push_back_chars(s),
@@ -231,16 +759,25 @@ impl Context {
                         chkey = Some(key.clone());
                         chname = if alter.is_empty() {
                             let res_key = format!("ch_{}", key);
-                            self.game
-                                .find_res_fallback(self.locale())
-                                .and_then(|map| map.get(&res_key))
-                                .map(|v| v.get_str().into_owned())
+                            self.res.get(&res_key).map(|v| v.get_str().into_owned())
                         } else {
                             Some(alter)
                         }
                     }
+                    Command::Voice(key) => voice = Some(key),
+                    Command::Wait(ms) => action_line.push_back(ActionLine::Pause(ms)),
+                    // Evaluated before exacting.
+                    Command::When(_) => {}
                     Command::Exec(p) => {
-                        let param = self.call(&p);
+                        let param = self.table_with(&mut ctx.locals).call(&p);
+                        action_line.push_back_chars(format!("{{{}}}", action_line_params.len()));
+                        action_line_params.push(param);
+                    }
+                    Command::Var(r, spec) => {
+                        let mut param = self.table_with(&mut ctx.locals).call(&r);
+                        if let Some(spec) = spec {
+                            param = RawValue::Str(format_value(&param, &spec));
+                        }
                         action_line.push_back_chars(format!("{{{}}}", action_line_params.len()));
                         action_line_params.push(param);
                     }
@@ -248,17 +785,46 @@ impl Context {
                         text,
                         action,
                         enabled,
+                        default,
+                        timeout_ms,
                     } => {
+                        // Only the first default switch is kept.
+                        let (default, timeout_ms) =
+                            if default && switches.iter().any(|s: &Switch| s.default) {
+                                warn!("More than one default switch, ignored: {}", text);
+                                (false, None)
+                            } else {
+                                (default, timeout_ms)
+                            };
                         // unwrap: when enabled is None, it means true.
-                        let enabled = enabled.map(|p| self.call(&p).get_bool()).unwrap_or(true);
+                        let enabled = enabled
+                            .map(|p| self.table_with(&mut ctx.locals).call(&p).get_bool())
+                            .unwrap_or(true);
                         switches.push(Switch {
                             text,
                             action,
                             enabled,
+                            default,
+                            timeout_ms,
                         });
                     }
                     Command::Other(name, args) => {
                         if let Some(m) = self.runtime.text_modules.get(&name) {
+                            if let Some(&(min, max)) = self.runtime.text_arity.get(&name) {
+                                if !(min..=max).contains(&args.len()) {
+                                    let expected = if min == max {
+                                        min.to_string()
+                                    } else {
+                                        format!("{} to {}", min, max)
+                                    };
+                                    bail!(
+                                        "Command \\{} expects {} args, got {}",
+                                        name,
+                                        expected,
+                                        args.len()
+                                    );
+                                }
+                            }
                             let game_context = TextProcessContextRef {
                                 root_path: &self.root_path,
                                 game_props: &self.game.props,
@@ -280,13 +846,20 @@ impl Context {
                 },
             }
         }
+        let voice = voice.map(|key| {
+            self.res
+                .get(&key)
+                .map(|v| v.get_str().into_owned())
+                .unwrap_or(key)
+        });
         Ok(Action {
-            ctx: self.ctx.clone(),
+            ctx: ctx.clone(),
             line: action_line,
             line_params: action_line_params,
             ch_key: chkey,
             character: chname,
             para_title,
+            voice,
             switches,
             props,
         })
@@ -302,6 +875,7 @@ impl Context {
             let ch_key = actions.ch_key.flatten().and_any();
             let character = actions.character.flatten().and_any();
             let para_title = actions.para_title.flatten().and_any();
+            let voice = actions.voice.flatten().and_any();
             let switches = actions
                 .switches
                 .into_iter()
@@ -316,10 +890,16 @@ impl Context {
                         .unwrap_or_default();
                     let (enabled, base_enabled) = s.enabled.unzip();
                     let enabled = base_enabled.or(enabled).unwrap_or(true);
+                    let (default, base_default) = s.default.unzip();
+                    let default = base_default.or(default).unwrap_or_default();
+                    let (timeout_ms, base_timeout_ms) = s.timeout_ms.unzip();
+                    let timeout_ms = base_timeout_ms.or(timeout_ms).flatten();
                     Switch {
                         text,
                         action,
                         enabled,
+                        default,
+                        timeout_ms,
                     }
                 })
                 .collect();
@@ -336,6 +916,7 @@ impl Context {
                 ch_key,
                 character,
                 para_title,
+                voice,
                 switches,
                 props,
             })
@@ -344,7 +925,7 @@ impl Context {
         }
     }
 
-    fn process_action(&mut self, mut action: Action) -> Result<Action> {
+    fn process_action(&self, mut action: Action) -> Result<Action> {
         {
             let params = std::mem::take(&mut action.line_params);
             let named = HashMap::<String, RawValue>::new();
@@ -355,12 +936,12 @@ impl Context {
                             .map_err(|id| anyhow!("Format error at {}", id))?
                             .to_string();
                     }
+                    ActionLine::Pause(_) => {}
                 }
             }
         }
         let last_action = self.record.history.last();
         for action_module in &self.runtime.action_modules {
-            let module = &self.runtime.modules[action_module];
             let ctx = ActionProcessContextRef {
                 root_path: &self.root_path,
                 game_props: &self.game.props,
@@ -368,7 +949,11 @@ impl Context {
                 last_action,
                 action: &action,
             };
-            action = module.process_action(ctx)?;
+            let res = self.runtime.process_action(action_module, ctx)?;
+            action = res.action;
+            if res.terminal {
+                break;
+            }
         }
         while let Some(act) = action.line.back() {
             if act.as_str().trim().is_empty() {
@@ -384,17 +969,14 @@ impl Context {
                 break;
             }
         }
-        if !action.line.is_empty() || action.character.is_some() {
-            self.record.history.push(action.clone());
-        }
         Ok(action)
     }
 
-    fn parse_text_rich_error(&self, text: &str) -> Text {
+    fn parse_text_rich_error(&self, ctx: &RawContext, text: &str) -> Text {
         match TextParser::new(text).parse() {
             Ok(t) => t,
             Err(e) => {
-                error!("{}", self.rich_error(text, &e));
+                error!("{}", self.rich_error(ctx, text, &e));
                 Text::default()
             }
         }
@@ -402,7 +984,7 @@ impl Context {
 
     fn check_text_rich_error(&self, text: &str) -> bool {
         if let Err(e) = TextParser::new(text).parse() {
-            eprintln!("{}", self.rich_error(text, &e));
+            eprintln!("{}", self.rich_error(&self.ctx, text, &e));
             false
         } else {
             true
@@ -410,6 +992,8 @@ impl Context {
     }
 
     /// Step to next line.
+    ///
+    /// If [`None`] is returned, the reason could be got by [`Context::end_reason`].
     pub fn next_run(&mut self) -> Option<Action> {
         if let Some(action) = self.record.history.last() {
             self.global_record
@@ -418,38 +1002,214 @@ impl Context {
                 .and_modify(|act| *act = (*act).max(action.ctx.cur_act))
                 .or_insert(action.ctx.cur_act);
         }
-        let cur_para = self.current_paragraph();
+        let mut ctx = std::mem::take(&mut self.ctx);
+        let action = self.next_action(&mut ctx);
+        self.ctx = ctx;
+        self.take_calls();
+        match action {
+            Ok(action) => {
+                self.end_reason = None;
+                let gallery = self.gallery_of(&action.ctx);
+                self.global_record.gallery.extend(gallery);
+                if !action.line.is_empty() || action.character.is_some() {
+                    self.record.history.push(action.clone());
+                    self.line_calls = (self.record.history.len(), self.runtime.take_line_calls());
+                }
+                Some(action)
+            }
+            Err(reason) => {
+                // Keep the first reason if stepping again after the end.
+                if self.end_reason.is_none() {
+                    if let EndReason::Ending(ending) = &reason {
+                        self.global_record.endings.insert(ending.clone());
+                    }
+                    self.end_reason = Some(reason);
+                }
+                None
+            }
+        }
+    }
+
+    /// The endings the player has reached, in all playthroughs.
+    ///
+    /// They are stored in the [`GlobalRecord`].
+    pub fn unlocked_endings(&self) -> &BTreeSet<String> {
+        &self.global_record.endings
+    }
+
+    /// The gallery items the player has seen, in all playthroughs.
+    ///
+    /// An item is unlocked when an action is shown by [`Context::next_run`],
+    /// with a text command taking the item name as an argument.
+    /// The items declared after a record is saved are not unlocked by loading it,
+    /// they should be shown again.
+    pub fn unlocked_gallery(&self) -> &BTreeSet<String> {
+        &self.global_record.gallery
+    }
+
+    fn gallery_of(&self, ctx: &RawContext) -> Vec<String> {
+        if self.game.gallery.is_empty() {
+            return vec![];
+        }
+        let (text, base_text) = self.parsed_text_of(ctx).unzip();
+        text.into_iter()
+            .chain(base_text)
+            .flat_map(|text| text.0)
+            .filter_map(|line| match line {
+                Line::Cmd(Command::Other(_, args)) => Some(args),
+                _ => None,
+            })
+            .flatten()
+            .filter(|arg| self.game.gallery.contains(arg))
+            .collect()
+    }
+
+    /// The reason why the last [`Context::next_run`] returned [`None`],
+    /// or [`None`] if the game hasn't ended.
+    pub fn end_reason(&self) -> Option<&EndReason> {
+        self.end_reason.as_ref()
+    }
+
+    /// Compute the action that the next [`Context::next_run`] returns, without stepping.
+    ///
+    /// The scripts and the action plugins are run on a clone of the current [`RawContext`],
+    /// so neither the position, the locals nor the record is changed,
+    /// and the plugin calls are neither recorded nor replayed.
+    /// The script methods are not called, as the plugins with inner states,
+    /// e.g., `random`, would be advanced.
+    /// It returns [`None`] if the game ends, or the action calls a script method.
+    pub fn peek_next(&self) -> Option<Action> {
+        let mut ctx = self.ctx.clone();
+        self.runtime
+            .rerender_line(&[], || self.next_action(&mut ctx))
+            .and_then(|res| res.ok())
+    }
+
+    /// Evaluate the `\when` command of the text.
+    /// The condition in the base language takes precedence,
+    /// as the game logic shouldn't differ between locales.
+    fn text_visible(&self, ctx: &mut RawContext, text: Fallback<&Text>) -> bool {
+        fn find_when(t: &Text) -> Option<&Program> {
+            t.0.iter().find_map(|line| match line {
+                Line::Cmd(Command::When(p)) => Some(p),
+                _ => None,
+            })
+        }
+
+        let (when, base_when) = text.map(find_when).unzip();
+        match base_when.flatten().or_else(|| when.flatten()) {
+            Some(p) => self.table_with(&mut ctx.locals).call(p).get_bool(),
+            None => true,
+        }
+    }
+
+    fn next_action(&self, ctx: &mut RawContext) -> Result<Action, EndReason> {
+        let cur_para = self.paragraph_of(ctx);
         if cur_para.is_some() {
-            let cur_text = self.current_text();
-            if cur_text.is_some() {
-                let text = cur_text.map(|act| self.parse_text_rich_error(act));
+            let text = self.parsed_text_of(ctx);
+            if text.is_some() {
+                self.runtime.begin_line();
+                if !self.text_visible(ctx, text.as_ref()) {
+                    ctx.cur_act += 1;
+                    return self.next_action(ctx);
+                }
                 let para_title = cur_para.and_then(|p| p.title.as_ref()).cloned();
+                let voice = cur_para
+                    .and_then(|p| p.voices.get(ctx.cur_act).filter(|s| !s.is_empty()))
+                    .cloned();
                 let actions = text.map(|t| {
-                    self.exact_text(para_title.clone(), t).unwrap_or_else(|e| {
-                        error!("Exact text error: {}", e);
-                        Action::default()
-                    })
+                    self.exact_text(ctx, para_title.clone(), voice.clone(), t)
+                        .unwrap_or_else(|e| {
+                            error!(
+                                "Exact text error on paragraph \"{}\", act {}: {}",
+                                ctx.cur_para,
+                                ctx.cur_act + 1,
+                                e
+                            );
+                            Action::default()
+                        })
                 });
-                let res = self.merge_action(actions).map(|act| {
-                    self.process_action(act).unwrap_or_else(|e| {
-                        error!("Error when processing action: {}", e);
-                        Action::default()
+                let res = self
+                    .merge_action(actions)
+                    .map(|act| {
+                        self.process_action(act).unwrap_or_else(|e| {
+                            error!("Error when processing action: {}", e);
+                            Action::default()
+                        })
                     })
-                });
-                self.ctx.cur_act += 1;
+                    .ok_or_else(|| EndReason::Error(ctx.cur_para.clone()));
+                ctx.cur_act += 1;
                 res
             } else {
-                self.ctx.cur_para = cur_para
+                let next = cur_para
                     .and_then(|p| p.next.as_ref())
-                    .map(|next| self.parse_text_rich_error(next))
-                    .map(|text| self.call(&text).into_str())
+                    .map(|next| self.parse_text_rich_error(ctx, next))
+                    .map(|text| self.table_with(&mut ctx.locals).call(&text).into_str())
                     .unwrap_or_default();
-                self.ctx.cur_act = 0;
-                self.next_run()
+                let ending = self
+                    .game
+                    .ending_of(self.locale(), &ctx.cur_para)
+                    .map(|ending| ending.to_string());
+                ctx.cur_para = next;
+                ctx.cur_act = 0;
+                if ctx.cur_para.is_empty() {
+                    Err(ending.map(EndReason::Ending).unwrap_or(EndReason::NoNext))
+                } else {
+                    self.next_action(ctx)
+                }
             }
+        } else if ctx.cur_para.is_empty() {
+            Err(EndReason::NoNext)
         } else {
-            None
+            Err(EndReason::Error(ctx.cur_para.clone()))
+        }
+    }
+
+    /// Step through the actions as a stream, until the game ends.
+    ///
+    /// The stream stops after yielding an action with switches,
+    /// so that the caller could choose one with [`Context::switch`] and continue.
+    #[stream(Action, lifetime = "'a")]
+    pub async fn run_stream<'a>(&'a mut self) {
+        while let Some(action) = self.next_run() {
+            let has_switches = !action.switches.is_empty();
+            yield action;
+            if has_switches {
+                break;
+            }
+        }
+    }
+
+    /// Skip the visited actions, and step to the first unvisited one.
+    ///
+    /// If `skip_all` is true, the unvisited actions are also skipped.
+    /// The skipping always stops at an action with switches.
+    /// Returns the action it stops at.
+    pub fn skip_run(&mut self, skip_all: bool) -> Option<Action> {
+        let mut last_action = None;
+        while let Some(action) = self.next_run() {
+            if !action.switches.is_empty() || !(skip_all || self.visited(&action)) {
+                return Some(action);
+            }
+            last_action = Some(action);
+        }
+        last_action
+    }
+
+    /// Jump to the start of a paragraph by tag.
+    ///
+    /// Only the position is changed, and nothing is recorded.
+    /// The next [`Context::next_run`] returns the first action of the paragraph,
+    /// and records it in the history,
+    /// so [`Context::next_back_run`] steps back across the jump.
+    pub fn goto(&mut self, tag: &str) -> Result<()> {
+        if !self.game.find_para_fallback(self.locale(), tag).is_some() {
+            bail!("Cannot find paragraph \"{}\".", tag);
         }
+        log::debug!("Goto para {}", tag);
+        self.ctx.cur_para = tag.to_string();
+        self.ctx.cur_act = 0;
+        Ok(())
     }
 
     /// Step back to the last run.
@@ -469,6 +1229,60 @@ impl Context {
         }
     }
 
+    /// Seek to the action at the index of the history.
+    ///
+    /// The context is restored to the state after the action,
+    /// and the actions and choices after it are dropped,
+    /// so the next [`Context::next_run`] continues from it.
+    /// The local variables are restored from the snapshot in the action,
+    /// so nothing is replayed.
+    pub fn seek(&mut self, index: usize) -> Result<Action> {
+        if index >= self.record.history.len() {
+            bail!(
+                "Index {} out of range of the history with {} actions.",
+                index,
+                self.record.history.len()
+            );
+        }
+        // Keep the choices made before the action.
+        let mut choices_len = 0;
+        for action in &self.record.history[..index] {
+            if let Some(choice) = self.record.choices.get(choices_len) {
+                if choice.para == action.ctx.cur_para && choice.act == action.ctx.cur_act {
+                    choices_len += 1;
+                }
+            }
+        }
+        self.record.choices.truncate(choices_len);
+        self.record.history.truncate(index + 1);
+        let action = self.record.history[index].clone();
+        self.ctx = action.ctx.clone();
+        self.ctx.cur_act += 1;
+        log::debug!(
+            "Seek to para {}, act {}",
+            action.ctx.cur_para,
+            action.ctx.cur_act
+        );
+        Ok(action)
+    }
+
+    /// Check the game statically with [`check_game`],
+    /// and append the diagnostics of the game plugins as warnings.
+    pub fn diagnose(&self) -> Vec<CheckMessage> {
+        let mut messages = check_game(&self.game, &self.runtime);
+        for (name, msg) in &self.game_diagnostics {
+            messages.push(CheckMessage {
+                severity: Severity::Warning,
+                locale: None,
+                para: None,
+                act: None,
+                loc: None,
+                message: format!("Plugin {}: {}", name, msg),
+            });
+        }
+        messages
+    }
+
     /// Check all paragraphs to find grammer errors.
     pub fn check(&mut self) -> bool {
         let mut succeed = true;
@@ -487,3 +1301,570 @@ impl Context {
         succeed
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{plugin::Runtime, *};
+    use std::path::{Path, PathBuf};
+
+    /// Creates a context of the game with the `format` plugin, and starts a new game.
+    async fn context_of(yaml: &str) -> Context {
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["format"],
+        )
+        .await
+        .unwrap();
+        let mut context =
+            Context::create(game, FrontendType::Text, PathBuf::new(), runtime).unwrap();
+        context.init_new();
+        context
+    }
+
+    #[tokio::test]
+    async fn peek_next() {
+        let mut context = Context::open(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../examples/Fibonacci/config.yaml"
+            ),
+            FrontendType::Text,
+        )
+        .await
+        .unwrap();
+        context.init_new();
+        for _ in 0..10 {
+            let ctx = context.ctx.clone();
+            let history_len = context.record.history.len();
+            let peeked = context.peek_next().unwrap();
+            assert_eq!(context.ctx.cur_para, ctx.cur_para);
+            assert_eq!(context.ctx.cur_act, ctx.cur_act);
+            assert_eq!(context.ctx.locals, ctx.locals);
+            assert_eq!(context.record.history.len(), history_len);
+            let action = context.next_run().unwrap();
+            assert_eq!(*peeked.line, *action.line);
+            assert_eq!(peeked.ctx.cur_para, action.ctx.cur_para);
+            assert_eq!(peeked.ctx.cur_act, action.ctx.cur_act);
+        }
+    }
+
+    #[tokio::test]
+    async fn seek() {
+        let mut context = Context::open(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../examples/Fibonacci/config.yaml"
+            ),
+            FrontendType::Text,
+        )
+        .await
+        .unwrap();
+        context.init_new();
+        let actions = (0..10)
+            .map(|_| context.next_run().unwrap())
+            .collect::<Vec<_>>();
+        assert!(context.seek(10).is_err());
+        let action = context.seek(4).unwrap();
+        assert_eq!(*action.line, *actions[4].line);
+        assert_eq!(context.record.history.len(), 5);
+        for expected in &actions[5..] {
+            assert_eq!(*context.next_run().unwrap().line, *expected.line);
+        }
+    }
+
+    #[tokio::test]
+    async fn vars() {
+        let mut context = Context::open(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../examples/Fibonacci/config.yaml"
+            ),
+            FrontendType::Text,
+        )
+        .await
+        .unwrap();
+        context.init_new();
+        assert_eq!(context.get_var("a"), None);
+        context.set_var("a", RawValue::Num(1));
+        assert_eq!(context.get_var("a"), Some(RawValue::Num(1)));
+        let program = ayaka_script::parse_program("$a += 1; $a").unwrap();
+        assert_eq!(context.call(&program), RawValue::Num(2));
+        assert_eq!(context.get_var("a"), Some(RawValue::Num(2)));
+    }
+
+    #[tokio::test]
+    async fn when() {
+        let yaml = r#"
+title: When
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - \exec{$flag = 1; ""}First
+        - \when{$flag}Shown
+        - \when{$flag == 0}Hidden
+        - \when{$none}Hidden
+        - Last
+"#;
+        let mut context = context_of(yaml).await;
+        let lines = std::iter::from_fn(|| context.next_run())
+            .map(|action| action.line[0].as_str().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["First", "Shown", "Last"]);
+        assert_eq!(context.record.history.len(), 3);
+        assert_eq!(context.record.history[2].ctx.cur_act, 4);
+    }
+
+    #[tokio::test]
+    async fn reset() {
+        let yaml = r#"
+title: Reset
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - \exec{a = random.rnd(1000); $sum = $sum + a; a}
+        - \exec{random.rnd(1000)}
+        - \exec{$sum}
+"#;
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["format", "random"],
+        )
+        .await
+        .unwrap();
+        let mut context =
+            Context::create(game, FrontendType::Text, PathBuf::new(), runtime).unwrap();
+        context.init_new_seeded(114514);
+        // The script methods are not called when peeking.
+        assert!(context.peek_next().is_none());
+        let first = std::iter::from_fn(|| context.next_run())
+            .map(|action| action.line[0].as_str().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(first.len(), 3);
+        assert_eq!(first[0], first[2]);
+        context.reset();
+        assert!(context.record.history.is_empty());
+        assert!(context.ctx.locals.is_empty());
+        assert_eq!(context.ctx.cur_para, "init");
+        assert_eq!(context.ctx.cur_act, 0);
+        let second = std::iter::from_fn(|| context.next_run())
+            .map(|action| action.line[0].as_str().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(first, second);
+        // A new game without seed doesn't continue the seeded sequence.
+        context.init_new();
+        let third = std::iter::from_fn(|| context.next_run())
+            .map(|action| action.line[0].as_str().to_string())
+            .collect::<Vec<_>>();
+        assert_ne!(first, third);
+    }
+
+    #[tokio::test]
+    async fn goto() {
+        let yaml = r#"
+title: Goto
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - Hello
+        - Bye
+    - tag: other
+      texts:
+        - Other
+"#;
+        let mut context = context_of(yaml).await;
+        assert_eq!(context.next_run().unwrap().line[0].as_str(), "Hello");
+        assert!(context.goto("nowhere").is_err());
+        context.goto("other").unwrap();
+        assert_eq!(context.record.history.len(), 1);
+        assert_eq!(context.next_run().unwrap().line[0].as_str(), "Other");
+        assert_eq!(context.record.history.len(), 2);
+        // Step back across the jump, and the jump is kept.
+        assert_eq!(context.next_back_run().unwrap().line[0].as_str(), "Hello");
+        assert_eq!(context.next_run().unwrap().line[0].as_str(), "Other");
+    }
+
+    #[tokio::test]
+    async fn ruby() {
+        let yaml = r#"
+title: Ruby
+base_lang: ja
+paras:
+  ja:
+    - tag: init
+      texts:
+        - \ruby{漢字}{かんじ}です
+        - \ruby{漢字}です
+"#;
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["format", "ruby"],
+        )
+        .await
+        .unwrap();
+        let mut context =
+            Context::create(game, FrontendType::Text, PathBuf::new(), runtime).unwrap();
+        context.init_new();
+        let line = |action: Action| action.line.iter().map(|l| l.as_str()).collect::<String>();
+        assert_eq!(line(context.next_run().unwrap()), "漢字(かんじ)です");
+        // The reading is omitted, but the line is still shown.
+        assert_eq!(line(context.next_run().unwrap()), "漢字です");
+    }
+
+    #[tokio::test]
+    async fn locale_seeded() {
+        let yaml = r#"
+title: Locale
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - \exec{random.rnd(1000)}
+        - \exec{random.rnd(1000)}
+        - \exec{random.rnd(1000)}
+  ja:
+    - tag: init
+      texts:
+        - 乱数\exec{random.rnd(1000)}
+"#;
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["format", "random"],
+        )
+        .await
+        .unwrap();
+        let mut context =
+            Context::create(game, FrontendType::Text, PathBuf::new(), runtime).unwrap();
+        let line = |action: &Action| action.line.iter().map(|l| l.as_str()).collect::<String>();
+        context.set_locale(locale!("en"));
+        context.init_new_seeded(114514);
+        let first = std::iter::from_fn(|| context.next_run())
+            .map(|action| line(&action))
+            .collect::<Vec<_>>();
+
+        context.init_new_seeded(114514);
+        let mut second = vec![line(&context.next_run().unwrap())];
+        // The line is shown in the new locale, with the same random number.
+        context.set_locale(locale!("ja"));
+        assert_eq!(
+            line(context.record.history.last().unwrap()),
+            format!("乱数{}", second[0])
+        );
+        context.set_locale(locale!("en"));
+        assert_eq!(line(context.record.history.last().unwrap()), second[0]);
+        second.extend(std::iter::from_fn(|| context.next_run()).map(|action| line(&action)));
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn end_reason() {
+        let yaml = r#"
+title: Endings
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - Hello
+      next: \exec{$route}
+    - tag: good
+      texts:
+        - Good
+      ending: True End
+    - tag: plain
+      texts:
+        - Plain
+"#;
+        let mut context = context_of(yaml).await;
+        let mut run = |route: &str| {
+            context.init_new();
+            context
+                .ctx
+                .locals
+                .insert("route".to_string(), RawValue::Str(route.to_string()));
+            while context.next_run().is_some() {
+                assert!(context.end_reason().is_none());
+            }
+            // Stepping again after the end keeps the reason.
+            assert!(context.next_run().is_none());
+            context.end_reason().cloned()
+        };
+        assert_eq!(run("good"), Some(EndReason::Ending("True End".to_string())));
+        assert_eq!(run("plain"), Some(EndReason::NoNext));
+        assert_eq!(
+            run("nowhere"),
+            Some(EndReason::Error("nowhere".to_string()))
+        );
+        assert_eq!(
+            context.unlocked_endings().iter().collect::<Vec<_>>(),
+            ["True End"]
+        );
+        context.init_new();
+        assert!(context.end_reason().is_none());
+    }
+
+    #[tokio::test]
+    async fn gallery() {
+        let yaml = r#"
+title: Gallery
+base_lang: en
+gallery:
+  - cg1
+  - cg2
+paras:
+  en:
+    - tag: init
+      texts:
+        - \bg{cg1}Hello
+        - \bg{plain}Hi
+        - Bye
+"#;
+        let game = Game::from_slice(yaml.as_bytes(), Path::new("config.yaml")).unwrap();
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["media"],
+        )
+        .await
+        .unwrap();
+        let mut context =
+            Context::create(game, FrontendType::Text, PathBuf::new(), runtime).unwrap();
+        context.init_new();
+        assert!(context.unlocked_gallery().is_empty());
+        while context.next_run().is_some() {}
+        assert_eq!(
+            context.unlocked_gallery().iter().collect::<Vec<_>>(),
+            ["cg1"]
+        );
+
+        // Loading a record which has shown a newly-added item doesn't unlock it.
+        let snap = context.snapshot();
+        context.game.gallery.push("plain".to_string());
+        context.restore(&snap);
+        assert_eq!(
+            context.unlocked_gallery().iter().collect::<Vec<_>>(),
+            ["cg1"]
+        );
+        context.init_new();
+        while context.next_run().is_some() {}
+        assert_eq!(
+            context.unlocked_gallery().iter().collect::<Vec<_>>(),
+            ["cg1", "plain"]
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshot() {
+        let yaml = r#"
+title: Snapshot
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - \exec{$n = 1; $n}
+        - \exec{$n += 1; $n}
+        - \exec{$n += 1; $n}
+"#;
+        let mut context = context_of(yaml).await;
+        assert_eq!(context.next_run().unwrap().line[0].as_str(), "1");
+        let snap = context.snapshot();
+        assert_eq!(context.next_run().unwrap().line[0].as_str(), "2");
+        assert_eq!(context.next_run().unwrap().line[0].as_str(), "3");
+        context.restore(&snap);
+        assert_eq!(context.record.history.len(), 1);
+        assert_eq!(context.ctx.locals["n"], RawValue::Num(1));
+        assert_eq!(context.next_run().unwrap().line[0].as_str(), "2");
+    }
+
+    #[tokio::test]
+    async fn set_prop() {
+        let yaml = r#"
+title: Props
+base_lang: en
+props:
+  volume: "100"
+paras:
+  en:
+    - tag: init
+      texts:
+        - Hello
+"#;
+        let mut context = context_of(yaml).await;
+        assert!(context.runtime.prop_modules.is_empty());
+        context.set_prop("volume", "50");
+        context.set_prop("crt", "on");
+        assert_eq!(context.game.props["volume"], "50");
+        assert_eq!(context.game.props["crt"], "on");
+    }
+
+    #[tokio::test]
+    async fn locales() {
+        let yaml = r#"
+title: Locales
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - Hello
+        - Bye
+  ja:
+    - tag: init
+      texts:
+        - こんにちは
+res:
+  en: {}
+  zh-Hans: {}
+"#;
+        let mut context = context_of(yaml).await;
+        assert_eq!(
+            context.available_locales(),
+            [locale!("en"), locale!("ja"), locale!("zh-Hans")]
+        );
+        context.set_locale(locale!("en"));
+        context.init_new();
+        let line = |context: &Context| context.record.history.last().unwrap().line[0].clone();
+        context.next_run().unwrap();
+        assert_eq!(line(&context).as_str(), "Hello");
+        context.set_locale(locale!("ja"));
+        assert_eq!(line(&context).as_str(), "こんにちは");
+        assert_eq!(context.record.history.len(), 1);
+        // The second text falls back to the base language.
+        context.next_run().unwrap();
+        assert_eq!(line(&context).as_str(), "Bye");
+        let mut settings = context.settings().clone();
+        settings.lang = locale!("en");
+        context.set_settings(settings);
+        assert_eq!(line(&context).as_str(), "Bye");
+    }
+
+    #[tokio::test]
+    async fn text_cache() {
+        let mut context = Context::open(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../examples/Fibonacci/config.yaml"
+            ),
+            FrontendType::Text,
+        )
+        .await
+        .unwrap();
+        context.init_new();
+        let actions = (0..10)
+            .map(|_| context.next_run().unwrap())
+            .collect::<Vec<_>>();
+        let stats = context.text_cache_stats();
+        // The loop paragraph is visited repeatedly.
+        assert!(stats.hits > 0);
+        for _ in 0..5 {
+            context.next_back_run().unwrap();
+        }
+        for expected in &actions[5..] {
+            assert_eq!(*context.next_run().unwrap().line, *expected.line);
+        }
+        let backtracked = context.text_cache_stats();
+        assert_eq!(backtracked.misses, stats.misses);
+        assert_eq!(backtracked.hits, stats.hits + 5);
+        assert!(backtracked.saved_time() >= stats.saved_time());
+
+        context.clear_text_cache();
+        assert_eq!(context.text_cache_stats(), Default::default());
+    }
+
+    #[tokio::test]
+    async fn switch_enabled() {
+        let yaml = r#"
+title: Switch
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - \exec{$key = true; ""}Key
+        - \switch{Open}{$s = 1}{$key}\switch{Break}{$s = 2}{$key == false}\switch{Leave}{$s = 3}
+"#;
+        let mut context = context_of(yaml).await;
+        context.next_run().unwrap();
+        let action = context.next_run().unwrap();
+        let enabled = action
+            .switches
+            .iter()
+            .map(|s| s.enabled)
+            .collect::<Vec<_>>();
+        assert_eq!(enabled, [true, false, true]);
+
+        let err = context.switch(&action, 1).unwrap_err();
+        assert_eq!(err.to_string(), "Switch 1 is disabled.");
+        assert!(context.choice_history().is_empty());
+        assert_eq!(context.get_var("s"), None);
+
+        assert!(!context.switch_visited(&action, 0));
+        context.switch(&action, 0).unwrap();
+        assert_eq!(context.get_var("s"), Some(RawValue::Num(1)));
+        assert_eq!(context.choice_history().len(), 1);
+        assert!(context.switch_visited(&action, 0));
+        assert!(!context.switch_visited(&action, 2));
+
+        // The choices of the earlier playthroughs are kept.
+        context.init_new();
+        assert!(context.switch_visited(&action, 0));
+    }
+
+    #[tokio::test]
+    async fn default_switch() {
+        let yaml = r#"
+title: Switch
+base_lang: en
+paras:
+  en:
+    - tag: init
+      texts:
+        - \switch{Speak}{$s = 1}\switch{Stay silent}{$s = 0}{}{3000}
+        - \switch{A}{}{}{}\switch{B}{}{}{}
+        - Last
+"#;
+        let mut context = context_of(yaml).await;
+        let action = context.next_run().unwrap();
+        assert_eq!(action.default_switch(), Some(1));
+        assert_eq!(action.switches[1].timeout_ms, Some(3000));
+        assert_eq!(action.switches[0].timeout_ms, None);
+
+        // Only the first default switch is kept.
+        let action = context.next_run().unwrap();
+        assert_eq!(action.switches.len(), 2);
+        assert_eq!(action.default_switch(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn run_stream() {
+        let mut context = Context::open(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../examples/Fibonacci/config.yaml"
+            ),
+            FrontendType::Text,
+        )
+        .await
+        .unwrap();
+        context.init_new();
+        let actions = context.run_stream().collect::<Vec<_>>().await;
+        assert_eq!(actions.len(), 51);
+        assert_eq!(actions[50].line[0].as_str(), "20365011074");
+        assert!(context.next_run().is_none());
+    }
+}
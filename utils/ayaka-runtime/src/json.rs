@@ -0,0 +1,103 @@
+use crate::*;
+use ayaka_bindings_types::ActionLines;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The stable JSON representation of an [`Action`],
+/// for the frontends of [`FrontendType::Json`].
+///
+/// Unlike [`Action`] itself, it doesn't contain the inner context
+/// or the scripts of the switches.
+#[derive(Debug, Serialize)]
+pub struct ActionJson<'a> {
+    /// The key of current character.
+    pub ch_key: Option<&'a str>,
+    /// The current character.
+    pub character: Option<&'a str>,
+    /// The title of current paragraph.
+    pub para_title: Option<&'a str>,
+    /// The voice of current line.
+    pub voice: Option<&'a str>,
+    /// The lines, each as `{"type": "Chars" | "Block", "data": string}`,
+    /// or `{"type": "Pause", "data": number}` in milliseconds.
+    pub line: &'a ActionLines,
+    /// The switches.
+    pub switches: Vec<SwitchJson<'a>>,
+    /// The props.
+    pub props: &'a BTreeMap<String, String>,
+}
+
+/// The JSON representation of a [`Switch`].
+#[derive(Debug, Serialize)]
+pub struct SwitchJson<'a> {
+    /// The switch text.
+    pub text: &'a str,
+    /// Whether the switch is enabled.
+    pub enabled: bool,
+    /// Whether the switch is chosen when the player doesn't act.
+    pub default: bool,
+    /// The delay in milliseconds before choosing the default switch.
+    pub timeout_ms: Option<u64>,
+}
+
+impl<'a> From<&'a Action> for ActionJson<'a> {
+    fn from(action: &'a Action) -> Self {
+        Self {
+            ch_key: action.ch_key.as_deref(),
+            character: action.character.as_deref(),
+            para_title: action.para_title.as_deref(),
+            voice: action.voice.as_deref(),
+            line: &action.line,
+            switches: action
+                .switches
+                .iter()
+                .map(|s| SwitchJson {
+                    text: &s.text,
+                    enabled: s.enabled,
+                    default: s.default,
+                    timeout_ms: s.timeout_ms,
+                })
+                .collect(),
+            props: &action.props,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use serde_json::json;
+
+    #[test]
+    fn action_json() {
+        let mut action = Action {
+            character: Some("Ayaka".to_string()),
+            ..Default::default()
+        };
+        action.line.push_back_chars("Hello");
+        action.line.push_back_block("<br />");
+        action.line.push_back(ActionLine::Pause(300));
+        action.switches.push(Switch {
+            text: "Yes".to_string(),
+            enabled: false,
+            ..Default::default()
+        });
+        action.props.insert("bg".to_string(), "room".to_string());
+        assert_eq!(
+            serde_json::to_value(ActionJson::from(&action)).unwrap(),
+            json!({
+                "ch_key": null,
+                "character": "Ayaka",
+                "para_title": null,
+                "voice": null,
+                "line": [
+                    { "type": "Chars", "data": "Hello" },
+                    { "type": "Block", "data": "<br />" },
+                    { "type": "Pause", "data": 300 },
+                ],
+                "switches": [{ "text": "Yes", "enabled": false, "default": false, "timeout_ms": null }],
+                "props": { "bg": "room" },
+            })
+        );
+    }
+}
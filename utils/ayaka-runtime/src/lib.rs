@@ -11,9 +11,12 @@
 #![feature(once_cell)]
 #![feature(round_char_boundary)]
 
+mod check;
 mod config;
 mod context;
+mod json;
 mod locale;
+mod pack;
 pub mod plugin;
 pub mod script;
 mod settings;
@@ -24,12 +27,15 @@ pub use anyhow;
 pub use ayaka_script::log;
 #[doc(no_inline)]
 pub use ayaka_script_types::RawValue;
+pub use check::*;
 pub use config::*;
 pub use context::*;
 #[doc(no_inline)]
 pub use futures_util::{pin_mut, StreamExt, TryStreamExt};
+pub use json::*;
 #[doc(no_inline)]
 pub use locale::*;
+pub use pack::*;
 pub use settings::*;
 
 /// Get the version of Ayaka runtime.
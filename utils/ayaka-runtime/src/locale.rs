@@ -8,6 +8,7 @@ static MATCHER: LazyLock<LanguageMatcher> = LazyLock::new(LanguageMatcher::new);
 
 /// Representation of a language identifier.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
 #[repr(transparent)]
 pub struct Locale(pub LanguageIdentifier);
 
@@ -47,9 +48,57 @@ impl Locale {
         &self,
         locales: impl IntoIterator<Item = impl Into<&'a Locale>>,
     ) -> Option<&'a Locale> {
-        MATCHER
-            .matches(self.0.clone(), locales.into_iter().map(|loc| loc.into()))
-            .map(|(lang, _)| lang)
+        self.rank_refs(locales)
+            .into_iter()
+            .next()
+            .map(|(loc, _)| loc)
+    }
+
+    /// Rank the provided locales by the distance to this one, from the best match.
+    ///
+    /// The distance is calculated by [`LanguageMatcher::distance`];
+    /// the smaller, the better.
+    /// The locales with distance not less than [`Locale::MAX_DISTANCE`] are dropped,
+    /// as they don't match at all.
+    /// If the distances are equal, the locale exactly equals to this one comes first,
+    /// and then the others are ordered by their string representations,
+    /// so the result doesn't depend on the order of the provided locales.
+    ///
+    /// ```
+    /// # use ayaka_runtime::locale;
+    /// let current = locale!("zh-CN");
+    /// let ranked = current.rank_from(&[locale!("en"), locale!("zh-Hant"), locale!("zh-Hans")]);
+    /// assert_eq!(ranked[0].0, locale!("zh-Hans"));
+    /// assert_eq!(ranked[0].1, 0);
+    /// assert_eq!(ranked.len(), 2);
+    /// ```
+    pub fn rank_from<'a>(
+        &self,
+        locales: impl IntoIterator<Item = impl Into<&'a Locale>>,
+    ) -> Vec<(Locale, u32)> {
+        self.rank_refs(locales)
+            .into_iter()
+            .map(|(loc, distance)| (loc.clone(), distance))
+            .collect()
+    }
+
+    /// The distance from which the locales don't match.
+    pub const MAX_DISTANCE: u32 = 1000;
+
+    fn rank_refs<'a>(
+        &self,
+        locales: impl IntoIterator<Item = impl Into<&'a Locale>>,
+    ) -> Vec<(&'a Locale, u32)> {
+        let mut ranked = locales
+            .into_iter()
+            .map(|loc| {
+                let loc = loc.into();
+                (loc, MATCHER.distance(self.0.clone(), loc.0.clone()) as u32)
+            })
+            .filter(|(_, distance)| *distance < Self::MAX_DISTANCE)
+            .collect::<Vec<_>>();
+        ranked.sort_by_cached_key(|(loc, distance)| (*distance, *loc != self, loc.to_string()));
+        ranked
     }
 }
 
@@ -118,4 +167,18 @@ mod test {
             Some(&locale!("zh-Hant"))
         );
     }
+
+    #[test]
+    fn rank_stable() {
+        let accepts = [locale!("en-GB"), locale!("en-AU"), locale!("en-NZ")];
+        let mut reversed = accepts.clone();
+        reversed.reverse();
+        let current = locale!("en-CA");
+        assert_eq!(current.rank_from(&accepts), current.rank_from(&reversed));
+        assert_eq!(
+            current.choose_from(&accepts),
+            current.choose_from(&reversed)
+        );
+        assert!(current.rank_from(&[locale!("ja")]).is_empty());
+    }
 }
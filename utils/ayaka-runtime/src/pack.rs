@@ -0,0 +1,210 @@
+use crate::*;
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    path::{Component, Path, PathBuf},
+};
+use zip::ZipArchive;
+
+/// The manifest of a game pack.
+/// It is stored as `manifest.json` in the root of the pack.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PackManifest {
+    /// The version of the pack format.
+    pub version: u32,
+    /// The path of the config file inside the pack.
+    pub config: String,
+}
+
+impl PackManifest {
+    /// The current version of the pack format.
+    pub const VERSION: u32 = 1;
+    /// The file name of the manifest.
+    pub const FILE_NAME: &'static str = "manifest.json";
+}
+
+/// The game pack, a zip archive containing
+/// the manifest, the config, the plugins and the resources.
+pub struct Pack {
+    archive: ZipArchive<Cursor<Vec<u8>>>,
+    manifest: PackManifest,
+}
+
+fn normalize(path: impl AsRef<Path>) -> PathBuf {
+    path.as_ref()
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+impl Pack {
+    /// Open a pack file.
+    ///
+    /// It fails if the manifest is missing or of an unsupported version.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_bytes(tokio::fs::read(path).await?)
+    }
+
+    /// Open a pack from the bytes in memory.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self> {
+        let mut archive = ZipArchive::new(Cursor::new(buf))?;
+        let manifest: PackManifest = {
+            let file = archive.by_name(PackManifest::FILE_NAME)?;
+            serde_json::from_reader(file)?
+        };
+        if manifest.version > PackManifest::VERSION {
+            bail!("Unsupported pack version {}", manifest.version);
+        }
+        Ok(Self { archive, manifest })
+    }
+
+    /// The manifest of the pack.
+    pub fn manifest(&self) -> &PackManifest {
+        &self.manifest
+    }
+
+    fn config_dir(&self) -> PathBuf {
+        normalize(
+            Path::new(&self.manifest.config)
+                .parent()
+                .unwrap_or_else(|| Path::new("")),
+        )
+    }
+
+    fn read_file(&mut self, index: usize) -> Result<(PathBuf, Vec<u8>)> {
+        let mut file = self.archive.by_index(index)?;
+        let path = file
+            .enclosed_name()
+            .map(normalize)
+            .ok_or_else(|| anyhow!("Invalid file name in pack: {}", file.name()))?;
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+        Ok((path, buf))
+    }
+
+    /// Read the config file.
+    pub fn game(&mut self) -> Result<Game> {
+        let mut buf = vec![];
+        self.archive
+            .by_name(&self.manifest.config)?
+            .read_to_end(&mut buf)?;
+        Game::from_slice(&buf, Path::new(&self.manifest.config))
+    }
+
+    /// Read the plugins in the plugin directory of the config.
+    ///
+    /// If [`PluginConfig::modules`] is not empty, only the specified plugins are read.
+    pub fn plugins(&mut self, game: &Game) -> Result<HashMap<String, Vec<u8>>> {
+        let plugin_dir = normalize(self.config_dir().join(&game.plugins.dir));
+        let mut plugins = HashMap::new();
+        for i in 0..self.archive.len() {
+            if self.archive.by_index(i)?.is_dir() {
+                continue;
+            }
+            let (path, buf) = self.read_file(i)?;
+            if path.parent() != Some(plugin_dir.as_path())
+                || path.extension().map(|ex| ex != "wasm").unwrap_or(true)
+            {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if game.plugins.modules.is_empty() || game.plugins.modules.contains(&name) {
+                plugins.insert(name, buf);
+            }
+        }
+        Ok(plugins)
+    }
+
+    /// Extract the files other than the manifest, the config and the plugins
+    /// into the directory, and returns the root path of the game.
+    ///
+    /// The directory should be new, e.g., a temp directory,
+    /// and it fails if the directory is not empty.
+    pub(crate) async fn extract_resources(&mut self, game: &Game, dir: &Path) -> Result<PathBuf> {
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            bail!("Cannot extract resources into non-empty {}.", dir.display());
+        }
+        let plugin_dir = normalize(self.config_dir().join(&game.plugins.dir));
+        let manifest_path = Path::new(PackManifest::FILE_NAME);
+        let config_path = normalize(&self.manifest.config);
+        for i in 0..self.archive.len() {
+            if self.archive.by_index(i)?.is_dir() {
+                continue;
+            }
+            let (path, buf) = self.read_file(i)?;
+            let is_plugin = path.parent() == Some(plugin_dir.as_path())
+                && path.extension().map(|ex| ex == "wasm").unwrap_or(false);
+            if path == manifest_path || path == config_path || is_plugin {
+                continue;
+            }
+            let target = dir.join(&path);
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(target, buf).await?;
+        }
+        Ok(dir.join(self.config_dir()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::io::Write;
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn pack(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(std::io::Cursor::new(vec![]));
+        for (name, buf) in files {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(buf).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn read_pack() {
+        let config = "title: Pack\nbase_lang: en\nparas:\n  en: []\nplugins:\n  dir: plugins\n";
+        let buf = pack(&[
+            (
+                PackManifest::FILE_NAME,
+                br#"{"version":1,"config":"game/config.yaml"}"#,
+            ),
+            ("game/config.yaml", config.as_bytes()),
+            ("game/plugins/format.wasm", b"wasm"),
+            ("game/plugins/readme.txt", b"readme"),
+            ("game/bg/room.txt", b"room"),
+        ]);
+        let mut pack = Pack::from_bytes(buf).unwrap();
+        let game = pack.game().unwrap();
+        assert_eq!(game.title, "Pack");
+        let plugins = pack.plugins(&game).unwrap();
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins["format"], b"wasm");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = pack.extract_resources(&game, dir.path()).await.unwrap();
+        assert_eq!(root, dir.path().join("game"));
+        assert!(root.join("bg/room.txt").exists());
+        assert!(root.join("plugins/readme.txt").exists());
+        assert!(!root.join("plugins/format.wasm").exists());
+        assert!(!root.join("config.yaml").exists());
+        // The extracted files are not overwritten or removed.
+        assert!(pack.extract_resources(&game, dir.path()).await.is_err());
+        assert!(root.join("bg/room.txt").exists());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let buf = pack(&[(
+            PackManifest::FILE_NAME,
+            br#"{"version":2,"config":"config.yaml"}"#,
+        )]);
+        assert!(Pack::from_bytes(buf).is_err());
+    }
+}
@@ -4,49 +4,251 @@
 #![allow(clippy::mut_from_ref)]
 
 use crate::*;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use ayaka_bindings_types::*;
 use futures_util::TryStreamExt;
-use log::warn;
+use log::{warn, LevelFilter};
 use scopeguard::defer;
-use serde::{de::DeserializeOwned, Serialize};
-use std::{collections::HashMap, path::Path};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt::Display,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
 use stream_future::stream;
 use tokio_stream::wrappers::ReadDirStream;
 use wasmer::*;
 use wasmer_wasi::*;
 
+#[doc(no_inline)]
+pub use ayaka_bindings_types::Capability;
+
+/// The error when calling a plugin method.
+#[derive(Debug)]
+pub enum PluginError {
+    /// The method is not exported by the plugin.
+    FuncNotFound(String),
+    /// The plugin traps when running, e.g., it panics.
+    Trap(RuntimeError),
+    /// Cannot decode the returned data from MessagePack.
+    Decode(rmp_serde::decode::Error),
+    /// Cannot encode the arguments into MessagePack.
+    Encode(rmp_serde::encode::Error),
+    /// The plugin doesn't follow the ABI,
+    /// e.g., the signature of a method is incompatible.
+    Abi(String),
+    /// The plugin passes a buffer out of the bounds of its memory.
+    MemoryAccess {
+        /// The start of the buffer.
+        start: usize,
+        /// The end of the buffer.
+        end: usize,
+        /// The size of the memory.
+        size: u64,
+    },
+    /// Cannot read the plugin file.
+    Io(std::io::Error),
+    /// Cannot compile the WASM module.
+    Compile(CompileError),
+    /// Cannot instantiate the WASM module.
+    Instantiation(Box<InstantiationError>),
+    /// Cannot create the WASI environment.
+    Wasi(WasiStateCreationError),
+    /// The plugin imports the host functions of a [`Capability`] it doesn't declare.
+    Capability(Capability),
+    /// The method is called when rendering a line again,
+    /// but it isn't called when the line was rendered.
+    NotReplayed(String),
+}
+
+impl PluginError {
+    fn from_export(e: ExportError, name: &str) -> Self {
+        match e {
+            ExportError::Missing(name) => Self::FuncNotFound(name),
+            ExportError::IncompatibleType => {
+                Self::Abi(format!("Incompatible signature of `{}`.", name))
+            }
+        }
+    }
+}
+
+impl Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FuncNotFound(name) => write!(f, "Cannot find function `{}`.", name),
+            Self::Trap(e) => write!(f, "Plugin traps: {}", e),
+            Self::Decode(e) => write!(f, "Decode error: {}", e),
+            Self::Encode(e) => write!(f, "Encode error: {}", e),
+            Self::Abi(msg) => write!(f, "ABI error: {}", msg),
+            Self::MemoryAccess { start, end, size } => write!(
+                f,
+                "Memory access out of bounds: {}..{}, memory size {}.",
+                start, end, size
+            ),
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::Compile(e) => write!(f, "Compile error: {}", e),
+            Self::Instantiation(e) => write!(f, "Instantiation error: {}", e),
+            Self::Wasi(e) => write!(f, "WASI error: {}", e),
+            Self::Capability(c) => write!(
+                f,
+                "Capability error: the plugin requires {:?}, but doesn't declare it.",
+                c
+            ),
+            Self::NotReplayed(name) => write!(
+                f,
+                "Cannot call `{}` again without side effects, as it isn't called before.",
+                name
+            ),
+        }
+    }
+}
+
+impl Error for PluginError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Trap(e) => Some(e),
+            Self::Decode(e) => Some(e),
+            Self::Encode(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Compile(e) => Some(e),
+            Self::Instantiation(e) => Some(e.as_ref()),
+            Self::Wasi(e) => Some(e),
+            Self::FuncNotFound(_)
+            | Self::Abi(_)
+            | Self::MemoryAccess { .. }
+            | Self::Capability(_) => None,
+        }
+    }
+}
+
+impl From<RuntimeError> for PluginError {
+    fn from(e: RuntimeError) -> Self {
+        Self::Trap(e)
+    }
+}
+
+impl From<rmp_serde::decode::Error> for PluginError {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        Self::Decode(e)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for PluginError {
+    fn from(e: rmp_serde::encode::Error) -> Self {
+        Self::Encode(e)
+    }
+}
+
+impl From<std::io::Error> for PluginError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<CompileError> for PluginError {
+    fn from(e: CompileError) -> Self {
+        Self::Compile(e)
+    }
+}
+
+impl From<InstantiationError> for PluginError {
+    fn from(e: InstantiationError) -> Self {
+        Self::Instantiation(Box::new(e))
+    }
+}
+
+impl From<WasiStateCreationError> for PluginError {
+    fn from(e: WasiStateCreationError) -> Self {
+        Self::Wasi(e)
+    }
+}
+
+/// The [`std::result::Result`] when calling a plugin method.
+pub type PluginResult<T> = std::result::Result<T, PluginError>;
+
+/// The size of the chunks passed by [`Host::call_streaming`].
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
 /// An instance of a WASM plugin module.
 pub struct Host {
     abi_free: NativeFunc<(i32, i32), ()>,
     abi_alloc: NativeFunc<i32, i32>,
     instance: Instance,
+    call_count: AtomicU64,
+    call_nanos: AtomicU64,
+    borrows: AtomicUsize,
+}
+
+/// The resource usage of a [`Host`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PluginStats {
+    /// The size of the WASM memory.
+    pub memory_bytes: u64,
+    /// The count of the calls, including the failed ones.
+    pub call_count: u64,
+    /// The total time spent in the calls.
+    pub total_call_time: Duration,
 }
 
-unsafe fn mem_slice(memory: &Memory, start: i32, len: i32) -> &[u8] {
-    memory
-        .data_unchecked()
-        .get_unchecked(start as usize..)
-        .get_unchecked(..len as usize)
+fn mem_range(memory: &Memory, start: i32, len: i32) -> PluginResult<std::ops::Range<usize>> {
+    let (start, len) = (start as u32 as usize, len as u32 as usize);
+    start
+        .checked_add(len)
+        .filter(|end| *end <= memory.data_size() as usize)
+        .map(|end| start..end)
+        .ok_or_else(|| PluginError::MemoryAccess {
+            start,
+            end: start.saturating_add(len),
+            size: memory.data_size(),
+        })
 }
 
-unsafe fn mem_slice_mut(memory: &Memory, start: i32, len: i32) -> &mut [u8] {
-    memory
-        .data_unchecked_mut()
-        .get_unchecked_mut(start as usize..)
-        .get_unchecked_mut(..len as usize)
+/// Gets the slice of the guest memory.
+/// A [`PluginError::MemoryAccess`] is returned if the range exceeds the memory.
+///
+/// # Safety
+/// The memory should not be grown or written while the slice is alive.
+unsafe fn mem_slice(memory: &Memory, start: i32, len: i32) -> PluginResult<&[u8]> {
+    let range = mem_range(memory, start, len)?;
+    Ok(&memory.data_unchecked()[range])
+}
+
+/// Gets the mutable slice of the guest memory.
+/// A [`PluginError::MemoryAccess`] is returned if the range exceeds the memory.
+///
+/// # Safety
+/// The memory should not be grown or accessed while the slice is alive.
+unsafe fn mem_slice_mut(memory: &Memory, start: i32, len: i32) -> PluginResult<&mut [u8]> {
+    let range = mem_range(memory, start, len)?;
+    Ok(&mut memory.data_unchecked_mut()[range])
 }
 
 impl Host {
     /// Loads the WASM [`Module`], with some imports.
-    pub fn new(module: &Module, resolver: &(dyn Resolver + Send + Sync)) -> Result<Self> {
+    pub fn new(module: &Module, resolver: &(dyn Resolver + Send + Sync)) -> PluginResult<Self> {
         let instance = Instance::new(module, resolver)?;
-        let abi_free = instance.exports.get_native_function("__abi_free")?;
-        let abi_alloc = instance.exports.get_native_function("__abi_alloc")?;
+        let abi_free = instance
+            .exports
+            .get_native_function("__abi_free")
+            .map_err(|e| PluginError::from_export(e, "__abi_free"))?;
+        let abi_alloc = instance
+            .exports
+            .get_native_function("__abi_alloc")
+            .map_err(|e| PluginError::from_export(e, "__abi_alloc"))?;
         Ok(Self {
             abi_free,
             abi_alloc,
             instance,
+            call_count: AtomicU64::new(0),
+            call_nanos: AtomicU64::new(0),
+            borrows: AtomicUsize::new(0),
         })
     }
 
@@ -57,60 +259,335 @@ impl Host {
         &self,
         name: &str,
         args: Params,
-    ) -> Result<Res> {
-        let memory = self.instance.exports.get_memory("memory")?;
-        let func = self
+    ) -> PluginResult<Res> {
+        let data = rmp_serde::to_vec(&args)?;
+        let res_data = self.call_bytes_with(name, &data, |res| rmp_serde::from_slice(res))??;
+        Ok(res_data)
+    }
+
+    fn check_borrows(&self) -> PluginResult<()> {
+        if self.borrows.load(Ordering::Acquire) > 0 {
+            Err(PluginError::Abi(
+                "The memory is borrowed by the host.".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn borrow_memory<T>(&self, data: &[u8], f: impl FnOnce(&[u8]) -> T) -> T {
+        self.borrows.fetch_add(1, Ordering::AcqRel);
+        defer! { self.borrows.fetch_sub(1, Ordering::AcqRel); }
+        f(data)
+    }
+
+    /// Frees the buffer in the plugin memory.
+    ///
+    /// It is called in `defer!`, so a trap is logged instead of panicking.
+    fn free(&self, ptr: i32, len: i32) {
+        if let Err(e) = self.abi_free.call(ptr, len) {
+            warn!("Cannot free the plugin memory: {}", e);
+        }
+    }
+
+    /// Borrows the whole memory of the plugin.
+    ///
+    /// The plugin cannot be called while the memory is borrowed,
+    /// and the calls return [`PluginError::Abi`].
+    pub fn with_memory<T>(&self, f: impl FnOnce(&[u8]) -> T) -> PluginResult<T> {
+        let memory = self
             .instance
             .exports
-            .get_native_function::<(i32, i32), u64>(name)?;
+            .get_memory("memory")
+            .map_err(|e| PluginError::Abi(e.to_string()))?;
+        Ok(self.borrow_memory(unsafe { memory.data_unchecked() }, f))
+    }
 
-        let data = rmp_serde::to_vec(&args)?;
+    /// Calls a method by name, with the raw bytes as input.
+    ///
+    /// The input is copied into the plugin memory as is,
+    /// and the output buffer of the plugin is copied out as is,
+    /// without MessagePack framing.
+    /// The method should have the same signature as the methods exported by `#[export]`.
+    pub fn call_bytes(&self, name: &str, data: &[u8]) -> PluginResult<Vec<u8>> {
+        self.call_bytes_with(name, data, |res| res.to_vec())
+    }
+
+    /// Calls a method by name, with the raw bytes as input,
+    /// and borrows the output buffer in the plugin memory without copying.
+    ///
+    /// The buffer is freed after `f` returns.
+    /// The plugin cannot be called in `f`, see [`Host::with_memory`].
+    pub fn call_bytes_with<T>(
+        &self,
+        name: &str,
+        data: &[u8],
+        f: impl FnOnce(&[u8]) -> T,
+    ) -> PluginResult<T> {
+        self.check_borrows()?;
+        let start = Instant::now();
+        defer! {
+            self.call_count.fetch_add(1, Ordering::Relaxed);
+            self.call_nanos
+                .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+        let memory = self
+            .instance
+            .exports
+            .get_memory("memory")
+            .map_err(|e| PluginError::Abi(e.to_string()))?;
+        let func = self
+            .instance
+            .exports
+            .get_native_function::<(i32, i32), u64>(name)
+            .map_err(|e| PluginError::from_export(e, name))?;
 
-        let ptr = self.abi_alloc.call(data.len() as i32)?;
-        defer! { self.abi_free.call(ptr, data.len() as i32).unwrap(); }
-        unsafe { mem_slice_mut(memory, ptr, data.len() as i32) }.copy_from_slice(&data);
+        // An empty payload is passed as `(0, 0)`, without allocating.
+        let ptr = if data.is_empty() {
+            0
+        } else {
+            self.abi_alloc.call(data.len() as i32)?
+        };
+        defer! {
+            if !data.is_empty() {
+                self.free(ptr, data.len() as i32);
+            }
+        }
+        unsafe { mem_slice_mut(memory, ptr, data.len() as i32) }?.copy_from_slice(data);
 
         let res = func.call(data.len() as i32, ptr)?;
         let (len, res) = ((res >> 32) as i32, (res & 0xFFFFFFFF) as i32);
-        defer! { self.abi_free.call(res, len).unwrap(); }
+        defer! {
+            if len != 0 {
+                self.free(res, len);
+            }
+        }
 
-        let res_data = unsafe { mem_slice(memory, res, len) };
-        let res_data = rmp_serde::from_slice(res_data)?;
-        Ok(res_data)
+        let res_data = unsafe { mem_slice(memory, res, len) }?;
+        Ok(self.borrow_memory(res_data, f))
+    }
+
+    /// Calls a method by name, streaming the input and the output in chunks.
+    ///
+    /// The input is read into a buffer of [`STREAM_CHUNK_SIZE`] bytes in the plugin memory,
+    /// and the method is called with every chunk, the same way as [`Host::call_bytes`].
+    /// After the input ends, it is called with `(0, 0)` again and again,
+    /// until it returns an empty output, which signals the completion.
+    /// The output of every call is written to `writer` and freed immediately.
+    ///
+    /// The buffer is reused, so the plugin should not keep or free it.
+    /// Neither the host nor the plugin needs to hold the whole input.
+    pub fn call_streaming(
+        &self,
+        name: &str,
+        mut reader: impl Read,
+        mut writer: impl Write,
+    ) -> PluginResult<()> {
+        self.check_borrows()?;
+        let start = Instant::now();
+        defer! {
+            self.call_count.fetch_add(1, Ordering::Relaxed);
+            self.call_nanos
+                .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+        let memory = self
+            .instance
+            .exports
+            .get_memory("memory")
+            .map_err(|e| PluginError::Abi(e.to_string()))?;
+        let func = self
+            .instance
+            .exports
+            .get_native_function::<(i32, i32), u64>(name)
+            .map_err(|e| PluginError::from_export(e, name))?;
+
+        let buf_len = STREAM_CHUNK_SIZE as i32;
+        let buf = self.abi_alloc.call(buf_len)?;
+        defer! { self.free(buf, buf_len); }
+        let mut chunk = vec![0; STREAM_CHUNK_SIZE];
+        let mut ended = false;
+        loop {
+            let len = if ended {
+                0
+            } else {
+                match reader.read(&mut chunk) {
+                    Ok(len) => len,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            };
+            let ptr = if len == 0 {
+                ended = true;
+                0
+            } else {
+                unsafe { mem_slice_mut(memory, buf, len as i32) }?.copy_from_slice(&chunk[..len]);
+                buf
+            };
+
+            let res = func.call(len as i32, ptr)?;
+            let (len, res) = ((res >> 32) as i32, (res & 0xFFFFFFFF) as i32);
+            if len == 0 {
+                if ended {
+                    break;
+                }
+                continue;
+            }
+            let written = unsafe { mem_slice(memory, res, len) }.map(|out| writer.write_all(out));
+            self.abi_free.call(res, len)?;
+            written??;
+        }
+        Ok(())
+    }
+
+    /// Gets the memory size and the accumulated call timings.
+    pub fn stats(&self) -> PluginStats {
+        PluginStats {
+            memory_bytes: self
+                .instance
+                .exports
+                .get_memory("memory")
+                .map(|memory| memory.data_size())
+                .unwrap_or_default(),
+            call_count: self.call_count.load(Ordering::Relaxed),
+            total_call_time: Duration::from_nanos(self.call_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Gets the names of the exported functions.
+    pub fn exports(&self) -> Vec<String> {
+        self.instance
+            .exports
+            .iter()
+            .functions()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Determines if the plugin exports a function with the name.
+    pub fn has_export(&self, name: &str) -> bool {
+        self.instance.exports.get_function(name).is_ok()
     }
 
     /// Calls a script plugin method by name.
-    pub fn dispatch_method(&self, name: &str, args: &[RawValue]) -> Result<RawValue> {
+    pub fn dispatch_method(&self, name: &str, args: &[RawValue]) -> PluginResult<RawValue> {
         self.call(name, (args,))
     }
 
     /// Gets the [`PluginType`].
-    pub fn plugin_type(&self) -> Result<PluginType> {
+    pub fn plugin_type(&self) -> PluginResult<PluginType> {
         self.call("plugin_type", ())
     }
 
+    /// Gets the names of the plugins this plugin depends on.
+    ///
+    /// The export is optional; an empty list is returned if it doesn't exist.
+    pub fn plugin_deps(&self) -> PluginResult<Vec<String>> {
+        if self.has_export("plugin_deps") {
+            self.call("plugin_deps", ())
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Gets the names of the script methods which could be called without namespace.
+    ///
+    /// The export is optional; an empty list is returned if it doesn't exist.
+    pub fn global_methods(&self) -> PluginResult<Vec<String>> {
+        if self.has_export("global_methods") {
+            self.call("global_methods", ())
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Seeds the random engine of the plugin.
+    ///
+    /// The export is optional; nothing happens if it doesn't exist.
+    pub fn seed(&self, value: u64) -> PluginResult<()> {
+        if self.has_export("seed") {
+            self.call("seed", (value,))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Gets the [`Capability`]s the plugin declares.
+    ///
+    /// The export is optional; an empty list is returned if it doesn't exist.
+    pub fn capabilities(&self) -> PluginResult<Vec<Capability>> {
+        if self.has_export("capabilities") {
+            self.call("capabilities", ())
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Notifies the plugin that the runtime is shutting down, to flush its buffered state.
+    ///
+    /// The export is optional; nothing happens if it doesn't exist.
+    pub fn on_shutdown(&self) -> PluginResult<()> {
+        if self.has_export("on_shutdown") {
+            self.call("on_shutdown", ())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Gets the keys of the game props the plugin subscribes to.
+    ///
+    /// The export is optional; an empty list is returned if it doesn't exist.
+    pub fn prop_keys(&self) -> PluginResult<Vec<String>> {
+        if self.has_export("prop_keys") {
+            self.call("prop_keys", ())
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Notifies the plugin that a subscribed game prop is changed.
+    pub fn on_prop_changed(&self, key: &str, value: &str) -> PluginResult<()> {
+        self.call("on_prop_changed", (key, value))
+    }
+
     /// Processes [`Action`] in action plugin.
-    pub fn process_action(&self, ctx: ActionProcessContextRef) -> Result<Action> {
-        self.call("process_action", (ctx,))
+    ///
+    /// The plugins returning [`Action`] directly are also accepted.
+    pub fn process_action(
+        &self,
+        ctx: ActionProcessContextRef,
+    ) -> PluginResult<ActionProcessResult> {
+        let data = rmp_serde::to_vec(&(ctx,))?;
+        self.call_bytes_with("process_action", &data, decode_action_result)?
     }
 
     /// Gets registered TeX commands of a text plugin.
-    pub fn text_commands(&self) -> Result<Vec<String>> {
+    pub fn text_commands(&self) -> PluginResult<Vec<String>> {
         self.call("text_commands", ())
     }
 
+    /// Gets the range of the argument count of a text command.
+    ///
+    /// The export is optional; [`None`] is returned if it doesn't exist.
+    pub fn text_command_arity(&self, name: &str) -> PluginResult<Option<(usize, usize)>> {
+        if self.has_export("text_command_arity") {
+            self.call("text_command_arity", (name,))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Calls a custom command in the text plugin.
     pub fn dispatch_command(
         &self,
         name: &str,
         args: &[String],
         ctx: TextProcessContextRef,
-    ) -> Result<TextProcessResult> {
+    ) -> PluginResult<TextProcessResult> {
         self.call(name, (args, ctx))
     }
 
     /// Processes [`Game`] when opening the config file.
-    pub fn process_game(&self, ctx: GameProcessContextRef) -> Result<GameProcessResult> {
+    pub fn process_game(&self, ctx: GameProcessContextRef) -> PluginResult<GameProcessResult> {
         self.call("process_game", (ctx,))
     }
 }
@@ -123,8 +600,131 @@ pub struct Runtime {
     pub action_modules: Vec<String>,
     /// The text plugins by command name.
     pub text_modules: HashMap<String, String>,
+    /// The ranges of the argument count of text commands, if declared.
+    pub text_arity: HashMap<String, (usize, usize)>,
     /// The game plugins.
     pub game_modules: Vec<String>,
+    /// The plugins by global method name.
+    pub global_modules: HashMap<String, String>,
+    /// The plugins failed to load, with the errors.
+    pub failed_modules: Vec<(String, PluginError)>,
+    /// The capabilities declared by the plugins.
+    pub capabilities: HashMap<String, Vec<Capability>>,
+    /// The plugins by the game prop keys they subscribe to.
+    pub prop_modules: HashMap<String, Vec<String>>,
+    text_conflicts: Vec<(String, Vec<String>)>,
+    calls: Mutex<CallLog>,
+    log_levels: LogLevels,
+    events: EventHandler,
+}
+
+type LogLevels = Arc<RwLock<HashMap<String, LevelFilter>>>;
+
+type EventHandler = Arc<RwLock<Option<Arc<dyn Fn(PluginEvent) + Send + Sync>>>>;
+
+/// A custom event emitted by a plugin with `__emit_event`.
+///
+/// The frontend decides what to do with it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PluginEvent {
+    /// The name of the plugin.
+    pub plugin: String,
+    /// The name of the event.
+    pub name: String,
+    /// The payload.
+    pub value: RawValue,
+}
+
+/// A plugin call recorded by [`Runtime`].
+///
+/// The args and the result are stored as MessagePack bytes,
+/// exactly as they are passed to and from the plugin.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PluginCall {
+    /// The name of the plugin.
+    pub module: String,
+    /// The name of the method.
+    pub name: String,
+    /// The encoded args.
+    pub args: Vec<u8>,
+    /// The encoded result.
+    pub result: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct CallLog {
+    record: bool,
+    suspended: bool,
+    calls: Vec<PluginCall>,
+    replay: VecDeque<PluginCall>,
+    /// The script method calls of the current line.
+    line: Vec<PluginCall>,
+    /// The script method calls fed back when rendering the line again.
+    line_replay: Option<VecDeque<PluginCall>>,
+    line_diverged: bool,
+}
+
+impl CallLog {
+    fn replay_line(&mut self, module: &str, name: &str) -> Option<PluginResult<Vec<u8>>> {
+        let replay = self.line_replay.as_mut()?;
+        match replay.pop_front() {
+            Some(call) if call.module == module && call.name == name => Some(Ok(call.result)),
+            _ => {
+                // Don't feed the rest, as the order diverges.
+                replay.clear();
+                self.line_diverged = true;
+                Some(Err(PluginError::NotReplayed(format!(
+                    "{}.{}",
+                    module, name
+                ))))
+            }
+        }
+    }
+
+    fn record_line(&mut self, module: &str, name: &str, args: Vec<u8>, result: &[u8]) {
+        if !self.suspended {
+            self.line.push(PluginCall {
+                module: module.to_string(),
+                name: name.to_string(),
+                args,
+                result: result.to_vec(),
+            });
+        }
+    }
+
+    fn replay(&mut self, module: &str, name: &str, args: &[u8]) -> Option<Vec<u8>> {
+        if self.suspended {
+            return None;
+        }
+        let call = self.replay.front()?;
+        if call.module != module || call.name != name {
+            warn!(
+                "Replay diverges: expected `{}.{}`, but `{}.{}` is called. Stop replaying.",
+                call.module, call.name, module, name
+            );
+            self.replay.clear();
+            return None;
+        }
+        let call = self.replay.pop_front().unwrap();
+        if call.args != args {
+            warn!(
+                "The args of `{}.{}` differ from the recorded ones.",
+                module, name
+            );
+        }
+        Some(call.result)
+    }
+
+    fn record(&mut self, module: &str, name: &str, args: Vec<u8>, result: &[u8]) {
+        if self.record && !self.suspended {
+            self.calls.push(PluginCall {
+                module: module.to_string(),
+                name: name.to_string(),
+                args,
+                result: result.to_vec(),
+            });
+        }
+    }
 }
 
 /// The load status of [`Runtime`].
@@ -134,27 +734,100 @@ pub enum LoadStatus {
     CreateEngine,
     /// Loading the plugin.
     LoadPlugin(String, usize, usize),
+    /// The plugin failed to load, with the error message.
+    PluginFailed(String, String),
+}
+
+/// The error when the loading is cancelled by the flag.
+///
+/// It could be checked with [`anyhow::Error::is`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The loading is cancelled.")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// Fails with [`Cancelled`] if the flag is set.
+pub(crate) fn check_cancel(cancel: &AtomicBool) -> Result<()> {
+    if cancel.load(Ordering::Acquire) {
+        Err(Cancelled.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// The flag which is never set, for the loading without cancellation.
+pub(crate) static NEVER_CANCEL: AtomicBool = AtomicBool::new(false);
+
+enum PluginSource {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
 }
 
 #[derive(Default, Clone, WasmerEnv)]
 struct RuntimeInstanceData {
     #[wasmer(export)]
     memory: LazyInit<Memory>,
+    target: String,
+    log_levels: LogLevels,
+    events: EventHandler,
+}
+
+/// The log target of a plugin, e.g., `ayaka::plugin::random`.
+///
+/// The target provided by the plugin is appended as a sub-target,
+/// unless it is empty or the same as the plugin name.
+fn plugin_log_target(name: &str, target: &str) -> String {
+    if target.is_empty() || target == name {
+        format!("ayaka::plugin::{}", name)
+    } else {
+        format!("ayaka::plugin::{}::{}", name, target)
+    }
 }
 
 impl Runtime {
-    fn imports(store: &Store) -> Result<Box<dyn NamedResolver + Send + Sync>> {
+    fn imports(
+        store: &Store,
+        name: &str,
+        log_levels: &LogLevels,
+        events: &EventHandler,
+        capabilities: &[Capability],
+    ) -> PluginResult<Box<dyn NamedResolver + Send + Sync>> {
         let log_func = Function::new_native_with_env(
             store,
-            RuntimeInstanceData::default(),
+            RuntimeInstanceData {
+                target: name.to_string(),
+                log_levels: log_levels.clone(),
+                ..Default::default()
+            },
             |env_data: &RuntimeInstanceData, len: i32, data: i32| {
                 let memory = unsafe { env_data.memory.get_unchecked() };
-                let data = unsafe { mem_slice(memory, data, len) };
+                let data = match unsafe { mem_slice(memory, data, len) } {
+                    Ok(data) => data,
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                };
                 let data: Record = rmp_serde::from_slice(data).unwrap();
+                let max_level = env_data
+                    .log_levels
+                    .read()
+                    .unwrap()
+                    .get(&env_data.target)
+                    .copied();
+                if max_level.map(|max| data.level > max).unwrap_or(false) {
+                    return;
+                }
                 log::logger().log(
                     &log::Record::builder()
                         .level(data.level)
-                        .target(&data.target)
+                        .target(&plugin_log_target(&env_data.target, &data.target))
                         .args(format_args!("{}", data.msg))
                         .module_path(data.module_path.as_deref())
                         .file(data.file.as_deref())
@@ -164,40 +837,179 @@ impl Runtime {
             },
         );
         let log_flush_func = Function::new_native(store, || log::logger().flush());
+        let emit_event_func = Function::new_native_with_env(
+            store,
+            RuntimeInstanceData {
+                target: name.to_string(),
+                events: events.clone(),
+                ..Default::default()
+            },
+            |env_data: &RuntimeInstanceData, len: i32, data: i32| {
+                let memory = unsafe { env_data.memory.get_unchecked() };
+                let data = match unsafe { mem_slice(memory, data, len) } {
+                    Ok(data) => data,
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                };
+                let (name, value) = match rmp_serde::from_slice::<(String, RawValue)>(data) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        log::error!("Cannot decode event from \"{}\": {}", env_data.target, e);
+                        return;
+                    }
+                };
+                // Clone the handler out, in case it emits events itself.
+                let handler = env_data.events.read().unwrap().clone();
+                if let Some(handler) = handler {
+                    handler(PluginEvent {
+                        plugin: env_data.target.clone(),
+                        name,
+                        value,
+                    });
+                }
+            },
+        );
         let import_object = imports! {
             "log" => {
                 "__log" => log_func,
                 "__log_flush" => log_flush_func,
+            },
+            "event" => {
+                "__emit_event" => emit_event_func,
             }
         };
-        let wasi_env = WasiState::new("ayaka-runtime")
-            .preopen_dir("/")?
-            .finalize()?;
+        let mut wasi_state = WasiState::new("ayaka-runtime");
+        if capabilities.contains(&Capability::Fs) {
+            wasi_state.preopen_dir("/")?;
+        }
+        let wasi_env = wasi_state.finalize()?;
         let wasi_import = generate_import_object_from_env(store, wasi_env, WasiVersion::Latest);
         Ok(Box::new(import_object.chain_front(wasi_import)))
     }
 
+    /// Gets the capabilities required by the WASI functions the module imports.
+    ///
+    /// [`Capability::Random`] is not included, because it is not enforced.
+    fn required_capabilities(module: &Module) -> Vec<Capability> {
+        let mut capabilities = vec![];
+        for import in module.imports() {
+            if !import.module().starts_with("wasi") {
+                continue;
+            }
+            let capability = if import.name().starts_with("path_") {
+                Capability::Fs
+            } else if import.name().starts_with("sock_") {
+                Capability::Network
+            } else {
+                continue;
+            };
+            if !capabilities.contains(&capability) {
+                capabilities.push(capability);
+            }
+        }
+        capabilities
+    }
+
+    async fn load_plugin(
+        store: &Store,
+        name: &str,
+        log_levels: &LogLevels,
+        events: &EventHandler,
+        source: PluginSource,
+    ) -> PluginResult<(Host, PluginType, Vec<String>, Vec<Capability>)> {
+        let buf = match source {
+            PluginSource::Path(path) => tokio::fs::read(path).await?,
+            PluginSource::Bytes(buf) => buf,
+        };
+        let module = Module::from_binary(store, &buf)?;
+        // The capabilities are unknown before instantiating,
+        // so nothing is granted for the first instance.
+        let import_object = Self::imports(store, name, log_levels, events, &[])?;
+        let mut runtime = Host::new(&module, import_object.as_ref())?;
+        let capabilities = runtime.capabilities()?;
+        if let Some(c) = Self::required_capabilities(&module)
+            .into_iter()
+            .find(|c| !capabilities.contains(c))
+        {
+            return Err(PluginError::Capability(c));
+        }
+        if capabilities.contains(&Capability::Fs) {
+            let import_object = Self::imports(store, name, log_levels, events, &capabilities)?;
+            runtime = Host::new(&module, import_object.as_ref())?;
+        }
+        let plugin_type = runtime.plugin_type()?;
+        let deps = runtime.plugin_deps()?;
+        Ok((runtime, plugin_type, deps, capabilities))
+    }
+
+    /// Sorts the plugins so that the dependencies come before the dependents.
+    ///
+    /// The original order is kept if there's no dependency between plugins.
+    /// The dependencies not in `names` are ignored.
+    fn sort_deps(names: &[String], deps: &HashMap<String, Vec<String>>) -> Result<Vec<String>> {
+        let mut remaining = names.to_vec();
+        let mut sorted = Vec::with_capacity(names.len());
+        while !remaining.is_empty() {
+            let ready = remaining.iter().position(|name| {
+                deps.get(name)
+                    .map(|deps| {
+                        deps.iter()
+                            .all(|dep| !names.contains(dep) || sorted.contains(dep))
+                    })
+                    .unwrap_or(true)
+            });
+            match ready {
+                Some(index) => sorted.push(remaining.remove(index)),
+                None => bail!(
+                    "Cyclic dependency detected among plugins: {}",
+                    remaining.join(", ")
+                ),
+            }
+        }
+        Ok(sorted)
+    }
+
     /// Load plugins from specific directory and plugin names.
     ///
     /// The actual load folder will be `rel_to.join(dir)`.
     ///
-    /// If `names` is empty, all WASM files will be loaded.
+    /// If `names` is empty, all WASM files will be loaded, in the order of file names.
+    ///
+    /// The plugins are sorted by the dependencies declared by `plugin_deps`.
+    /// It fails if the dependencies are cyclic.
+    ///
+    /// A plugin failed to load is skipped and recorded in
+    /// [`Runtime::failed_modules`], instead of failing the whole runtime.
     #[stream(LoadStatus, lifetime = "'a")]
     pub async fn load<'a>(
         dir: impl AsRef<Path> + 'a,
         rel_to: impl AsRef<Path> + 'a,
         names: &'a [impl AsRef<str>],
+    ) -> Result<Self> {
+        let runtime = Self::load_with_cancel(dir, rel_to, names, &NEVER_CANCEL);
+        pin_mut!(runtime);
+        while let Some(status) = runtime.next().await {
+            yield status;
+        }
+        runtime.await
+    }
+
+    /// Load plugins as [`Runtime::load`], and stop if `cancel` is set.
+    ///
+    /// The flag is checked before each plugin.
+    /// It fails with [`Cancelled`], and the loaded plugins are dropped.
+    #[stream(LoadStatus, lifetime = "'a")]
+    pub async fn load_with_cancel<'a>(
+        dir: impl AsRef<Path> + 'a,
+        rel_to: impl AsRef<Path> + 'a,
+        names: &'a [impl AsRef<str>],
+        cancel: &'a AtomicBool,
     ) -> Result<Self> {
         let path = rel_to.as_ref().join(dir);
-        yield LoadStatus::CreateEngine;
-        let store = Store::default();
-        let import_object = Self::imports(&store)?;
-        let mut modules = HashMap::new();
-        let mut action_modules = vec![];
-        let mut text_modules = HashMap::new();
-        let mut game_modules = vec![];
         let paths = if names.is_empty() {
-            ReadDirStream::new(tokio::fs::read_dir(path).await?)
+            let mut paths = ReadDirStream::new(tokio::fs::read_dir(path).await?)
                 .try_filter_map(|f| async move {
                     let p = f.path();
                     if p.extension()
@@ -216,7 +1028,9 @@ impl Runtime {
                     }
                 })
                 .try_collect::<Vec<_>>()
-                .await?
+                .await?;
+            paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+            paths
         } else {
             names
                 .iter()
@@ -231,17 +1045,126 @@ impl Runtime {
                 })
                 .collect::<Vec<_>>()
         };
-        let total_len = paths.len();
-        for (i, (name, p)) in paths.into_iter().enumerate() {
+        let sources = paths
+            .into_iter()
+            .map(|(name, p)| (name, PluginSource::Path(p)))
+            .collect();
+        let runtime = Self::load_sources(sources, cancel);
+        pin_mut!(runtime);
+        while let Some(status) = runtime.next().await {
+            yield status;
+        }
+        runtime.await
+    }
+
+    /// Load plugins from the WASM binaries in memory.
+    ///
+    /// The plugins are loaded in the order of names,
+    /// and then sorted by the dependencies as [`Runtime::load`].
+    #[stream(LoadStatus)]
+    pub async fn load_from_bytes(plugins: HashMap<String, Vec<u8>>) -> Result<Self> {
+        let runtime = Self::load_from_bytes_with_cancel(plugins, &NEVER_CANCEL);
+        pin_mut!(runtime);
+        while let Some(status) = runtime.next().await {
+            yield status;
+        }
+        runtime.await
+    }
+
+    /// Load plugins as [`Runtime::load_from_bytes`], and stop if `cancel` is set.
+    ///
+    /// See [`Runtime::load_with_cancel`].
+    #[stream(LoadStatus, lifetime = "'a")]
+    pub async fn load_from_bytes_with_cancel<'a>(
+        plugins: HashMap<String, Vec<u8>>,
+        cancel: &'a AtomicBool,
+    ) -> Result<Self> {
+        let mut sources = plugins
+            .into_iter()
+            .map(|(name, buf)| (name, PluginSource::Bytes(buf)))
+            .collect::<Vec<_>>();
+        sources.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let runtime = Self::load_sources(sources, cancel);
+        pin_mut!(runtime);
+        while let Some(status) = runtime.next().await {
+            yield status;
+        }
+        runtime.await
+    }
+
+    #[stream(LoadStatus, lifetime = "'a")]
+    async fn load_sources<'a>(
+        sources: Vec<(String, PluginSource)>,
+        cancel: &'a AtomicBool,
+    ) -> Result<Self> {
+        yield LoadStatus::CreateEngine;
+        let store = Store::default();
+        let log_levels = LogLevels::default();
+        let events = EventHandler::default();
+        let mut modules = HashMap::new();
+        let mut action_modules = vec![];
+        let mut text_modules = HashMap::new();
+        let mut text_claims = HashMap::<String, Vec<String>>::new();
+        let mut text_arity = HashMap::new();
+        let mut game_modules = vec![];
+        let mut global_modules = HashMap::new();
+        let mut failed_modules = vec![];
+        let mut capabilities = HashMap::new();
+        let mut prop_modules = HashMap::<String, Vec<String>>::new();
+        let total_len = sources.len();
+        let mut loaded = HashMap::new();
+        let mut loaded_names = vec![];
+        let mut deps = HashMap::new();
+        for (i, (name, source)) in sources.into_iter().enumerate() {
+            // The loaded plugins are dropped if cancelled.
+            check_cancel(cancel)?;
             yield LoadStatus::LoadPlugin(name.clone(), i, total_len);
-            let buf = tokio::fs::read(p).await?;
-            let module = Module::from_binary(&store, &buf)?;
-            let runtime = Host::new(&module, &import_object)?;
-            let plugin_type = runtime.plugin_type()?;
-            if plugin_type.action {
+            match Self::load_plugin(&store, &name, &log_levels, &events, source).await {
+                Ok((runtime, plugin_type, plugin_deps, plugin_capabilities)) => {
+                    loaded.insert(name.clone(), (runtime, plugin_type));
+                    loaded_names.push(name.clone());
+                    deps.insert(name.clone(), plugin_deps);
+                    capabilities.insert(name, plugin_capabilities);
+                }
+                Err(e) => {
+                    warn!("Cannot load plugin \"{}\": {}", name, e);
+                    yield LoadStatus::PluginFailed(name.clone(), e.to_string());
+                    failed_modules.push((name, e));
+                }
+            }
+        }
+        check_cancel(cancel)?;
+        for (name, plugin_deps) in &deps {
+            for dep in plugin_deps {
+                if !loaded.contains_key(dep) {
+                    warn!(
+                        "Plugin \"{}\" depends on \"{}\", which is not loaded",
+                        name, dep
+                    );
+                }
+            }
+        }
+        for name in Self::sort_deps(&loaded_names, &deps)? {
+            let (runtime, plugin_type) = loaded.remove(&name).unwrap();
+            let exports = runtime.exports();
+            let check_export = |func: &str| {
+                let res = exports.iter().any(|e| e == func);
+                if !res {
+                    warn!("Plugin \"{}\" doesn't export `{}`", name, func);
+                }
+                res
+            };
+            if plugin_type.action && check_export("process_action") {
                 action_modules.push(name.clone());
             }
             for cmd in plugin_type.text {
+                if !check_export(&cmd) {
+                    continue;
+                }
+                text_claims
+                    .entry(cmd.clone())
+                    .or_default()
+                    .push(name.clone());
                 let res = text_modules.insert(cmd.clone(), name.clone());
                 if let Some(old_module) = res {
                     warn!(
@@ -249,17 +1172,1064 @@ impl Runtime {
                         cmd, name, old_module
                     );
                 }
+                match runtime.text_command_arity(&cmd) {
+                    Ok(Some(arity)) => {
+                        text_arity.insert(cmd, arity);
+                    }
+                    Ok(None) => {
+                        text_arity.remove(&cmd);
+                    }
+                    Err(e) => {
+                        warn!("Cannot get arity of command `{}`: {}", cmd, e);
+                        text_arity.remove(&cmd);
+                    }
+                }
             }
-            if plugin_type.game {
+            if plugin_type.game && check_export("process_game") {
                 game_modules.push(name.clone());
             }
+            let methods = runtime.global_methods().unwrap_or_else(|e| {
+                warn!("Cannot get global methods of \"{}\": {}", name, e);
+                vec![]
+            });
+            for method in methods {
+                if !check_export(&method) {
+                    continue;
+                }
+                let res = global_modules.insert(method.clone(), name.clone());
+                if let Some(old_module) = res {
+                    warn!(
+                        "Method `{}` is overrided by \"{}\" over \"{}\"",
+                        method, name, old_module
+                    );
+                }
+            }
+            let keys = runtime.prop_keys().unwrap_or_else(|e| {
+                warn!("Cannot get prop keys of \"{}\": {}", name, e);
+                vec![]
+            });
+            if !keys.is_empty() && check_export("on_prop_changed") {
+                for key in keys {
+                    prop_modules.entry(key).or_default().push(name.clone());
+                }
+            }
             modules.insert(name, runtime);
         }
+        let mut text_conflicts = text_claims
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .collect::<Vec<_>>();
+        text_conflicts.sort_by(|(a, _), (b, _)| a.cmp(b));
         Ok(Self {
             modules,
             action_modules,
             text_modules,
+            text_arity,
             game_modules,
+            global_modules,
+            failed_modules,
+            capabilities,
+            prop_modules,
+            text_conflicts,
+            calls: Mutex::default(),
+            log_levels,
+            events,
         })
     }
+
+    /// The text commands claimed by more than one plugin,
+    /// with the plugins in load order.
+    ///
+    /// The last plugin takes effect.
+    pub fn text_command_conflicts(&self) -> &[(String, Vec<String>)] {
+        &self.text_conflicts
+    }
+
+    fn call_logged_bytes(&self, module: &str, name: &str, data: Vec<u8>) -> PluginResult<Vec<u8>> {
+        let replayed = self.calls.lock().unwrap().replay(module, name, &data);
+        let res_data = match replayed {
+            Some(res_data) => res_data,
+            None => self.modules[module].call_bytes(name, &data)?,
+        };
+        self.calls
+            .lock()
+            .unwrap()
+            .record(module, name, data, &res_data);
+        Ok(res_data)
+    }
+
+    /// Calls a script plugin method by name, recorded or replayed.
+    ///
+    /// It panics if the plugin is not loaded.
+    pub fn dispatch_method(
+        &self,
+        module: &str,
+        name: &str,
+        args: &[RawValue],
+    ) -> PluginResult<RawValue> {
+        let data = rmp_serde::to_vec(&(args,))?;
+        let replayed = self.calls.lock().unwrap().replay_line(module, name);
+        let res_data = match replayed {
+            Some(res_data) => res_data?,
+            None => {
+                let res_data = self.call_logged_bytes(module, name, data.clone())?;
+                self.calls
+                    .lock()
+                    .unwrap()
+                    .record_line(module, name, data, &res_data);
+                res_data
+            }
+        };
+        Ok(rmp_serde::from_slice(&res_data)?)
+    }
+
+    /// Processes [`Action`] in action plugin, recorded or replayed.
+    ///
+    /// It panics if the plugin is not loaded.
+    /// The plugins returning [`Action`] directly are also accepted.
+    pub fn process_action(
+        &self,
+        module: &str,
+        ctx: ActionProcessContextRef,
+    ) -> PluginResult<ActionProcessResult> {
+        let data = rmp_serde::to_vec(&(ctx,))?;
+        decode_action_result(&self.call_logged_bytes(module, "process_action", data)?)
+    }
+
+    /// Enables or disables recording the calls of
+    /// [`Runtime::dispatch_method`] and [`Runtime::process_action`].
+    ///
+    /// It is disabled by default to avoid the overhead in normal play.
+    pub fn set_record_calls(&self, record_calls: bool) {
+        self.calls.lock().unwrap().record = record_calls;
+    }
+
+    /// Takes the recorded calls since the last take.
+    pub fn take_calls(&self) -> Vec<PluginCall> {
+        std::mem::take(&mut self.calls.lock().unwrap().calls)
+    }
+
+    /// Feeds the recorded calls back, in order, instead of invoking the plugins.
+    ///
+    /// The replay stops when a call differs from the recorded one by the plugin or method name,
+    /// and a warning is logged if only the args differ.
+    pub fn replay_calls(&self, calls: impl IntoIterator<Item = PluginCall>) {
+        self.calls.lock().unwrap().replay = calls.into_iter().collect();
+    }
+
+    /// Starts rendering a new line, and clears the script method calls of the last line.
+    pub(crate) fn begin_line(&self) {
+        let mut calls = self.calls.lock().unwrap();
+        if !calls.suspended {
+            calls.line.clear();
+        }
+    }
+
+    /// Takes the script method calls of the current line since [`Runtime::begin_line`].
+    pub(crate) fn take_line_calls(&self) -> Vec<PluginCall> {
+        std::mem::take(&mut self.calls.lock().unwrap().line)
+    }
+
+    /// Renders a line again in `f`, without side effects.
+    ///
+    /// The script method calls are fed back from `line` instead of invoking the plugins,
+    /// and the other calls are neither recorded nor replayed.
+    /// It returns [`None`] if a script method call isn't in `line`,
+    /// because the plugin would be invoked.
+    pub(crate) fn rerender_line<T>(&self, line: &[PluginCall], f: impl FnOnce() -> T) -> Option<T> {
+        {
+            let mut calls = self.calls.lock().unwrap();
+            calls.suspended = true;
+            calls.line_replay = Some(line.iter().cloned().collect());
+            calls.line_diverged = false;
+        }
+        let res = f();
+        let mut calls = self.calls.lock().unwrap();
+        calls.suspended = false;
+        calls.line_replay = None;
+        if std::mem::take(&mut calls.line_diverged) {
+            None
+        } else {
+            Some(res)
+        }
+    }
+
+    /// Sets the max log levels of the plugins by name.
+    ///
+    /// The log records of a plugin above its level are dropped before forwarded to the logger.
+    /// The records of the plugins not in the map are always forwarded.
+    pub fn set_log_levels(&self, levels: HashMap<String, LevelFilter>) {
+        *self.log_levels.write().unwrap() = levels;
+    }
+
+    /// Sets the handler of the [`PluginEvent`]s emitted by the plugins.
+    ///
+    /// The events are dropped if no handler is set.
+    pub fn set_event_handler(&self, handler: impl Fn(PluginEvent) + Send + Sync + 'static) {
+        *self.events.write().unwrap() = Some(Arc::new(handler));
+    }
+
+    /// Gets the [`PluginStats`] of all plugins by name.
+    pub fn stats(&self) -> HashMap<String, PluginStats> {
+        self.modules
+            .iter()
+            .map(|(name, module)| (name.clone(), module.stats()))
+            .collect()
+    }
+
+    /// Seeds the random engines of all plugins exporting `seed`.
+    pub fn seed(&self, value: u64) -> Result<()> {
+        for (name, module) in &self.modules {
+            module
+                .seed(value)
+                .map_err(|e| anyhow!("Cannot seed plugin {}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// Seeds the random engines of all plugins exporting `seed` by entropy,
+    /// so that they don't continue a seeded sequence.
+    pub fn seed_from_entropy(&self) -> Result<()> {
+        use std::hash::{BuildHasher, Hasher};
+
+        // The keys of `RandomState` are random, and differ every time.
+        self.seed(
+            std::collections::hash_map::RandomState::new()
+                .build_hasher()
+                .finish(),
+        )
+    }
+
+    /// Notifies the plugins subscribing to the game prop that it is changed.
+    ///
+    /// The failures of the plugins are logged and don't stop the others.
+    pub fn prop_changed(&self, key: &str, value: &str) {
+        for name in self.prop_modules.get(key).into_iter().flatten() {
+            if let Err(e) = self.modules[name].on_prop_changed(key, value) {
+                warn!("Cannot notify plugin {} of prop `{}`: {}", name, key, e);
+            }
+        }
+    }
+
+    /// Shuts down the runtime, calling `on_shutdown` of all plugins exporting it,
+    /// and flushes the logger.
+    ///
+    /// The failures of the plugins are logged and don't stop the others.
+    pub fn shutdown(self) {
+        for (name, module) in &self.modules {
+            if let Err(e) = module.on_shutdown() {
+                warn!("Cannot shut down plugin {}: {}", name, e);
+            }
+        }
+        log::logger().flush();
+    }
+}
+
+/// Decodes the result of `process_action`.
+///
+/// The plugins built before [`ActionProcessResult`] was introduced return [`Action`],
+/// which is decoded as a non-terminal result.
+fn decode_action_result(data: &[u8]) -> PluginResult<ActionProcessResult> {
+    rmp_serde::from_slice::<ActionProcessResult>(data).or_else(|e| {
+        rmp_serde::from_slice::<Action>(data)
+            .map(ActionProcessResult::from)
+            .map_err(|_| PluginError::from(e))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{plugin::Runtime, RawValue};
+    use ayaka_bindings_types::*;
+    use std::collections::HashMap;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn decode_action_result() {
+        let mut action = Action::default();
+        action.line.push_back_chars("Hello");
+        let res = super::decode_action_result(
+            &rmp_serde::to_vec(&ActionProcessResult {
+                action: action.clone(),
+                terminal: true,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(res.terminal);
+        assert_eq!(*res.action.line, *action.line);
+
+        let res = super::decode_action_result(&rmp_serde::to_vec(&action).unwrap()).unwrap();
+        assert!(!res.terminal);
+        assert_eq!(*res.action.line, *action.line);
+
+        assert!(super::decode_action_result(&rmp_serde::to_vec(&1).unwrap()).is_err());
+    }
+
+    #[test]
+    fn mem_bounds() {
+        use super::{mem_slice, mem_slice_mut, PluginError};
+        use wasmer::{Memory, MemoryType, Store};
+
+        let store = Store::default();
+        let memory = Memory::new(&store, MemoryType::new(1, None, false)).unwrap();
+        let size = memory.data_size() as i32;
+        unsafe {
+            assert_eq!(mem_slice(&memory, 0, size).unwrap().len(), size as usize);
+            assert_eq!(mem_slice(&memory, size, 0).unwrap().len(), 0);
+            assert!(matches!(
+                mem_slice(&memory, size - 1, 2),
+                Err(PluginError::MemoryAccess { start, end, size: s })
+                    if start == size as usize - 1 && end == size as usize + 1 && s == size as u64
+            ));
+            assert!(mem_slice(&memory, -1, 1).is_err());
+            assert!(mem_slice_mut(&memory, 1, -1).is_err());
+        }
+    }
+
+    #[test]
+    fn emit_event() {
+        use super::{mem_slice_mut, EventHandler, PluginEvent};
+        use std::sync::{Arc, Mutex};
+        use wasmer::{Instance, Module, Store};
+
+        let store = Store::default();
+        let module = Module::new(
+            &store,
+            r#"(module
+                (import "event" "__emit_event" (func $emit (param i32 i32)))
+                (memory (export "memory") 1)
+                (func (export "emit") (param i32 i32)
+                    local.get 0
+                    local.get 1
+                    call $emit))"#,
+        )
+        .unwrap();
+        let events = EventHandler::default();
+        let import_object =
+            Runtime::imports(&store, "toast", &Default::default(), &events, &[]).unwrap();
+        let instance = Instance::new(&module, import_object.as_ref()).unwrap();
+        let memory = instance.exports.get_memory("memory").unwrap();
+        let emit = instance
+            .exports
+            .get_native_function::<(i32, i32), ()>("emit")
+            .unwrap();
+
+        let data = rmp_serde::to_vec(&("show", RawValue::Str("Hello".to_string()))).unwrap();
+        unsafe { mem_slice_mut(memory, 0, data.len() as i32).unwrap() }.copy_from_slice(&data);
+        // No handler, the event is dropped.
+        emit.call(data.len() as i32, 0).unwrap();
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let received_clone = received.clone();
+        *events.write().unwrap() = Some(Arc::new(move |e: PluginEvent| {
+            received_clone.lock().unwrap().push(e)
+        }));
+        emit.call(data.len() as i32, 0).unwrap();
+        assert_eq!(
+            *received.lock().unwrap(),
+            [PluginEvent {
+                plugin: "toast".to_string(),
+                name: "show".to_string(),
+                value: RawValue::Str("Hello".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn plugin_log_target() {
+        use super::plugin_log_target;
+
+        assert_eq!(plugin_log_target("random", ""), "ayaka::plugin::random");
+        assert_eq!(
+            plugin_log_target("random", "random"),
+            "ayaka::plugin::random"
+        );
+        assert_eq!(
+            plugin_log_target("random", "dice"),
+            "ayaka::plugin::random::dice"
+        );
+    }
+
+    #[test]
+    fn sort_deps() {
+        let deps = HashMap::from([
+            ("markdown".to_string(), names(&["ruby"])),
+            ("ruby".to_string(), names(&["format", "unknown"])),
+        ]);
+        assert_eq!(
+            Runtime::sort_deps(&names(&["markdown", "media", "ruby", "format"]), &deps).unwrap(),
+            names(&["media", "format", "ruby", "markdown"])
+        );
+        assert_eq!(
+            Runtime::sort_deps(&names(&["format", "media"]), &deps).unwrap(),
+            names(&["format", "media"])
+        );
+    }
+
+    #[test]
+    fn sort_deps_cycle() {
+        let deps = HashMap::from([
+            ("a".to_string(), names(&["b"])),
+            ("b".to_string(), names(&["a"])),
+        ]);
+        assert!(Runtime::sort_deps(&names(&["a", "b", "c"]), &deps).is_err());
+    }
+
+    #[tokio::test]
+    async fn text_arity() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["basictex", "live2d", "ruby"],
+        )
+        .await
+        .unwrap();
+        assert_eq!(runtime.text_arity.get("ruby"), Some(&(2, 2)));
+        assert_eq!(runtime.text_arity.get("par"), Some(&(0, 0)));
+        assert_eq!(runtime.text_arity.get("show"), None);
+    }
+
+    #[tokio::test]
+    async fn load_from_bytes() {
+        let buf = std::fs::read(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../examples/plugins/format.wasm"),
+        )
+        .unwrap();
+        let runtime = Runtime::load_from_bytes(HashMap::from([
+            ("format".to_string(), buf),
+            ("broken".to_string(), vec![0, 1, 2]),
+        ]))
+        .await
+        .unwrap();
+        assert!(runtime.modules.contains_key("format"));
+        assert_eq!(runtime.failed_modules.len(), 1);
+        assert_eq!(runtime.failed_modules[0].0, "broken");
+    }
+
+    #[tokio::test]
+    async fn seed() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random"],
+        )
+        .await
+        .unwrap();
+        let random = &runtime.modules["random"];
+        let rolls = || {
+            (0..10)
+                .map(|_| {
+                    random
+                        .dispatch_method("rnd", &[RawValue::Num(100)])
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        runtime.seed(42).unwrap();
+        let first = rolls();
+        runtime.seed(42).unwrap();
+        assert_eq!(rolls(), first);
+    }
+
+    #[tokio::test]
+    async fn record_calls() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random"],
+        )
+        .await
+        .unwrap();
+        let rolls = || {
+            (0..10)
+                .map(|_| {
+                    runtime
+                        .dispatch_method("random", "rnd", &[RawValue::Num(100)])
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        rolls();
+        assert!(runtime.take_calls().is_empty());
+
+        runtime.set_record_calls(true);
+        runtime.seed(42).unwrap();
+        let first = rolls();
+        let calls = runtime.take_calls();
+        assert_eq!(calls.len(), 10);
+        assert!(calls
+            .iter()
+            .all(|c| c.module == "random" && c.name == "rnd"));
+
+        runtime.seed(114514).unwrap();
+        runtime.replay_calls(calls.clone());
+        assert_eq!(rolls(), first);
+        assert_eq!(runtime.take_calls(), calls);
+
+        runtime.replay_calls(calls);
+        runtime
+            .dispatch_method("random", "roll", &[RawValue::Str("2d6".to_string())])
+            .unwrap();
+        assert_ne!(rolls(), first);
+    }
+
+    #[tokio::test]
+    async fn capabilities() {
+        let store = wasmer::Store::default();
+        let module = |name: &str| {
+            let buf = std::fs::read(
+                std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("../../examples/plugins")
+                    .join(name)
+                    .with_extension("wasm"),
+            )
+            .unwrap();
+            wasmer::Module::from_binary(&store, &buf).unwrap()
+        };
+        assert_eq!(
+            Runtime::required_capabilities(&module("media")),
+            [Capability::Fs]
+        );
+        assert!(Runtime::required_capabilities(&module("random")).is_empty());
+
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["format"],
+        )
+        .await
+        .unwrap();
+        assert!(runtime.capabilities["format"].is_empty());
+        assert_eq!(
+            super::PluginError::Capability(Capability::Fs).to_string(),
+            "Capability error: the plugin requires Fs, but doesn't declare it."
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel() {
+        use futures_util::StreamExt;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let cancel = AtomicBool::new(false);
+        let runtime = Runtime::load_with_cancel(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random", "format"],
+            &cancel,
+        );
+        futures_util::pin_mut!(runtime);
+        // Cancel after the first plugin starts loading.
+        while let Some(status) = runtime.next().await {
+            if let super::LoadStatus::LoadPlugin(..) = status {
+                cancel.store(true, Ordering::Release);
+            }
+        }
+        let err = runtime.await.err().unwrap();
+        assert!(err.is::<super::Cancelled>());
+
+        let cancel = AtomicBool::new(false);
+        let runtime = Runtime::load_with_cancel(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random"],
+            &cancel,
+        )
+        .await
+        .unwrap();
+        assert!(runtime.modules.contains_key("random"));
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random"],
+        )
+        .await
+        .unwrap();
+        assert!(!runtime.modules["random"].has_export("on_shutdown"));
+        runtime.modules["random"].on_shutdown().unwrap();
+        runtime.shutdown();
+    }
+
+    #[tokio::test]
+    async fn prop_changed() {
+        // The input is always written at 1024, and the outputs are in the data segment:
+        // `PluginType::default()`, `["volume"]` and `()`.
+        let buf = wasmer::wat2wasm(
+            br#"(module
+                (memory (export "memory") 1)
+                (global $count (export "count") (mut i32) (i32.const 0))
+                (global $len (export "len") (mut i32) (i32.const 0))
+                (data (i32.const 0) "\93\c2\90\c2")
+                (data (i32.const 4) "\91\a6volume")
+                (data (i32.const 12) "\c0")
+                (func (export "__abi_alloc") (param i32) (result i32)
+                    i32.const 1024)
+                (func (export "__abi_free") (param i32 i32))
+                (func (export "plugin_type") (param i32 i32) (result i64)
+                    i64.const 0x0000000400000000)
+                (func (export "prop_keys") (param i32 i32) (result i64)
+                    i64.const 0x0000000800000004)
+                (func (export "on_prop_changed") (param $len i32) (param i32) (result i64)
+                    global.get $count
+                    i32.const 1
+                    i32.add
+                    global.set $count
+                    local.get $len
+                    global.set $len
+                    i64.const 0x000000010000000C))"#,
+        )
+        .unwrap()
+        .to_vec();
+        let runtime = Runtime::load_from_bytes(HashMap::from([("watch".to_string(), buf)]))
+            .await
+            .unwrap();
+        assert_eq!(runtime.prop_modules["volume"], names(&["watch"]));
+
+        let watch = &runtime.modules["watch"];
+        let global = |name: &str| {
+            watch
+                .instance
+                .exports
+                .get_global(name)
+                .unwrap()
+                .get()
+                .unwrap_i32()
+        };
+        runtime.prop_changed("speed", "2");
+        assert_eq!(global("count"), 0);
+
+        runtime.prop_changed("volume", "0.5");
+        assert_eq!(global("count"), 1);
+        let len = global("len") as usize;
+        let (key, value) = watch
+            .with_memory(|m| rmp_serde::from_slice::<(String, String)>(&m[1024..1024 + len]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(key, "volume");
+        assert_eq!(value, "0.5");
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random"],
+        )
+        .await
+        .unwrap();
+        let before = runtime.stats()["random"];
+        assert!(before.memory_bytes > 0);
+        for _ in 0..3 {
+            runtime
+                .dispatch_method("random", "rnd", &[RawValue::Num(100)])
+                .unwrap();
+        }
+        let after = runtime.stats()["random"];
+        assert_eq!(after.call_count, before.call_count + 3);
+        assert!(after.total_call_time > before.total_call_time);
+    }
+
+    #[tokio::test]
+    async fn call_bytes() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random"],
+        )
+        .await
+        .unwrap();
+        let random = &runtime.modules["random"];
+        let data = rmp_serde::to_vec(&()).unwrap();
+        let res = random.call_bytes("global_methods", &data).unwrap();
+        let methods: Vec<String> = rmp_serde::from_slice(&res).unwrap();
+        assert_eq!(methods, random.global_methods().unwrap());
+    }
+
+    #[tokio::test]
+    async fn rnd_bounds() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random"],
+        )
+        .await
+        .unwrap();
+        let rnd = |args: &[i64]| {
+            let args = args.iter().map(|i| RawValue::Num(*i)).collect::<Vec<_>>();
+            runtime.modules["random"]
+                .dispatch_method("rnd", &args)
+                .unwrap()
+                .get_num()
+        };
+        assert_eq!(rnd(&[5, 5]), 5);
+        assert_eq!(rnd(&[0]), 0);
+        for _ in 0..10 {
+            assert!((0..10).contains(&rnd(&[10, 0])));
+            assert!((-10..0).contains(&rnd(&[-10])));
+        }
+    }
+
+    #[tokio::test]
+    async fn roll() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["random"],
+        )
+        .await
+        .unwrap();
+        let roll = |notation: &str| {
+            runtime.modules["random"]
+                .dispatch_method("roll", &[RawValue::Str(notation.to_string())])
+                .unwrap()
+        };
+        for _ in 0..10 {
+            assert!((1..=20).contains(&roll("d20").get_num()));
+            assert!((3..=13).contains(&roll("2d6+1").get_num()));
+            assert!((0..=9).contains(&roll("3d4 - 2 - 1d1").get_num()));
+        }
+        assert_eq!(roll("4").get_num(), 4);
+        assert_eq!(roll("-1d1").get_num(), -1);
+        for notation in ["", "d", "2d", "0d6", "d0", "2d6+", "xd6", "2d6++1"] {
+            assert_eq!(roll(notation), RawValue::Unit, "{}", notation);
+        }
+    }
+
+    async fn ruby(frontend: FrontendType, args: &[&str]) -> Vec<ActionLine> {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["ruby"],
+        )
+        .await
+        .unwrap();
+        let args = args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let res = runtime.modules["ruby"]
+            .dispatch_command(
+                "ruby",
+                &args,
+                TextProcessContextRef {
+                    root_path: std::path::Path::new(""),
+                    game_props: &HashMap::new(),
+                    frontend,
+                },
+            )
+            .unwrap();
+        res.line.into_iter().collect()
+    }
+
+    #[tokio::test]
+    async fn ruby_command() {
+        assert_eq!(
+            ruby(FrontendType::Text, &["漢字", "かんじ"]).await,
+            [ActionLine::chars("漢字(かんじ)")]
+        );
+        assert_eq!(
+            ruby(FrontendType::Html, &["漢字", "かんじ"]).await,
+            [
+                ActionLine::block("<ruby>"),
+                ActionLine::chars("漢字"),
+                ActionLine::block("<rp>(</rp><rt>"),
+                ActionLine::chars("かんじ"),
+                ActionLine::block("</rt><rp>)</rp></ruby>"),
+            ]
+        );
+        // The malformed commands don't fail the action.
+        assert_eq!(
+            ruby(FrontendType::Html, &["漢字"]).await,
+            [ActionLine::chars("漢字")]
+        );
+        assert!(ruby(FrontendType::Text, &[]).await.is_empty());
+        assert_eq!(
+            ruby(FrontendType::Text, &["漢字", "かんじ", "extra"]).await,
+            [ActionLine::chars("漢字(かんじ)")]
+        );
+    }
+
+    async fn markdown(frontend: FrontendType, line: ActionLines) -> Vec<ActionLine> {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["markdown"],
+        )
+        .await
+        .unwrap();
+        let action = Action {
+            line,
+            ..Default::default()
+        };
+        let res = runtime.modules["markdown"]
+            .process_action(ActionProcessContextRef {
+                root_path: std::path::Path::new(""),
+                game_props: &HashMap::new(),
+                frontend,
+                last_action: None,
+                action: &action,
+            })
+            .unwrap();
+        res.action.line.into_iter().collect()
+    }
+
+    #[tokio::test]
+    async fn markdown_blocks() {
+        let mut line = ActionLines::default();
+        line.push_back_chars("**Hello ");
+        line.push_back_block("<ruby>");
+        line.push_back_chars("world");
+        line.push_back_block("</ruby>");
+        line.push_back_chars("**");
+        assert_eq!(
+            markdown(FrontendType::Html, line).await,
+            [
+                ActionLine::block("<p><strong>"),
+                ActionLine::chars("Hello "),
+                ActionLine::block("<ruby>"),
+                ActionLine::chars("world"),
+                ActionLine::block("</ruby></strong></p>"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn lint_res() {
+        let runtime = Runtime::load(
+            "../../examples/plugins",
+            env!("CARGO_MANIFEST_DIR"),
+            &["lint"],
+        )
+        .await
+        .unwrap();
+        let res = ["foo", "bar", "baz", "ch_ayaka"]
+            .into_iter()
+            .map(|key| (key.to_string(), RawValue::Str(key.to_string())))
+            .collect();
+        let res = runtime.modules["lint"]
+            .process_game(GameProcessContextRef {
+                title: "",
+                author: "",
+                root_path: std::path::Path::new(""),
+                props: &HashMap::new(),
+                res: HashMap::from([("en".to_string(), &res)]),
+                texts: vec!["\\res{foo}", "\\exec{#bazz + #baz}", "#barbar"],
+            })
+            .unwrap();
+        assert_eq!(
+            res.diagnostics,
+            ["Resource \"bar\" of locale en is not referenced."]
+        );
+    }
+
+    #[tokio::test]
+    async fn markdown_text() {
+        let mut line = ActionLines::default();
+        line.push_back_chars(
+            "**Hello** *world*, see [docs](https://example.com).\n\n- a\n- b\n\n3. c\n4. d",
+        );
+        assert_eq!(
+            markdown(FrontendType::Text, line).await,
+            [ActionLine::chars(
+                "Hello world, see docs (https://example.com).\n- a\n- b\n3. c\n4. d"
+            )]
+        );
+    }
+
+    /// A minimal module following the plugin ABI, with a bump allocator which never frees.
+    const ABI_FIXTURE: &str = r#"(module
+        (memory (export "memory") 1)
+        (global $top (export "top") (mut i32) (i32.const 8))
+        (func (export "__abi_alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            global.get $top
+            local.set $ptr
+            local.get $ptr
+            local.get $len
+            i32.add
+            i32.const 7
+            i32.add
+            i32.const -8
+            i32.and
+            global.set $top
+            (block $done
+                (loop $grow
+                    global.get $top
+                    memory.size
+                    i32.const 16
+                    i32.shl
+                    i32.le_u
+                    br_if $done
+                    i32.const 1
+                    memory.grow
+                    drop
+                    br $grow))
+            local.get $ptr)
+        (func (export "__abi_free") (param i32 i32))
+        (func (export "echo") (param $len i32) (param $ptr i32) (result i64)
+            local.get $len
+            i64.extend_i32_u
+            i64.const 32
+            i64.shl
+            local.get $ptr
+            i64.extend_i32_u
+            i64.or)
+        (func (export "trap") (param i32 i32) (result i64)
+            unreachable)
+        (func (export "bad_sig") (param i32) (result i32)
+            local.get 0))"#;
+
+    fn abi_module() -> wasmer::Module {
+        let store = wasmer::Store::default();
+        wasmer::Module::new(&store, ABI_FIXTURE).unwrap()
+    }
+
+    fn abi_host(module: &wasmer::Module) -> super::Host {
+        super::Host::new(module, &wasmer::imports! {}).unwrap()
+    }
+
+    #[test]
+    fn abi_large_payload() {
+        let host = abi_host(&abi_module());
+        let data = (0..(4 << 20)).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        assert_eq!(host.call_bytes("echo", &data).unwrap(), data);
+        assert!(host.stats().memory_bytes > data.len() as u64);
+        assert_eq!(host.call_bytes("echo", &[]).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn abi_empty_payload() {
+        let host = abi_host(&abi_module());
+        let top = || {
+            host.instance
+                .exports
+                .get_global("top")
+                .unwrap()
+                .get()
+                .unwrap_i32()
+        };
+        let before = top();
+        assert_eq!(host.call_bytes("echo", &[]).unwrap(), Vec::<u8>::new());
+        assert_eq!(top(), before);
+        assert_eq!(host.call_bytes("echo", &[1]).unwrap(), [1]);
+        assert!(top() > before);
+    }
+
+    #[test]
+    fn abi_streaming() {
+        let host = abi_host(&abi_module());
+        let data = (0..(4 << 20) + 123)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let mut out = vec![];
+        host.call_streaming("echo", data.as_slice(), &mut out)
+            .unwrap();
+        assert_eq!(out, data);
+        // Only one chunk is held in the plugin memory.
+        assert!(host.stats().memory_bytes < data.len() as u64);
+        assert_eq!(host.stats().call_count, 1);
+
+        let mut out = vec![];
+        host.call_streaming("echo", std::io::empty(), &mut out)
+            .unwrap();
+        assert!(out.is_empty());
+        assert!(host
+            .call_streaming("trap", data.as_slice(), &mut out)
+            .is_err());
+    }
+
+    #[test]
+    fn abi_borrow_memory() {
+        use super::PluginError;
+
+        let host = abi_host(&abi_module());
+        let len = host
+            .call_bytes_with("echo", b"hello", |res| {
+                assert_eq!(res, b"hello");
+                assert!(matches!(
+                    host.call_bytes("echo", b"world"),
+                    Err(PluginError::Abi(_))
+                ));
+                res.len()
+            })
+            .unwrap();
+        assert_eq!(len, 5);
+        let size = host
+            .with_memory(|memory| {
+                assert!(memory.windows(5).any(|w| w == b"hello"));
+                assert!(matches!(
+                    host.call_bytes("echo", b"world"),
+                    Err(PluginError::Abi(_))
+                ));
+                memory.len() as u64
+            })
+            .unwrap();
+        assert_eq!(size, host.stats().memory_bytes);
+        // The borrows are released.
+        assert_eq!(host.call_bytes("echo", b"world").unwrap(), b"world");
+    }
+
+    #[test]
+    fn abi_errors() {
+        use super::PluginError;
+
+        let host = abi_host(&abi_module());
+        assert!(matches!(
+            host.call_bytes("nowhere", &[1, 2, 3]),
+            Err(PluginError::FuncNotFound(name)) if name == "nowhere"
+        ));
+        assert!(matches!(
+            host.call_bytes("bad_sig", &[1, 2, 3]),
+            Err(PluginError::Abi(_))
+        ));
+        assert!(matches!(
+            host.call_bytes("trap", &[1, 2, 3]),
+            Err(PluginError::Trap(_))
+        ));
+        // The instance is still usable after a trap.
+        assert_eq!(host.call_bytes("echo", &[1, 2, 3]).unwrap(), [1, 2, 3]);
+        assert_eq!(host.stats().call_count, 4);
+    }
+
+    #[test]
+    fn abi_free_trap() {
+        let store = wasmer::Store::default();
+        let module = wasmer::Module::new(
+            &store,
+            ABI_FIXTURE.replace(
+                r#"(func (export "__abi_free") (param i32 i32))"#,
+                r#"(func (export "__abi_free") (param i32 i32) unreachable)"#,
+            ),
+        )
+        .unwrap();
+        let host = abi_host(&module);
+        // The trap in freeing is logged, and the result is still returned.
+        assert_eq!(host.call_bytes("echo", &[1, 2, 3]).unwrap(), [1, 2, 3]);
+        assert_eq!(host.stats().call_count, 1);
+    }
+
+    #[test]
+    fn abi_concurrent() {
+        let module = abi_module();
+        std::thread::scope(|s| {
+            for t in 0..4u8 {
+                let module = &module;
+                s.spawn(move || {
+                    // An instance is not reentrant, so every thread has its own.
+                    let host = abi_host(module);
+                    for i in 0..100u8 {
+                        let data = vec![t, i, t ^ i];
+                        assert_eq!(host.call_bytes("echo", &data).unwrap(), data);
+                    }
+                });
+            }
+        });
+    }
 }
@@ -4,27 +4,65 @@ use crate::{plugin::Runtime, *};
 use ayaka_script_types::*;
 use fallback::Fallback;
 use log::{error, warn};
+use std::fmt::Display;
 
 /// The variable table in scripts.
 pub struct VarTable<'a> {
     /// The plugin runtime.
     pub runtime: &'a Runtime,
-    /// The resource map.
-    pub res: Fallback<&'a VarMap>,
+    /// The current locale, with the base language as fallback.
+    pub locale: Fallback<&'a Locale>,
+    /// The resource map, merged with the base language.
+    pub res: &'a VarMap,
     /// The context variables.
     pub locals: &'a mut VarMap,
     /// The locale variables.
     pub vars: VarMap,
+    /// The max iterations of a `while` loop.
+    /// The loop breaks with a warning when it is exceeded.
+    pub loop_limit: usize,
+    evaluated: usize,
+    progress: Option<(usize, Box<dyn FnMut(usize) + 'a>)>,
 }
 
+/// The default value of [`VarTable::loop_limit`].
+pub const DEFAULT_LOOP_LIMIT: usize = 10_000;
+
 impl<'a> VarTable<'a> {
     /// Creates a new [`VarTable`].
-    pub fn new(runtime: &'a Runtime, res: Fallback<&'a VarMap>, locals: &'a mut VarMap) -> Self {
+    pub fn new(
+        runtime: &'a Runtime,
+        locale: Fallback<&'a Locale>,
+        res: &'a VarMap,
+        locals: &'a mut VarMap,
+    ) -> Self {
         Self {
             runtime,
+            locale,
             res,
             locals,
             vars: VarMap::default(),
+            loop_limit: DEFAULT_LOOP_LIMIT,
+            evaluated: 0,
+            progress: None,
+        }
+    }
+
+    /// Sets a hook invoked every `interval` evaluated expressions,
+    /// with the count of the evaluated expressions.
+    ///
+    /// It could be used to report that a long script is still running.
+    /// The hook doesn't affect the evaluation.
+    pub fn set_progress(&mut self, interval: usize, hook: impl FnMut(usize) + 'a) {
+        self.progress = Some((interval.max(1), Box::new(hook)));
+    }
+
+    fn tick(&mut self) {
+        self.evaluated += 1;
+        if let Some((interval, hook)) = &mut self.progress {
+            if self.evaluated % *interval == 0 {
+                hook(self.evaluated);
+            }
         }
     }
 
@@ -68,6 +106,7 @@ impl Callable for Program {
 
 impl Callable for Expr {
     fn call(&self, ctx: &mut VarTable) -> RawValue {
+        ctx.tick();
         match self {
             Self::Ref(r) => r.call(ctx),
             Self::Const(c) => c.clone(),
@@ -79,6 +118,8 @@ impl Callable for Expr {
                     RawValue::Bool(b) => RawValue::Bool(!b),
                     RawValue::Num(i) => RawValue::Num(!i),
                     RawValue::Str(_) => RawValue::Str(String::new()),
+                    // Like the strings, it is the empty value of the type.
+                    RawValue::Array(_) => RawValue::Array(vec![]),
                 },
             },
             Self::Binary(lhs, op, rhs) => match op {
@@ -107,6 +148,7 @@ fn bin_val(ctx: &mut VarTable, lhs: &Expr, op: &ValBinaryOp, rhs: &Expr) -> RawV
         ValueType::Bool => bin_bool_val(lhs.get_bool(), op, rhs.get_bool()),
         ValueType::Num => RawValue::Num(bin_num_val(lhs.get_num(), op, rhs.get_num())),
         ValueType::Str => bin_str_val(lhs, op, rhs),
+        ValueType::Array => bin_array_val(lhs, op, rhs),
     }
 }
 
@@ -156,6 +198,20 @@ fn bin_str_val(lhs: RawValue, op: &ValBinaryOp, rhs: RawValue) -> RawValue {
     }
 }
 
+fn bin_array_val(lhs: RawValue, op: &ValBinaryOp, rhs: RawValue) -> RawValue {
+    match op {
+        ValBinaryOp::Add => {
+            let mut arr = lhs.into_array();
+            arr.extend(rhs.into_array());
+            RawValue::Array(arr)
+        }
+        op => {
+            warn!("Operator {:?} is not supported on arrays.", op);
+            RawValue::Unit
+        }
+    }
+}
+
 fn bin_logic(ctx: &mut VarTable, lhs: &Expr, op: &LogicBinaryOp, rhs: &Expr) -> RawValue {
     let res = match op {
         LogicBinaryOp::And => lhs.call(ctx).get_bool() && rhs.call(ctx).get_bool(),
@@ -169,6 +225,7 @@ fn bin_logic(ctx: &mut VarTable, lhs: &Expr, op: &LogicBinaryOp, rhs: &Expr) ->
                 ValueType::Bool => bin_ord_logic(&lhs.get_bool(), op, &rhs.get_bool()),
                 ValueType::Num => bin_ord_logic(&lhs.get_num(), op, &rhs.get_num()),
                 ValueType::Str => bin_ord_logic(&lhs.get_str(), op, &rhs.get_str()),
+                ValueType::Array => bin_ord_logic(&lhs.into_array(), op, &rhs.into_array()),
             }
         }
     };
@@ -199,6 +256,8 @@ fn assign(ctx: &mut VarTable, e: &Expr, val: RawValue) -> RawValue {
     RawValue::Unit
 }
 
+const INTRINSICS: &[&str] = &["if", "while", "len", "get", "push", "format_num"];
+
 fn call(ctx: &mut VarTable, ns: &str, name: &str, args: &[Expr]) -> RawValue {
     if ns.is_empty() {
         match name {
@@ -208,22 +267,115 @@ fn call(ctx: &mut VarTable, ns: &str, name: &str, args: &[Expr]) -> RawValue {
                 args.get(2)
             }
             .call(ctx),
-            _ => unimplemented!("intrinstics"),
+            "while" => {
+                let mut res = RawValue::Unit;
+                let mut count = 0;
+                while args.get(0).call(ctx).get_bool() {
+                    if count >= ctx.loop_limit {
+                        warn!(
+                            "The loop exceeds the limit of {} iterations.",
+                            ctx.loop_limit
+                        );
+                        break;
+                    }
+                    for body in args.iter().skip(1) {
+                        res = body.call(ctx);
+                    }
+                    count += 1;
+                }
+                res
+            }
+            "len" => RawValue::Num(match args.get(0).call(ctx) {
+                RawValue::Str(s) => s.chars().count() as i64,
+                RawValue::Array(a) => a.len() as i64,
+                _ => 0,
+            }),
+            "get" => {
+                let arr = args.get(0).call(ctx).into_array();
+                let index = args.get(1).call(ctx).get_num();
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| arr.into_iter().nth(index))
+                    .unwrap_or_default()
+            }
+            "push" => {
+                let mut arr = args.get(0).call(ctx).into_array();
+                arr.extend(args.iter().skip(1).map(|e| e.call(ctx)));
+                RawValue::Array(arr)
+            }
+            "format_num" => {
+                let value = args.get(0).call(ctx).get_num();
+                let digits = args.get(1).call(ctx).get_num().clamp(0, 18) as u32;
+                let (group, decimal) = ctx
+                    .locale
+                    .as_ref()
+                    .and_then(|loc| num_separators(loc))
+                    .unwrap_or(DEFAULT_NUM_SEPARATORS);
+                RawValue::Str(format_num(value, digits, group, decimal))
+            }
+            _ => {
+                if let Some(ns) = ctx.runtime.global_modules.get(name) {
+                    let args = args.iter().map(|e| e.call(ctx)).collect::<Vec<_>>();
+                    dispatch(ctx, ns, name, &args)
+                } else {
+                    error!("Cannot find method `{}`.", name);
+                    RawValue::Unit
+                }
+            }
         }
     } else {
         let args = args.iter().map(|e| e.call(ctx)).collect::<Vec<_>>();
-        if let Some(runtime) = ctx.runtime.modules.get(ns) {
-            match runtime.dispatch_method(name, &args) {
-                Ok(res) => res,
-                Err(e) => {
-                    error!("Calling `{}.{}` error: {}", ns, name, e);
-                    RawValue::Unit
-                }
+        dispatch(ctx, ns, name, &args)
+    }
+}
+
+const DEFAULT_NUM_SEPARATORS: (&str, &str) = (",", ".");
+
+/// Gets the group and decimal separators of numbers for a locale.
+fn num_separators(loc: &Locale) -> Option<(&'static str, &'static str)> {
+    match loc.0.language.as_str() {
+        "en" | "ja" | "zh" | "ko" | "th" | "he" => Some((",", ".")),
+        "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el" => Some((".", ",")),
+        "fr" | "ru" | "uk" | "pl" | "cs" | "sv" | "fi" | "nb" | "hu" => Some(("\u{a0}", ",")),
+        _ => None,
+    }
+}
+
+/// Formats a fixed-point number, with `digits` fractional digits.
+fn format_num(value: i64, digits: u32, group: &str, decimal: &str) -> String {
+    let abs = value.unsigned_abs();
+    let scale = 10u64.pow(digits);
+    let (int, frac) = (abs / scale, abs % scale);
+    let int = int.to_string();
+    let mut res = String::new();
+    if value < 0 {
+        res.push('-');
+    }
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            res.push_str(group);
+        }
+        res.push(c);
+    }
+    if digits > 0 {
+        res.push_str(decimal);
+        res.push_str(&format!("{:0width$}", frac, width = digits as usize));
+    }
+    res
+}
+
+fn dispatch(ctx: &VarTable, ns: &str, name: &str, args: &[RawValue]) -> RawValue {
+    if ctx.runtime.modules.contains_key(ns) {
+        match ctx.runtime.dispatch_method(ns, name, args) {
+            Ok(res) => res,
+            Err(e) => {
+                error!("Calling `{}.{}` error: {}", ns, name, e);
+                RawValue::Unit
             }
-        } else {
-            error!("Cannot find namespace `{}`.", ns);
-            RawValue::Unit
         }
+    } else {
+        error!("Cannot find namespace `{}`.", ns);
+        RawValue::Unit
     }
 }
 
@@ -238,15 +390,10 @@ impl Callable for Ref {
                 warn!("Cannot find context variable `{}`.", n);
                 Default::default()
             }),
-            Self::Res(n) => ctx
-                .res
-                .as_ref()
-                .and_then(|map| map.get(n))
-                .cloned()
-                .unwrap_or_else(|| {
-                    warn!("Cannot find resource `{}`.", n);
-                    Default::default()
-                }),
+            Self::Res(n) => ctx.res.get(n).cloned().unwrap_or_else(|| {
+                warn!("Cannot find resource `{}`.", n);
+                Default::default()
+            }),
         }
     }
 }
@@ -257,17 +404,187 @@ impl Callable for Text {
         for line in &self.0 {
             match line {
                 Line::Str(s) => str.push_str(s),
-                Line::Cmd(c) => {
-                    if let Command::Exec(p) = c {
-                        str.push_str(&p.call(ctx).get_str())
+                Line::Cmd(c) => match c {
+                    Command::Exec(p) => str.push_str(&p.call(ctx).get_str()),
+                    Command::Var(r, spec) => {
+                        let value = r.call(ctx);
+                        match spec {
+                            Some(spec) => str.push_str(&format_value(&value, spec)),
+                            None => str.push_str(&value.get_str()),
+                        }
                     }
-                }
+                    _ => {}
+                },
             }
         }
         RawValue::Str(str.trim().to_string())
     }
 }
 
+struct FormatSpec {
+    align: Option<char>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl FormatSpec {
+    fn parse(spec: &str) -> Option<Self> {
+        fn digits(s: &str) -> Option<usize> {
+            if s.bytes().all(|b| b.is_ascii_digit()) {
+                s.parse().ok()
+            } else {
+                None
+            }
+        }
+
+        let mut rest = spec;
+        let align = match rest.chars().next() {
+            Some(c @ ('<' | '^' | '>')) => {
+                rest = &rest[1..];
+                Some(c)
+            }
+            _ => None,
+        };
+        let zero = rest.starts_with('0');
+        if zero {
+            rest = &rest[1..];
+        }
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => (width, Some(digits(precision)?)),
+            None => (rest, None),
+        };
+        let width = if width.is_empty() { 0 } else { digits(width)? };
+        Some(Self {
+            align,
+            zero,
+            width,
+            precision,
+        })
+    }
+}
+
+/// Formats a value with a spec like the one of `format!` in Rust,
+/// `[align][0][width][.precision]`, e.g., `05` or `>8.2`.
+///
+/// The numbers, including the numeric strings, are aligned right by default,
+/// and the precision formats them as floats.
+/// The other values are aligned left, and the precision truncates them.
+/// If the spec is invalid, a warning is logged and the plain string is returned.
+pub fn format_value(value: &RawValue, spec: &str) -> String {
+    let str = value.get_str();
+    let spec = match FormatSpec::parse(spec) {
+        Some(spec) => spec,
+        None => {
+            warn!("Invalid format spec `{}`.", spec);
+            return str.into_owned();
+        }
+    };
+    let num = match value {
+        RawValue::Num(n) => Some(*n as f64),
+        RawValue::Str(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+    let body = match (spec.precision, num) {
+        (Some(p), Some(num)) => format!("{:.*}", p, num),
+        (Some(p), None) => str.chars().take(p).collect(),
+        (None, _) => str.into_owned(),
+    };
+    let len = body.chars().count();
+    if len >= spec.width {
+        return body;
+    }
+    let fill = spec.width - len;
+    if spec.zero && num.is_some() {
+        let sign = if body.starts_with(['-', '+']) { 1 } else { 0 };
+        return format!("{}{}{}", &body[..sign], "0".repeat(fill), &body[sign..]);
+    }
+    let align = spec.align.unwrap_or(if num.is_some() { '>' } else { '<' });
+    let (left, right) = match align {
+        '<' => (0, fill),
+        '^' => (fill / 2, fill - fill / 2),
+        _ => (fill, 0),
+    };
+    format!("{}{}{}", " ".repeat(left), body, " ".repeat(right))
+}
+
+/// The diagnostic reported by [`analyze`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The left side of an assignment is not a reference.
+    AssignToNonRef,
+    /// The namespace of a call is not a loaded plugin.
+    UnknownNamespace(String),
+    /// The method without namespace is neither an intrinsic nor a global method.
+    UnknownMethod(String),
+    /// Divide or modulo by a literal zero.
+    DivideByZero,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AssignToNonRef => write!(f, "Cannot assign to a non-reference expression."),
+            Self::UnknownNamespace(ns) => write!(f, "Cannot find namespace `{}`.", ns),
+            Self::UnknownMethod(name) => write!(f, "Cannot find method `{}`.", name),
+            Self::DivideByZero => write!(f, "Divide by zero."),
+        }
+    }
+}
+
+/// Analyze the [`Program`] statically, without executing anything.
+pub fn analyze(program: &Program, runtime: &Runtime) -> Vec<Diagnostic> {
+    let mut diag = vec![];
+    for expr in &program.0 {
+        analyze_expr(expr, runtime, &mut diag);
+    }
+    diag
+}
+
+fn analyze_expr(expr: &Expr, runtime: &Runtime, diag: &mut Vec<Diagnostic>) {
+    match expr {
+        Expr::Ref(_) | Expr::Const(_) => {}
+        Expr::Unary(_, e) => analyze_expr(e, runtime, diag),
+        Expr::Binary(lhs, op, rhs) => {
+            match op {
+                BinaryOp::Assign | BinaryOp::Inplace(_) => {
+                    if !matches!(lhs.as_ref(), Expr::Ref(_)) {
+                        diag.push(Diagnostic::AssignToNonRef);
+                    }
+                }
+                _ => {}
+            }
+            match op {
+                BinaryOp::Val(ValBinaryOp::Div | ValBinaryOp::Mod)
+                | BinaryOp::Inplace(ValBinaryOp::Div | ValBinaryOp::Mod) => {
+                    if let Expr::Const(c) = rhs.as_ref() {
+                        if c.try_get_num() == Ok(0) {
+                            diag.push(Diagnostic::DivideByZero);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            analyze_expr(lhs, runtime, diag);
+            analyze_expr(rhs, runtime, diag);
+        }
+        Expr::Call(ns, name, args) => {
+            if ns.is_empty() {
+                if !INTRINSICS.contains(&name.as_str())
+                    && !runtime.global_modules.contains_key(name)
+                {
+                    diag.push(Diagnostic::UnknownMethod(name.clone()));
+                }
+            } else if !runtime.modules.contains_key(ns) {
+                diag.push(Diagnostic::UnknownNamespace(ns.clone()));
+            }
+            for arg in args {
+                analyze_expr(arg, runtime, diag);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::{plugin::Runtime, script::*};
@@ -282,13 +599,20 @@ mod test {
                 let runtime = Runtime::load(
                     "../../examples/plugins",
                     env!("CARGO_MANIFEST_DIR"),
-                    &["format"],
+                    &["format", "random"],
                 );
                 runtime.await.unwrap()
             })
             .await;
         let mut locals = VarMap::default();
-        let mut ctx = VarTable::new(runtime, Fallback::new(None, None), &mut locals);
+        let locale = locale!("de");
+        let res = VarMap::default();
+        let mut ctx = VarTable::new(
+            runtime,
+            Fallback::new(Some(&locale), None),
+            &res,
+            &mut locals,
+        );
         f(&mut ctx);
     }
 
@@ -387,4 +711,187 @@ mod test {
         })
         .await;
     }
+
+    #[tokio::test]
+    async fn global_method() {
+        with_ctx(|ctx| {
+            let res = ProgramParser::new()
+                .parse("rnd(10)")
+                .ok()
+                .call(ctx)
+                .get_num();
+            assert!((0..10).contains(&res));
+            assert_eq!(
+                ProgramParser::new().parse("nowhere(10)").ok().call(ctx),
+                RawValue::Unit
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn analyze_test() {
+        with_ctx(|ctx| {
+            let analyze = |s| analyze(&ProgramParser::new().parse(s).unwrap(), ctx.runtime);
+            assert!(analyze("a = 1; $b = a / 2; format.fmt(\"{}\", rnd())").is_empty());
+            assert_eq!(analyze("a / 0"), [Diagnostic::DivideByZero]);
+            assert_eq!(analyze("a %= 0"), [Diagnostic::DivideByZero]);
+            let assign = Program(vec![Expr::Binary(
+                Box::new(Expr::Const(RawValue::Num(1))),
+                BinaryOp::Assign,
+                Box::new(Expr::Ref(Ref::Var("a".to_string()))),
+            )]);
+            assert_eq!(
+                super::analyze(&assign, ctx.runtime),
+                [Diagnostic::AssignToNonRef]
+            );
+            assert_eq!(
+                analyze("foo.bar()"),
+                [Diagnostic::UnknownNamespace("foo".to_string())]
+            );
+            assert_eq!(
+                analyze("max(1, 2)"),
+                [Diagnostic::UnknownMethod("max".to_string())]
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn while_loop() {
+        with_ctx(|ctx| {
+            ctx.loop_limit = 100;
+            let mut call = |s| ProgramParser::new().parse(s).ok().call(ctx);
+            assert_eq!(
+                call("$i = 0; $s = 0; while($i < 10, $i += 1, $s += $i)"),
+                RawValue::Unit
+            );
+            assert_eq!(call("$s"), RawValue::Num(55));
+            assert_eq!(
+                call("$i = 0; while($i < 3, $i += 1, $i * 2)"),
+                RawValue::Num(6)
+            );
+            assert_eq!(call("while(false, 1)"), RawValue::Unit);
+            call("$i = 0; while(true, $i += 1)");
+            assert_eq!(call("$i"), RawValue::Num(100));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn progress() {
+        with_ctx(|ctx| {
+            let ticks = std::rc::Rc::new(std::cell::Cell::new(vec![]));
+            let program = ProgramParser::new()
+                .parse("$i = 0; while($i < 100, $i += 1); $i")
+                .unwrap();
+            let expected = program.call(ctx);
+
+            let hook_ticks = ticks.clone();
+            ctx.set_progress(50, move |count| {
+                let mut t = hook_ticks.take();
+                t.push(count);
+                hook_ticks.set(t);
+            });
+            assert_eq!(program.call(ctx), expected);
+            let ticks = ticks.take();
+            assert!(!ticks.is_empty());
+            assert!(ticks.iter().all(|count| count % 50 == 0));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn array() {
+        with_ctx(|ctx| {
+            let mut call = |s| ProgramParser::new().parse(s).ok().call(ctx);
+            assert_eq!(call("$a = push(~, 1, \"a\"); len($a)"), RawValue::Num(2));
+            assert_eq!(call("get($a, 1)"), RawValue::Str("a".to_string()));
+            assert_eq!(call("get($a, 2)"), RawValue::Unit);
+            assert_eq!(
+                call("b = $a; b = push(b, true); $a + b"),
+                RawValue::Array(vec![
+                    RawValue::Num(1),
+                    RawValue::Str("a".to_string()),
+                    RawValue::Num(1),
+                    RawValue::Str("a".to_string()),
+                    RawValue::Bool(true),
+                ])
+            );
+            assert_eq!(call("len($a)"), RawValue::Num(2));
+            assert_eq!(call("$a == push(1, \"a\")"), RawValue::Bool(true));
+            assert_eq!(call("$a - 1"), RawValue::Unit);
+            assert_eq!(call("$a * 2"), RawValue::Unit);
+            assert_eq!(call("!$a"), RawValue::Array(vec![]));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn format_num() {
+        with_ctx(|ctx| {
+            let mut call = |s| ProgramParser::new().parse(s).ok().call(ctx).into_str();
+            assert_eq!(call("format_num(1234567)"), "1.234.567");
+            assert_eq!(call("format_num(-123456, 2)"), "-1.234,56");
+            assert_eq!(call("format_num(5, 2)"), "0,05");
+        })
+        .await;
+        assert_eq!(super::format_num(1234567, 0, ",", "."), "1,234,567");
+        assert_eq!(super::format_num(999, 1, ",", "."), "99.9");
+    }
+
+    #[test]
+    fn format_value_int() {
+        use super::format_value;
+
+        assert_eq!(format_value(&RawValue::Num(42), "05"), "00042");
+        assert_eq!(format_value(&RawValue::Num(-42), "05"), "-0042");
+        assert_eq!(format_value(&RawValue::Num(42), "5"), "   42");
+        assert_eq!(format_value(&RawValue::Num(42), "<5"), "42   ");
+        assert_eq!(format_value(&RawValue::Num(123456), "03"), "123456");
+        assert_eq!(format_value(&RawValue::Str("7".to_string()), "03"), "007");
+        assert_eq!(
+            format_value(&RawValue::Str("ab".to_string()), "^6"),
+            "  ab  "
+        );
+        assert_eq!(format_value(&RawValue::Str("ab".to_string()), "04"), "ab  ");
+    }
+
+    #[test]
+    fn format_value_float() {
+        use super::format_value;
+
+        assert_eq!(format_value(&RawValue::Num(3), ".2"), "3.00");
+        assert_eq!(
+            format_value(&RawValue::Str("3.14159".to_string()), ".2"),
+            "3.14"
+        );
+        assert_eq!(
+            format_value(&RawValue::Str("-2.5".to_string()), "07.1"),
+            "-0002.5"
+        );
+        assert_eq!(
+            format_value(&RawValue::Str("hello".to_string()), ".3"),
+            "hel"
+        );
+    }
+
+    #[test]
+    fn format_value_invalid() {
+        use super::format_value;
+
+        assert_eq!(format_value(&RawValue::Num(42), "x5"), "42");
+        assert_eq!(format_value(&RawValue::Num(42), "5."), "42");
+        assert_eq!(format_value(&RawValue::Num(42), "+5"), "42");
+    }
+
+    #[tokio::test]
+    async fn var_text() {
+        with_ctx(|ctx| {
+            ctx.vars.insert("score".to_string(), RawValue::Num(7));
+            let text = TextParser::new(r"Score: \var{score:03}").parse().unwrap();
+            assert_eq!(text.call(ctx), RawValue::Str("Score: 007".to_string()));
+        })
+        .await;
+    }
 }
@@ -1,39 +1,250 @@
 #[doc(no_inline)]
 pub use ayaka_bindings_types::VarMap;
 
-use crate::*;
-use anyhow::{anyhow, Result};
+use crate::{plugin::PluginCall, *};
+use anyhow::{anyhow, bail, Result};
+use ayaka_bindings_types::ActionLine;
 use dirs::{config_dir, data_local_dir};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use futures_util::TryStreamExt;
+use log::LevelFilter;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    io::Write,
+    ops::RangeInclusive,
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 use tokio_stream::wrappers::ReadDirStream;
 
 /// The settings of the game.
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Settings {
     /// The display language.
     pub lang: Locale,
+    /// The autosave interval in seconds.
+    /// `0` disables autosave.
+    #[serde(default = "Settings::default_autosave_interval")]
+    pub autosave_interval: u64,
+    /// The speed of text reveal, relative to the default speed.
+    #[serde(default = "Settings::default_text_speed")]
+    pub text_speed: f32,
+    /// The delay before auto advancing to the next line, in milliseconds.
+    /// `None` disables auto advance.
+    #[serde(default)]
+    pub auto_advance_ms: Option<u32>,
+    /// Compress the record files with gzip.
+    #[serde(default)]
+    pub compress_saves: bool,
+    /// The max log levels of the plugins by name.
+    /// See [`plugin::Runtime::set_log_levels`].
+    #[serde(default)]
+    pub plugin_log_levels: HashMap<String, LevelFilter>,
 }
 
 impl Settings {
+    /// The valid range of [`Settings::text_speed`].
+    pub const TEXT_SPEED_RANGE: RangeInclusive<f32> = 0.1..=10.0;
+    /// The valid range of [`Settings::auto_advance_ms`].
+    pub const AUTO_ADVANCE_MS_RANGE: RangeInclusive<u32> = 100..=60000;
+
     /// Creates [`Settings`] object with current locale.
     pub fn new() -> Self {
         Self {
             lang: Locale::default(),
+            autosave_interval: Self::default_autosave_interval(),
+            text_speed: Self::default_text_speed(),
+            auto_advance_ms: None,
+            compress_saves: false,
+            plugin_log_levels: HashMap::new(),
+        }
+    }
+
+    /// The version of the persisted settings.
+    pub const VERSION: u32 = 1;
+
+    /// Migrate the persisted settings from an older version.
+    ///
+    /// The version `0` is the format before the version is recorded.
+    pub fn migrate(raw: serde_json::Value, from: u32) -> Result<Self> {
+        match from {
+            // The new fields of version 1 all have defaults.
+            0 => Ok(serde_json::from_value(raw)?),
+            _ => bail!("Cannot migrate settings from version {}", from),
         }
     }
+
+    fn from_persisted(raw: serde_json::Value) -> Result<Self> {
+        let version = match raw.get("version") {
+            Some(v) => v
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| anyhow!("Invalid settings version {}", v))?,
+            None => 0,
+        };
+        match version.cmp(&Self::VERSION) {
+            Ordering::Less => Self::migrate(raw, version),
+            Ordering::Equal => Ok(serde_json::from_value(raw)?),
+            Ordering::Greater => bail!("Unsupported settings version {}", version),
+        }
+    }
+
+    fn default_autosave_interval() -> u64 {
+        60
+    }
+
+    fn default_text_speed() -> f32 {
+        1.0
+    }
+
+    /// Clamp the values into the valid ranges.
+    ///
+    /// An invalid [`Settings::text_speed`], e.g., `NaN`, is reset to default.
+    pub fn validate(&mut self) {
+        self.text_speed = if self.text_speed.is_nan() {
+            Self::default_text_speed()
+        } else {
+            self.text_speed.clamp(
+                *Self::TEXT_SPEED_RANGE.start(),
+                *Self::TEXT_SPEED_RANGE.end(),
+            )
+        };
+        self.auto_advance_ms = self.auto_advance_ms.map(|ms| {
+            ms.clamp(
+                *Self::AUTO_ADVANCE_MS_RANGE.start(),
+                *Self::AUTO_ADVANCE_MS_RANGE.end(),
+            )
+        });
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The bundle of all saves of a game.
+///
+/// It is used to move the saves between devices.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SaveBundle {
+    /// The title of the game.
+    pub game: String,
+    /// The settings.
+    pub settings: Settings,
+    /// The global record.
+    pub global_record: GlobalRecord,
+    /// The records.
+    pub records: Vec<ActionRecord>,
+}
+
+impl SaveBundle {
+    /// Serialize the bundle into gzip-compressed JSON.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        serde_json::to_writer(&mut encoder, self)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// Deserialize the bundle, and check if it belongs to the game.
+    pub fn from_bytes(bytes: &[u8], game: &str) -> Result<Self> {
+        let mut bundle: Self = if bytes.starts_with(&GZIP_MAGIC) {
+            serde_json::from_reader(GzDecoder::new(bytes))?
+        } else {
+            serde_json::from_slice(bytes)?
+        };
+        if bundle.game != game {
+            bail!(
+                "The saves belong to \"{}\", but not \"{}\".",
+                bundle.game,
+                game
+            );
+        }
+        bundle.settings.validate();
+        Ok(bundle)
+    }
+}
+
+#[derive(Serialize)]
+struct PersistedSettings<'a> {
+    version: u32,
+    #[serde(flatten)]
+    settings: &'a Settings,
 }
 
 /// The global record.
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+///
+/// The texts of a paragraph up to the maximum index are visited,
+/// so the records of different devices could be merged by [`GlobalRecord::merge`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct GlobalRecord {
     /// The key is the tag of paragraphs,
     /// the value is the maximum text index.
     pub record: HashMap<String, usize>,
+    /// The endings reached, see [`EndReason::Ending`].
+    #[serde(default)]
+    pub endings: BTreeSet<String>,
+    /// The gallery items shown, see [`Context::unlocked_gallery`].
+    #[serde(default)]
+    pub gallery: BTreeSet<String>,
+    /// The switches chosen, as the paragraph tag, the text index and the switch index.
+    /// See [`Context::switch_visited`].
+    #[serde(default)]
+    pub choices: BTreeSet<(String, usize, usize)>,
+}
+
+impl GlobalRecord {
+    /// Merge another record into this one.
+    ///
+    /// The paragraphs are unioned, with the maximum text indices,
+    /// and so are the endings, the gallery items and the choices.
+    /// The merge is associative, commutative and idempotent,
+    /// so the order of syncing the devices doesn't matter.
+    pub fn merge(&mut self, other: &GlobalRecord) {
+        for (tag, &act) in &other.record {
+            let max_act = self.record.entry(tag.clone()).or_insert(act);
+            *max_act = (*max_act).max(act);
+        }
+        self.endings.extend(other.endings.iter().cloned());
+        self.gallery.extend(other.gallery.iter().cloned());
+        self.choices.extend(other.choices.iter().cloned());
+    }
+}
+
+/// A matched action when searching the history.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HistoryMatch {
+    /// The index of the action in the history.
+    pub index: usize,
+    /// The text around the matched part.
+    pub snippet: String,
+}
+
+/// A choice made by the player.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Choice {
+    /// The tag of the paragraph.
+    pub para: String,
+    /// The index of the action in the paragraph.
+    pub act: usize,
+    /// The index of the chosen switch.
+    pub index: usize,
+    /// The text of the chosen switch.
+    pub text: String,
+}
+
+/// The metadata of an [`ActionRecord`], to be shown in the save slots.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct RecordMetadata {
+    /// The save time, in seconds since the UNIX epoch.
+    pub save_time: u64,
+    /// The title of the last paragraph.
+    pub para_title: Option<String>,
+    /// The text of the last action.
+    pub last_text: String,
 }
 
 /// The specific record.
@@ -41,9 +252,192 @@ pub struct GlobalRecord {
 pub struct ActionRecord {
     /// The history actions.
     pub history: Vec<Action>,
+    /// The save time, in seconds since the UNIX epoch.
+    /// It is `0` if the record has never been saved.
+    #[serde(default)]
+    pub save_time: u64,
+    /// The choices made by the player, in order.
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    /// The seed of the random engines at the start of the game.
+    /// The engines are seeded by entropy if it is [`None`],
+    /// or if the record is loaded in the middle.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// The plugin calls, if recorded with [`Context::set_record_calls`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<PluginCall>,
+}
+
+/// An [`ActionRecord`] stored as the difference from a base record.
+///
+/// The history shared with the base record is not stored.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeltaRecord {
+    /// The length of the history shared with the base record.
+    pub base_len: usize,
+    /// The [`ActionRecord::history_hash`] of the base record.
+    /// It is [`None`] for the records saved by the older versions,
+    /// which are not checked.
+    #[serde(default)]
+    pub base_hash: Option<u64>,
+    /// The record, with only the history after `base_len`.
+    pub record: ActionRecord,
+}
+
+impl DeltaRecord {
+    /// Produce the standalone record with the base record.
+    ///
+    /// It fails if the base record is not the one the delta is encoded from,
+    /// or it is shorter than the shared history.
+    pub fn flatten(self, base: &ActionRecord) -> Result<ActionRecord> {
+        if let Some(hash) = self.base_hash {
+            let base_hash = base.history_hash();
+            if hash != base_hash {
+                bail!(
+                    "The base record {:016x} doesn't match the required {:016x}.",
+                    base_hash,
+                    hash
+                );
+            }
+        }
+        if base.history.len() < self.base_len {
+            bail!(
+                "The base record has {} actions, but {} are required.",
+                base.history.len(),
+                self.base_len
+            );
+        }
+        let mut record = self.record;
+        record
+            .history
+            .splice(0..0, base.history[..self.base_len].iter().cloned());
+        Ok(record)
+    }
+}
+
+/// The record file, either standalone or the difference from the base record.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum StoredRecord {
+    Delta(DeltaRecord),
+    Full(ActionRecord),
+}
+
+fn same_action(a: &Action, b: &Action) -> bool {
+    matches!(
+        (serde_json::to_value(a), serde_json::to_value(b)),
+        (Ok(a), Ok(b)) if a == b
+    )
+}
+
+const SNIPPET_CONTEXT: usize = 10;
+
+fn plain_text(action: &Action) -> String {
+    action
+        .line
+        .iter()
+        .filter_map(|line| match line {
+            ActionLine::Chars(s) => Some(s.as_str()),
+            ActionLine::Block(_) | ActionLine::Pause(_) => None,
+        })
+        .collect()
+}
+
+fn find_ignore_case(text: &[char], query: &str) -> Option<usize> {
+    (0..text.len()).find(|&i| {
+        let mut lower = text[i..].iter().flat_map(|c| c.to_lowercase());
+        query.chars().all(|q| lower.next() == Some(q))
+    })
 }
 
 impl ActionRecord {
+    /// Search the history by the text and the character name, case-insensitively.
+    ///
+    /// Only [`ActionLine::Chars`] is searched,
+    /// because [`ActionLine::Block`] usually contains HTML tags or control characters.
+    pub fn search_history(&self, query: &str) -> Vec<HistoryMatch> {
+        let query = query.to_lowercase();
+        if query.is_empty() {
+            return vec![];
+        }
+        let query_len = query.chars().count();
+        self.history
+            .iter()
+            .enumerate()
+            .filter_map(|(index, action)| {
+                let text = plain_text(action).chars().collect::<Vec<_>>();
+                let start = match find_ignore_case(&text, &query) {
+                    Some(start) => start,
+                    None => {
+                        let ch = action.character.as_deref().unwrap_or_default();
+                        if ch.to_lowercase().contains(&query) {
+                            0
+                        } else {
+                            return None;
+                        }
+                    }
+                };
+                let snippet = text[start.saturating_sub(SNIPPET_CONTEXT)
+                    ..(start + query_len + SNIPPET_CONTEXT).min(text.len())]
+                    .iter()
+                    .collect();
+                Some(HistoryMatch { index, snippet })
+            })
+            .collect()
+    }
+
+    /// Encode the record as the difference from the base record.
+    pub fn delta(&self, base: &ActionRecord) -> DeltaRecord {
+        let base_len = self
+            .history
+            .iter()
+            .zip(&base.history)
+            .take_while(|(a, b)| same_action(a, b))
+            .count();
+        DeltaRecord {
+            base_len,
+            base_hash: Some(base.history_hash()),
+            record: ActionRecord {
+                history: self.history[base_len..].to_vec(),
+                save_time: self.save_time,
+                choices: self.choices.clone(),
+                seed: self.seed,
+                calls: self.calls.clone(),
+            },
+        }
+    }
+
+    /// The hash of the history, which identifies the base record of a [`DeltaRecord`].
+    ///
+    /// It is the FNV-1a hash of the JSON, which is stable across platforms and versions.
+    pub fn history_hash(&self) -> u64 {
+        let buffer = serde_json::to_vec(&self.history).unwrap_or_default();
+        buffer.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Set the save time to now.
+    pub fn update_save_time(&mut self) {
+        self.save_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+    }
+
+    /// Get the [`RecordMetadata`] from the last [`Action`] in the history.
+    ///
+    /// The paragraph title is the one resolved with the locale when the action ran.
+    pub fn metadata(&self) -> RecordMetadata {
+        let last_action = self.history.last();
+        RecordMetadata {
+            save_time: self.save_time,
+            para_title: last_action.and_then(|act| act.para_title.clone()),
+            last_text: last_action.map(plain_text).unwrap_or_default(),
+        }
+    }
+
     /// Get the [`RawContext`] object from the last [`Action`] in the history.
     pub fn last_ctx(&self) -> Option<&RawContext> {
         self.history.last().map(|act| &act.ctx)
@@ -66,12 +460,26 @@ impl ActionRecord {
     }
 }
 
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Load the data from a JSON file, which may be compressed with gzip.
 async fn load_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T> {
     let buffer = tokio::fs::read(path).await?;
-    Ok(serde_json::from_slice(&buffer)?)
+    if buffer.starts_with(&GZIP_MAGIC) {
+        Ok(serde_json::from_reader(GzDecoder::new(buffer.as_slice()))?)
+    } else {
+        Ok(serde_json::from_slice(&buffer)?)
+    }
 }
 
-async fn save_file<T: Serialize>(data: &T, path: impl AsRef<Path>, pretty: bool) -> Result<()> {
+/// Write the data to a temp file in the same directory,
+/// and rename it to the target, to avoid corrupting the file on crash.
+async fn save_file<T: Serialize>(
+    data: &T,
+    path: impl AsRef<Path>,
+    pretty: bool,
+    compress: bool,
+) -> Result<()> {
     let path = path.as_ref();
     if let Some(parent) = path.parent() {
         tokio::fs::create_dir_all(parent).await?;
@@ -81,7 +489,18 @@ async fn save_file<T: Serialize>(data: &T, path: impl AsRef<Path>, pretty: bool)
     } else {
         serde_json::to_vec(data)
     }?;
-    tokio::fs::write(path, &buffer).await?;
+    let buffer = if compress {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&buffer)?;
+        encoder.finish()?
+    } else {
+        buffer
+    };
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    tokio::fs::write(&temp_path, &buffer).await?;
+    tokio::fs::rename(&temp_path, path).await?;
     Ok(())
 }
 
@@ -91,13 +510,19 @@ fn settings_path(ident: &str) -> Result<PathBuf> {
 }
 
 /// Load settings from JSON file.
+///
+/// The settings of an older version are migrated with [`Settings::migrate`].
 pub async fn load_settings(ident: &str) -> Result<Settings> {
-    load_file(settings_path(ident)?).await
+    Settings::from_persisted(load_file(settings_path(ident)?).await?)
 }
 
 /// Save settings into pretty JSON file.
 pub async fn save_settings(ident: &str, data: &Settings) -> Result<()> {
-    save_file(data, settings_path(ident)?, true).await
+    let data = PersistedSettings {
+        version: Settings::VERSION,
+        settings: data,
+    };
+    save_file(&data, settings_path(ident)?, true, false).await
 }
 
 fn records_path(ident: &str, game: &str) -> Result<PathBuf> {
@@ -105,6 +530,18 @@ fn records_path(ident: &str, game: &str) -> Result<PathBuf> {
     Ok(path.join(ident).join("save").join(game))
 }
 
+/// The base record saved by the older versions, see [`DeltaRecord::base_hash`].
+const LEGACY_BASE_RECORD: &str = "base.json";
+
+/// The base records are named by the hash,
+/// so that a new base never overwrites the one the existing deltas depend on.
+fn base_record_name(hash: Option<u64>) -> String {
+    match hash {
+        Some(hash) => format!("base-{:016x}.json", hash),
+        None => LEGACY_BASE_RECORD.to_string(),
+    }
+}
+
 fn global_record_path(ident: &str, game: &str) -> Result<PathBuf> {
     Ok(records_path(ident, game)?.join("global.json"))
 }
@@ -116,13 +553,63 @@ pub async fn load_global_record(ident: &str, game: &str) -> Result<GlobalRecord>
 
 /// Save [`GlobalRecord`] into the records folder.
 pub async fn save_global_record(ident: &str, game: &str, data: &GlobalRecord) -> Result<()> {
-    save_file(data, global_record_path(ident, game)?, false).await
+    save_file(data, global_record_path(ident, game)?, false, false).await
+}
+
+fn autosave_record_path(ident: &str, game: &str) -> Result<PathBuf> {
+    Ok(records_path(ident, game)?.join("autosave.json"))
+}
+
+/// Load the autosaved [`ActionRecord`] from the records folder.
+pub async fn load_autosave_record(ident: &str, game: &str) -> Result<ActionRecord> {
+    load_file(autosave_record_path(ident, game)?).await
+}
+
+/// Save the autosaved [`ActionRecord`] into the records folder.
+///
+/// It is stored separately from the records saved by [`save_records`].
+/// If `compress` is true, the file is compressed with gzip.
+pub async fn save_autosave_record(
+    ident: &str,
+    game: &str,
+    data: &ActionRecord,
+    compress: bool,
+) -> Result<()> {
+    save_file(data, autosave_record_path(ident, game)?, false, compress).await
 }
 
 /// Load all [`ActionRecord`] from the records folder.
+///
+/// The records are sorted by the index in the file names.
+/// The records stored as [`DeltaRecord`] are flattened with the base record.
 pub async fn load_records(ident: &str, game: &str) -> Result<Vec<ActionRecord>> {
     let ctx_path = records_path(ident, game)?;
-    let contexts = ReadDirStream::new(tokio::fs::read_dir(ctx_path).await?)
+    let mut files = record_files(&ctx_path).await?;
+    files.sort_by_key(|(i, _)| *i);
+    let mut bases: HashMap<Option<u64>, ActionRecord> = HashMap::new();
+    let mut contexts = vec![];
+    for (_, p) in files {
+        let record = match load_file(p).await? {
+            StoredRecord::Full(record) => record,
+            StoredRecord::Delta(delta) => {
+                if !bases.contains_key(&delta.base_hash) {
+                    let base_path = ctx_path.join(base_record_name(delta.base_hash));
+                    if !base_path.exists() {
+                        bail!("Missing base record {}.", base_path.display());
+                    }
+                    bases.insert(delta.base_hash, load_file(base_path).await?);
+                }
+                let base = &bases[&delta.base_hash];
+                delta.flatten(base)?
+            }
+        };
+        contexts.push(record);
+    }
+    Ok(contexts)
+}
+
+async fn record_files(ctx_path: &Path) -> Result<Vec<(usize, PathBuf)>> {
+    let files = ReadDirStream::new(tokio::fs::read_dir(ctx_path).await?)
         .map_err(anyhow::Error::from)
         .try_filter_map(|entry| async move {
             let p = entry.path();
@@ -130,31 +617,406 @@ pub async fn load_records(ident: &str, game: &str) -> Result<Vec<ActionRecord>>
                 .map(|s| s.to_string_lossy())
                 .unwrap_or_default()
                 == "json"
-                && p.file_stem()
-                    .map(|s| s.to_string_lossy())
-                    .unwrap_or_default()
-                    != "global"
             {
-                Ok(Some(load_file(&p).await?))
+                let index = p
+                    .file_stem()
+                    .and_then(|s| s.to_string_lossy().parse::<usize>().ok());
+                Ok(index.map(|i| (i, p)))
             } else {
                 Ok(None)
             }
         })
         .try_collect()
         .await?;
-    Ok(contexts)
+    Ok(files)
+}
+
+async fn base_record_files(ctx_path: &Path) -> Result<Vec<PathBuf>> {
+    let files = ReadDirStream::new(tokio::fs::read_dir(ctx_path).await?)
+        .map_err(anyhow::Error::from)
+        .try_filter_map(|entry| async move {
+            let p = entry.path();
+            let name = p.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with("base") && name.ends_with(".json") {
+                Ok(Some(p))
+            } else {
+                Ok(None)
+            }
+        })
+        .try_collect()
+        .await?;
+    Ok(files)
 }
 
 /// Save all [`ActionRecord`] into the records folder.
-pub async fn save_records(ident: &str, game: &str, contexts: &[ActionRecord]) -> Result<()> {
+///
+/// The record with the longest history is stored once as the base record,
+/// and every record is stored as a [`DeltaRecord`] from it,
+/// so that the shared history is not duplicated.
+/// The record files with index out of range are removed.
+/// If `compress` is true, the files are compressed with gzip.
+///
+/// The base record is written under a new name before the deltas,
+/// and the old ones are removed only after all deltas are written,
+/// so that the files are always consistent if the saving is interrupted.
+pub async fn save_records(
+    ident: &str,
+    game: &str,
+    contexts: &[ActionRecord],
+    compress: bool,
+) -> Result<()> {
     let ctx_path = records_path(ident, game)?;
-    for (i, ctx) in contexts.iter().enumerate() {
-        save_file(
-            ctx,
-            ctx_path.join(i.to_string()).with_extension("json"),
-            false,
-        )
-        .await?;
+    let base_path = if let Some(base) = contexts.iter().max_by_key(|ctx| ctx.history.len()) {
+        let base_path = ctx_path.join(base_record_name(Some(base.history_hash())));
+        save_file(base, &base_path, false, compress).await?;
+        for (i, ctx) in contexts.iter().enumerate() {
+            save_file(
+                &StoredRecord::Delta(ctx.delta(base)),
+                ctx_path.join(i.to_string()).with_extension("json"),
+                false,
+                compress,
+            )
+            .await?;
+        }
+        Some(base_path)
+    } else {
+        None
+    };
+    if ctx_path.exists() {
+        for (i, p) in record_files(&ctx_path).await? {
+            if i >= contexts.len() {
+                tokio::fs::remove_file(p).await?;
+            }
+        }
+        for p in base_record_files(&ctx_path).await? {
+            if Some(&p) != base_path.as_ref() {
+                tokio::fs::remove_file(p).await?;
+            }
+        }
     }
     Ok(())
 }
+
+/// Export all saves of the game into a [`SaveBundle`].
+///
+/// The missing files are exported as default.
+pub async fn export_saves(ident: &str, game: &str) -> Result<Vec<u8>> {
+    let bundle = SaveBundle {
+        game: game.to_string(),
+        settings: load_settings(ident).await.unwrap_or_default(),
+        global_record: load_global_record(ident, game).await.unwrap_or_default(),
+        records: load_records(ident, game).await.unwrap_or_default(),
+    };
+    bundle.to_bytes()
+}
+
+/// Import all saves of the game from a [`SaveBundle`],
+/// and returns the imported bundle.
+///
+/// The global record of the bundle is merged with the existing one,
+/// see [`GlobalRecord::merge`].
+/// It fails if the bundle doesn't belong to the game.
+pub async fn import_saves(ident: &str, game: &str, bytes: &[u8]) -> Result<SaveBundle> {
+    let mut bundle = SaveBundle::from_bytes(bytes, game)?;
+    if let Ok(global_record) = load_global_record(ident, game).await {
+        bundle.global_record.merge(&global_record);
+    }
+    save_settings(ident, &bundle.settings).await?;
+    save_global_record(ident, game, &bundle.global_record).await?;
+    save_records(ident, game, &bundle.records, bundle.settings.compress_saves).await?;
+    Ok(bundle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PersistedSettings, StoredRecord};
+    use crate::*;
+
+    #[test]
+    fn sorted_locals() {
+        let keys = (0..32).map(|i| format!("v{}", i)).collect::<Vec<_>>();
+        let record = |keys: &mut dyn Iterator<Item = &String>| ActionRecord {
+            history: vec![Action {
+                ctx: RawContext {
+                    locals: keys
+                        .map(|k| (k.clone(), RawValue::Str(k.clone())))
+                        .collect(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let a = record(&mut keys.iter());
+        let b = record(&mut keys.iter().rev());
+        let json = serde_json::to_string(&a).unwrap();
+        assert_eq!(json, serde_json::to_string(&b).unwrap());
+        assert_eq!(
+            rmp_serde::to_vec(&a).unwrap(),
+            rmp_serde::to_vec(&b).unwrap()
+        );
+        let c: ActionRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(c.history[0].ctx.locals, a.history[0].ctx.locals);
+    }
+
+    #[test]
+    fn validate_settings() {
+        let mut settings = Settings {
+            text_speed: 100.0,
+            auto_advance_ms: Some(0),
+            ..Default::default()
+        };
+        settings.validate();
+        assert_eq!(settings.text_speed, 10.0);
+        assert_eq!(settings.auto_advance_ms, Some(100));
+
+        settings.text_speed = f32::NAN;
+        settings.validate();
+        assert_eq!(settings.text_speed, 1.0);
+    }
+
+    #[test]
+    fn plugin_log_levels() {
+        let settings: Settings =
+            serde_json::from_str(r#"{ "lang": "en", "plugin_log_levels": { "random": "WARN" } }"#)
+                .unwrap();
+        assert_eq!(
+            settings.plugin_log_levels.get("random"),
+            Some(&log::LevelFilter::Warn)
+        );
+        let settings: Settings = serde_json::from_str(r#"{ "lang": "en" }"#).unwrap();
+        assert!(settings.plugin_log_levels.is_empty());
+    }
+
+    #[test]
+    fn persisted_settings() {
+        let settings = Settings {
+            text_speed: 2.0,
+            ..Default::default()
+        };
+        let raw = serde_json::to_value(PersistedSettings {
+            version: Settings::VERSION,
+            settings: &settings,
+        })
+        .unwrap();
+        assert_eq!(raw["version"], Settings::VERSION);
+        assert_eq!(Settings::from_persisted(raw).unwrap().text_speed, 2.0);
+
+        let legacy = serde_json::json!({ "lang": "en" });
+        let settings = Settings::from_persisted(legacy).unwrap();
+        assert_eq!(settings.autosave_interval, 60);
+
+        let future = serde_json::json!({ "version": Settings::VERSION + 1, "lang": "en" });
+        assert!(Settings::from_persisted(future).is_err());
+    }
+
+    #[tokio::test]
+    async fn save_file_atomic() {
+        let dir = std::env::temp_dir().join("ayaka-save-file-atomic");
+        let path = dir.join("data.json");
+        super::save_file(&vec![1, 2, 3], &path, false, false)
+            .await
+            .unwrap();
+        super::save_file(&vec![4], &path, false, false)
+            .await
+            .unwrap();
+        let data: Vec<i32> = super::load_file(&path).await.unwrap();
+        assert_eq!(data, [4]);
+        assert!(!dir.join("data.json.tmp").exists());
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn save_file_compress() {
+        let dir = std::env::temp_dir().join("ayaka-save-file-compress");
+        let path = dir.join("data.json");
+        super::save_file(&vec![1, 2, 3], &path, false, true)
+            .await
+            .unwrap();
+        let buffer = tokio::fs::read(&path).await.unwrap();
+        assert!(buffer.starts_with(&super::GZIP_MAGIC));
+        let data: Vec<i32> = super::load_file(&path).await.unwrap();
+        assert_eq!(data, [1, 2, 3]);
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[test]
+    fn save_bundle() {
+        let bundle = SaveBundle {
+            game: "Fibonacci".to_string(),
+            settings: Settings::new(),
+            global_record: GlobalRecord::default(),
+            records: vec![ActionRecord::default()],
+        };
+        let bytes = bundle.to_bytes().unwrap();
+        let imported = SaveBundle::from_bytes(&bytes, "Fibonacci").unwrap();
+        assert_eq!(imported.records.len(), 1);
+        assert!(SaveBundle::from_bytes(&bytes, "Orga").is_err());
+    }
+
+    #[test]
+    fn merge_global_record() {
+        let record = |pairs: &[(&str, usize)]| GlobalRecord {
+            record: pairs
+                .iter()
+                .map(|&(tag, act)| (tag.to_string(), act))
+                .collect(),
+            ..Default::default()
+        };
+        let a = record(&[("init", 3), ("end", 1)]);
+        let b = record(&[("init", 5), ("loop", 2)]);
+        let c = record(&[("end", 4), ("loop", 1)]);
+
+        let mut ab_c = a.clone();
+        ab_c.merge(&b);
+        ab_c.merge(&c);
+        let mut bc = b.clone();
+        bc.merge(&c);
+        let mut a_bc = a.clone();
+        a_bc.merge(&bc);
+        let mut cba = c.clone();
+        cba.merge(&b);
+        cba.merge(&a);
+        assert_eq!(ab_c, record(&[("init", 5), ("end", 4), ("loop", 2)]));
+        assert_eq!(ab_c, a_bc);
+        assert_eq!(ab_c, cba);
+
+        let mut merged = ab_c.clone();
+        merged.merge(&ab_c);
+        merged.merge(&b);
+        assert_eq!(merged, ab_c);
+
+        let mut a = a;
+        a.endings.insert("good".to_string());
+        let mut b = b;
+        b.endings.insert("bad".to_string());
+        b.merge(&a);
+        assert_eq!(b.endings.iter().collect::<Vec<_>>(), ["bad", "good"]);
+        a.gallery.insert("cg1".to_string());
+        b.gallery.insert("cg2".to_string());
+        b.merge(&a);
+        assert_eq!(b.gallery.iter().collect::<Vec<_>>(), ["cg1", "cg2"]);
+    }
+
+    #[test]
+    fn delta_record() {
+        let base = ActionRecord {
+            history: vec![
+                action(None, "a", ""),
+                action(None, "b", ""),
+                action(None, "c", ""),
+            ],
+            ..Default::default()
+        };
+        let record = ActionRecord {
+            history: vec![
+                action(None, "a", ""),
+                action(None, "b", ""),
+                action(None, "d", ""),
+            ],
+            save_time: 1,
+            ..Default::default()
+        };
+        let delta = record.delta(&base);
+        assert_eq!(delta.base_len, 2);
+        assert_eq!(delta.record.history.len(), 1);
+        assert!(delta.clone().flatten(&ActionRecord::default()).is_err());
+        // A different base with the same length is rejected.
+        let other_base = ActionRecord {
+            history: vec![
+                action(None, "a", ""),
+                action(None, "x", ""),
+                action(None, "c", ""),
+            ],
+            ..Default::default()
+        };
+        assert!(delta.clone().flatten(&other_base).is_err());
+        // The older records without the hash are only checked by the length.
+        let legacy = DeltaRecord {
+            base_hash: None,
+            ..delta.clone()
+        };
+        assert!(legacy.flatten(&other_base).is_ok());
+        let flatten = delta.flatten(&base).unwrap();
+        assert_eq!(flatten.save_time, 1);
+        assert_eq!(
+            serde_json::to_value(&flatten.history).unwrap(),
+            serde_json::to_value(&record.history).unwrap()
+        );
+
+        let full = serde_json::to_string(&record).unwrap();
+        assert!(matches!(
+            serde_json::from_str(&full).unwrap(),
+            StoredRecord::Full(_)
+        ));
+    }
+
+    fn action(character: Option<&str>, chars: &str, block: &str) -> Action {
+        let mut action = Action {
+            character: character.map(|s| s.to_string()),
+            ..Default::default()
+        };
+        action.line.push_back_block(block);
+        action.line.push_back_chars(chars);
+        action
+    }
+
+    #[test]
+    fn search_history() {
+        let record = ActionRecord {
+            history: vec![
+                action(Some("Alice"), "Hello, World!", "<b>"),
+                action(
+                    None,
+                    "A long long long long line about the world.",
+                    "<hello>",
+                ),
+                action(Some("Bob"), "Nothing.", ""),
+            ],
+            ..Default::default()
+        };
+        let matches = record.search_history("WORLD");
+        assert_eq!(
+            matches,
+            [
+                HistoryMatch {
+                    index: 0,
+                    snippet: "Hello, World!".to_string()
+                },
+                HistoryMatch {
+                    index: 1,
+                    snippet: "about the world.".to_string()
+                }
+            ]
+        );
+        let matches = record.search_history("hello");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].index, 0);
+        let matches = record.search_history("bob");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].index, 2);
+        assert!(record.search_history("").is_empty());
+    }
+
+    #[test]
+    fn action_voice() {
+        let mut action = action(Some("Alice"), "Hello", "");
+        action.voice = Some("alice_hello".to_string());
+        let record = ActionRecord {
+            history: vec![action],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        let record: ActionRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(record.history[0].voice.as_deref(), Some("alice_hello"));
+
+        let data = rmp_serde::to_vec(&record.history[0]).unwrap();
+        let action: Action = rmp_serde::from_slice(&data).unwrap();
+        assert_eq!(action.voice.as_deref(), Some("alice_hello"));
+
+        let mut legacy = serde_json::to_value(&action).unwrap();
+        legacy.as_object_mut().unwrap().remove("voice");
+        let action: Action = serde_json::from_value(legacy).unwrap();
+        assert!(action.voice.is_none());
+    }
+}
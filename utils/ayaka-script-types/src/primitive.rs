@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
+use std::{borrow::Cow, error::Error, fmt::Display};
 
 /// The basic and only type used in scripts.
 /// ```
@@ -9,6 +9,19 @@ use std::borrow::Cow;
 /// assert_eq!(serde_yaml::from_str::<RawValue>("123").unwrap(), RawValue::Num(123));
 /// assert_eq!(serde_yaml::from_str::<RawValue>("\"hello\"").unwrap(), RawValue::Str("hello".to_string()));
 /// ```
+///
+/// The lenient getters coerce the value to the expected type:
+///
+/// | Variant       | `get_bool()`   | `get_num()`    | `get_str()`               |
+/// | ------------- | -------------- | -------------- | ------------------------- |
+/// | `Unit`        | `false`        | `0`            | `""`                      |
+/// | `Bool(b)`     | `b`            | `0` or `1`     | `"false"` or `"true"`     |
+/// | `Num(i)`      | `i != 0`       | `i`            | decimal digits of `i`     |
+/// | `Str(s)`      | non-empty      | length of `s`  | `s`                       |
+/// | `Array(a)`    | non-empty      | length of `a`  | elements joined by `", "` |
+///
+/// The strict getters, e.g., [`RawValue::try_get_num`], only succeed on the matching variant,
+/// and return a [`TypeError`] otherwise.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum RawValue {
     /// The unit type. It is empty, just like [`None`] or [`()`] in Rust.
@@ -19,6 +32,8 @@ pub enum RawValue {
     Num(i64),
     /// The string type.
     Str(String),
+    /// The array type. It is copied by value.
+    Array(Vec<RawValue>),
 }
 
 /// Represents the type of [`RawValue`].
@@ -32,8 +47,27 @@ pub enum ValueType {
     Num,
     /// The string type.
     Str,
+    /// The array type.
+    Array,
 }
 
+/// The error when a [`RawValue`] is not of the expected [`ValueType`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeError {
+    /// The expected type.
+    pub expected: ValueType,
+    /// The actual type.
+    pub found: ValueType,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Expected {:?}, found {:?}.", self.expected, self.found)
+    }
+}
+
+impl Error for TypeError {}
+
 impl Default for RawValue {
     fn default() -> Self {
         Self::Unit
@@ -48,6 +82,7 @@ impl RawValue {
             Self::Bool(_) => ValueType::Bool,
             Self::Num(_) => ValueType::Num,
             Self::Str(_) => ValueType::Str,
+            Self::Array(_) => ValueType::Array,
         }
     }
 
@@ -55,6 +90,7 @@ impl RawValue {
     /// * A [`RawValue::Unit`] converts to `false`.
     /// * A [`RawValue::Num`] converts to `false` if and only if it's zero.
     /// * A [`RawValue::Str`] converts to `false` if and only if it's empty.
+    /// * A [`RawValue::Array`] converts to `false` if and only if it's empty.
     ///
     /// ```
     /// # use ayaka_script_types::RawValue;
@@ -73,6 +109,7 @@ impl RawValue {
             Self::Bool(b) => *b,
             Self::Num(i) => *i != 0,
             Self::Str(s) => !s.is_empty(),
+            Self::Array(a) => !a.is_empty(),
         }
     }
 
@@ -80,6 +117,7 @@ impl RawValue {
     /// * A [`RawValue::Unit`] converts to 0.
     /// * A [`RawValue::Bool`] converts `false` to 0 and `true` to 1.
     /// * A [`RawValue::Str`] converts to the length of the string.
+    /// * A [`RawValue::Array`] converts to the length of the array.
     ///
     /// ```
     /// # use ayaka_script_types::RawValue;
@@ -96,13 +134,15 @@ impl RawValue {
             Self::Bool(b) => *b as i64,
             Self::Num(i) => *i,
             Self::Str(s) => s.len() as i64,
+            Self::Array(a) => a.len() as i64,
         }
     }
 
     /// Gets a string from the value:
     /// * A [`RawValue::Unit`] converts to empty string.
     /// * A [`RawValue::Bool`] converts to "false" or "true".
-    /// * A [`RawValue::Num`] converts to the string representation of the number.\
+    /// * A [`RawValue::Num`] converts to the string representation of the number.
+    /// * A [`RawValue::Array`] converts to the strings of the elements, joined with `", "`.
     ///
     /// Be careful to use `get_str().into_owned()`, if possible, use `into_str()` instead.
     ///
@@ -114,6 +154,8 @@ impl RawValue {
     /// assert_eq!(bool_value.get_str(), "true");
     /// let num_value = RawValue::Num(123);
     /// assert_eq!(num_value.get_str(), "123");
+    /// let array_value = RawValue::Array(vec![RawValue::Num(1), RawValue::Str("a".to_string())]);
+    /// assert_eq!(array_value.get_str(), "1, a");
     /// ```
     pub fn get_str(&self) -> Cow<str> {
         match self {
@@ -121,6 +163,7 @@ impl RawValue {
             Self::Bool(b) => b.to_string().into(),
             Self::Num(i) => i.to_string().into(),
             Self::Str(s) => s.as_str().into(),
+            Self::Array(a) => join_array(a).into(),
         }
     }
 
@@ -128,16 +171,94 @@ impl RawValue {
     /// * A [`RawValue::Unit`] converts to empty string.
     /// * A [`RawValue::Bool`] converts to "false" or "true".
     /// * A [`RawValue::Num`] converts to the string representation of the number.
+    /// * A [`RawValue::Array`] converts to the strings of the elements, joined with `", "`.
     pub fn into_str(self) -> String {
         match self {
             Self::Unit => String::default(),
             Self::Bool(b) => b.to_string(),
             Self::Num(i) => i.to_string(),
             Self::Str(s) => s,
+            Self::Array(a) => join_array(&a),
+        }
+    }
+
+    fn type_error(&self, expected: ValueType) -> TypeError {
+        TypeError {
+            expected,
+            found: self.get_type(),
+        }
+    }
+
+    /// Gets a boolean only if the value is a [`RawValue::Bool`].
+    ///
+    /// ```
+    /// # use ayaka_script_types::*;
+    /// assert_eq!(RawValue::Bool(true).try_get_bool(), Ok(true));
+    /// assert!(RawValue::Num(1).try_get_bool().is_err());
+    /// ```
+    pub fn try_get_bool(&self) -> Result<bool, TypeError> {
+        match self {
+            Self::Bool(b) => Ok(*b),
+            _ => Err(self.type_error(ValueType::Bool)),
+        }
+    }
+
+    /// Gets a number only if the value is a [`RawValue::Num`].
+    ///
+    /// ```
+    /// # use ayaka_script_types::*;
+    /// assert_eq!(RawValue::Num(123).try_get_num(), Ok(123));
+    /// assert_eq!(
+    ///     RawValue::Str("123".to_string()).try_get_num(),
+    ///     Err(TypeError { expected: ValueType::Num, found: ValueType::Str })
+    /// );
+    /// ```
+    pub fn try_get_num(&self) -> Result<i64, TypeError> {
+        match self {
+            Self::Num(i) => Ok(*i),
+            _ => Err(self.type_error(ValueType::Num)),
+        }
+    }
+
+    /// Gets a string only if the value is a [`RawValue::Str`].
+    pub fn try_get_str(&self) -> Result<&str, TypeError> {
+        match self {
+            Self::Str(s) => Ok(s),
+            _ => Err(self.type_error(ValueType::Str)),
+        }
+    }
+
+    /// Gets an array only if the value is a [`RawValue::Array`].
+    pub fn try_get_array(&self) -> Result<&[RawValue], TypeError> {
+        match self {
+            Self::Array(a) => Ok(a),
+            _ => Err(self.type_error(ValueType::Array)),
+        }
+    }
+
+    /// Gets an array from the value:
+    /// * A [`RawValue::Unit`] converts to empty array.
+    /// * A [`RawValue::Array`] is returned as is.
+    /// * Other values convert to an array with only one element.
+    ///
+    /// ```
+    /// # use ayaka_script_types::RawValue;
+    /// assert_eq!(RawValue::Unit.into_array(), vec![]);
+    /// assert_eq!(RawValue::Num(1).into_array(), vec![RawValue::Num(1)]);
+    /// ```
+    pub fn into_array(self) -> Vec<RawValue> {
+        match self {
+            Self::Unit => vec![],
+            Self::Array(a) => a,
+            v => vec![v],
         }
     }
 }
 
+fn join_array(a: &[RawValue]) -> String {
+    a.iter().map(|v| v.get_str()).collect::<Vec<_>>().join(", ")
+}
+
 impl<'de> Deserialize<'de> for RawValue {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -151,7 +272,7 @@ impl<'de> Deserialize<'de> for RawValue {
             type Value = RawValue;
 
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a unit, boolean, integer, string value")
+                formatter.write_str("a unit, boolean, integer, string or array value")
             }
 
             fn visit_unit<E>(self) -> Result<Self::Value, E>
@@ -188,6 +309,17 @@ impl<'de> Deserialize<'de> for RawValue {
             {
                 Ok(RawValue::Str(v.into()))
             }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut arr = vec![];
+                while let Some(v) = seq.next_element()? {
+                    arr.push(v);
+                }
+                Ok(RawValue::Array(arr))
+            }
         }
         deserializer.deserialize_any(ValueVisitor)
     }
@@ -203,6 +335,7 @@ impl Serialize for RawValue {
             Self::Bool(b) => serializer.serialize_bool(*b),
             Self::Num(n) => serializer.serialize_i64(*n),
             Self::Str(s) => serializer.serialize_str(s),
+            Self::Array(a) => a.serialize(serializer),
         }
     }
 }
@@ -214,7 +347,7 @@ use rt_format::{Format, FormatArgument, Specifier};
 impl FormatArgument for RawValue {
     fn supports_format(&self, specifier: &Specifier) -> bool {
         match self {
-            RawValue::Unit | RawValue::Bool(_) | RawValue::Str(_) => {
+            RawValue::Unit | RawValue::Bool(_) | RawValue::Str(_) | RawValue::Array(_) => {
                 matches!(specifier.format, Format::Debug | Format::Display)
             }
             RawValue::Num(_) => true,
@@ -228,6 +361,7 @@ impl FormatArgument for RawValue {
             RawValue::Bool(b) => b.fmt(f),
             RawValue::Num(n) => n.fmt(f),
             RawValue::Str(s) => s.fmt(f),
+            RawValue::Array(a) => join_array(a).fmt(f),
         }
     }
 
@@ -337,5 +471,40 @@ mod test {
             serde_yaml::to_string(&RawValue::Str("aaa".into())).unwrap(),
             "aaa\n"
         );
+
+        assert_eq!(
+            serde_yaml::from_str::<RawValue>("[1, a, ~]").unwrap(),
+            RawValue::Array(vec![
+                RawValue::Num(1),
+                RawValue::Str("a".into()),
+                RawValue::Unit
+            ])
+        );
+        assert_eq!(
+            serde_yaml::to_string(&RawValue::Array(vec![RawValue::Num(1)])).unwrap(),
+            "- 1\n"
+        );
+    }
+
+    #[test]
+    fn strict_get() {
+        let values = [
+            RawValue::Unit,
+            RawValue::Bool(true),
+            RawValue::Num(1),
+            RawValue::Str("1".into()),
+            RawValue::Array(vec![RawValue::Num(1)]),
+        ];
+        for v in &values {
+            let t = v.get_type();
+            assert_eq!(v.try_get_bool().is_ok(), t == ValueType::Bool);
+            assert_eq!(v.try_get_num().is_ok(), t == ValueType::Num);
+            assert_eq!(v.try_get_str().is_ok(), t == ValueType::Str);
+            assert_eq!(v.try_get_array().is_ok(), t == ValueType::Array);
+        }
+        assert_eq!(
+            RawValue::Unit.try_get_str().unwrap_err().to_string(),
+            "Expected Str, found Unit."
+        );
     }
 }
@@ -1,11 +1,11 @@
 use crate::*;
 
 /// A collection of [`Line`].
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Text(pub Vec<Line>);
 
 /// A part of a line, either some texts or a command.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Line {
     /// Raw texts.
     Str(String),
@@ -14,17 +14,30 @@ pub enum Line {
 }
 
 /// A TeX-like command in the text.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
     /// `\ch{}{}`
     ///
     /// Controls the current character.
     Character(String, String),
+    /// `\voice{}`
+    ///
+    /// Sets the voice of current line.
+    Voice(String),
+    /// `\when{}`
+    ///
+    /// The line is shown only if the program returns true.
+    When(Program),
     /// `\exec{}`
     ///
     /// Executes a program and calculates the return value into text.
     Exec(Program),
-    /// `\switch{}{}{}`
+    /// `\var{}`
+    ///
+    /// Inlines the value of a variable, with an optional format spec after `:`,
+    /// e.g., `\var{score:05}`.
+    Var(Ref, Option<String>),
+    /// `\switch{}{}{}{}`
     ///
     /// A switch.
     Switch {
@@ -33,8 +46,17 @@ pub enum Command {
         /// The action after choosing the switch,
         action: Program,
         /// The expression determines whether the switch is enabled.
+        /// [`None`] if it is omitted or empty, which means enabled.
         enabled: Option<Program>,
+        /// Whether the switch is chosen when the player doesn't act.
+        default: bool,
+        /// The delay in milliseconds before choosing the default switch.
+        timeout_ms: Option<u64>,
     },
+    /// `\wait{}`
+    ///
+    /// Pauses the printing for the milliseconds.
+    Wait(u64),
     /// Other custom commands.
     Other(String, Vec<String>),
 }
@@ -2,7 +2,8 @@
 
 use crate::*;
 use ayaka_script_types::*;
-use lalrpop_util::lalrpop_mod;
+use lalrpop_util::{lalrpop_mod, ParseError};
+use std::{error::Error, fmt::Display};
 
 lalrpop_mod!(
     #[allow(missing_docs)]
@@ -13,6 +14,54 @@ lalrpop_mod!(
 
 pub use grammer::{ConstParser, ExprParser, ProgramParser, RefParser};
 
+/// The error when parsing [`Program`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ScriptParseError {
+    /// The byte offsets of the error, relative to the source.
+    pub loc: Loc,
+    /// The expected tokens.
+    pub expected: Vec<String>,
+    /// The error message.
+    pub message: String,
+}
+
+impl Display for ScriptParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+impl Error for ScriptParseError {}
+
+/// Parse a [`Program`] from the source.
+///
+/// ```
+/// # use ayaka_script::*;
+/// let err = parse_program("a +").unwrap_err();
+/// assert_eq!(err.loc, Loc(3, 4));
+/// assert!(!err.expected.is_empty());
+/// ```
+pub fn parse_program(src: &str) -> Result<Program, ScriptParseError> {
+    ProgramParser::new().parse(src).map_err(|e| {
+        let (loc, expected) = match &e {
+            ParseError::InvalidToken { location } => (Loc(*location, location + 1), vec![]),
+            ParseError::UnrecognizedEOF { location, expected } => {
+                (Loc(*location, location + 1), expected.clone())
+            }
+            ParseError::UnrecognizedToken { token, expected } => {
+                (Loc(token.0, token.2), expected.clone())
+            }
+            ParseError::ExtraToken { token } => (Loc(token.0, token.2), vec![]),
+            ParseError::User { error: _ } => (Loc(0, src.len()), vec![]),
+        };
+        ScriptParseError {
+            loc,
+            expected,
+            message: e.to_string(),
+        }
+    })
+}
+
 #[cfg(test)]
 mod test {
     use crate::exec::*;
@@ -21,6 +70,14 @@ mod test {
         Expr::Ref(Ref::Var(s.into()))
     }
 
+    #[test]
+    fn program_error() {
+        let err = parse_program("a = ;").unwrap_err();
+        assert_eq!(err.loc, Loc(4, 5));
+        assert!(err.expected.contains(&"\"(\"".to_string()));
+        assert!(parse_program("a = 1;").is_ok());
+    }
+
     #[test]
     fn program() {
         assert_eq!(
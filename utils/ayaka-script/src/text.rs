@@ -164,6 +164,10 @@ pub enum ParseErrorType {
     InvalidParamsCount(String, usize),
     /// An error occurred when parsing [`Program`].
     InvalidProgram(String),
+    /// The timeout of `\switch` is not a number of milliseconds.
+    InvalidTimeout(String),
+    /// The variable of `\var` is not a reference.
+    InvalidVar(String),
 }
 
 impl Display for ParseErrorType {
@@ -180,6 +184,8 @@ impl Display for ParseErrorType {
                 name.escape_default()
             )?,
             Self::InvalidProgram(err) => write!(f, "Program parse error: {}", err)?,
+            Self::InvalidTimeout(s) => write!(f, "Invalid timeout \"{}\".", s.escape_default())?,
+            Self::InvalidVar(s) => write!(f, "Invalid variable \"{}\".", s.escape_default())?,
         }
         Ok(())
     }
@@ -499,25 +505,16 @@ impl<'a> TextParser<'a> {
 
     fn parse_program(toks: &[RichToken]) -> ParseResult<Program> {
         let program = Self::concat_params(toks)?;
-        match ProgramParser::new().parse(&program) {
+        match parse_program(&program) {
             Ok(p) => Ok(p),
             Err(e) => {
-                use lalrpop_util::ParseError as ExecParseError;
-
                 let loc = Loc::from_locs(toks.iter().map(|tok| tok.loc));
-                let loc = match &e {
-                    ExecParseError::InvalidToken { location } => {
-                        Loc(loc.0 + location, loc.0 + location + 1)
-                    }
-                    ExecParseError::UnrecognizedEOF {
-                        location: _,
-                        expected: _,
-                    } => Loc(loc.1, loc.1 + 1),
-                    ExecParseError::UnrecognizedToken { token, expected: _ }
-                    | ExecParseError::ExtraToken { token } => Loc(loc.0 + token.0, loc.0 + token.2),
-                    ExecParseError::User { error: _ } => loc,
+                let loc = if e.loc.0 >= program.len() {
+                    Loc(loc.1, loc.1 + 1)
+                } else {
+                    Loc(loc.0 + e.loc.0, loc.0 + e.loc.1)
                 };
-                parse_error(loc, ParseErrorType::InvalidProgram(e.to_string()))
+                parse_error(loc, ParseErrorType::InvalidProgram(e.message))
             }
         }
     }
@@ -559,14 +556,61 @@ impl<'a> TextParser<'a> {
                     )?,
                 )
             }
+            "voice" => {
+                Self::check_params_count(params_count, 1, 1, loc, name)?;
+                Command::Voice(Self::concat_params(&params[0])?)
+            }
             "exec" => {
                 Self::check_params_count(params_count, 1, 1, loc, name)?;
                 Command::Exec(Self::parse_program(&params[0])?)
             }
+            "when" => {
+                Self::check_params_count(params_count, 1, 1, loc, name)?;
+                Command::When(Self::parse_program(&params[0])?)
+            }
+            "wait" => {
+                Self::check_params_count(params_count, 1, 1, loc, name)?;
+                let ms = Self::concat_params(&params[0])?;
+                let ms = ms.trim();
+                Command::Wait(ms.parse().map_err(|_| {
+                    ParseError::new(loc, ParseErrorType::InvalidTimeout(ms.to_string()))
+                })?)
+            }
+            "var" => {
+                Self::check_params_count(params_count, 1, 1, loc, name)?;
+                let var = Self::concat_params(&params[0])?;
+                let (var, spec) = match var.split_once(':') {
+                    Some((var, spec)) => (var, Some(spec.to_string())),
+                    None => (var.as_str(), None),
+                };
+                let var = var.trim();
+                let var = RefParser::new().parse(var).map_err(|_| {
+                    ParseError::new(loc, ParseErrorType::InvalidVar(var.to_string()))
+                })?;
+                Command::Var(var, spec)
+            }
             "switch" => {
-                Self::check_params_count(params_count, 1, 3, loc, name)?;
+                Self::check_params_count(params_count, 1, 4, loc, name)?;
                 let enabled = match params.get(2) {
-                    Some(toks) => Some(Self::parse_program(toks)?),
+                    Some(toks) if !toks.is_empty() => Some(Self::parse_program(toks)?),
+                    _ => None,
+                };
+                // The fourth param marks the default switch, with an optional timeout.
+                let timeout_ms = match params.get(3) {
+                    Some(toks) => {
+                        let timeout = Self::concat_params(toks)?;
+                        let timeout = timeout.trim();
+                        if timeout.is_empty() {
+                            None
+                        } else {
+                            Some(timeout.parse().map_err(|_| {
+                                ParseError::new(
+                                    loc,
+                                    ParseErrorType::InvalidTimeout(timeout.to_string()),
+                                )
+                            })?)
+                        }
+                    }
                     None => None,
                 };
                 Command::Switch {
@@ -577,6 +621,8 @@ impl<'a> TextParser<'a> {
                         Program::default()
                     },
                     enabled,
+                    default: params.len() > 3,
+                    timeout_ms,
                 }
             }
             name => {
@@ -703,13 +749,153 @@ mod test_parser {
             Text(vec![Line::Cmd(Command::Switch {
                 text: "hello".to_string(),
                 action: Program(vec![Expr::Const(RawValue::Str("Hello world!".to_string()))]),
-                enabled: None
+                enabled: None,
+                default: false,
+                timeout_ms: None,
             })])
         );
 
         TextParser::new(r##"\switch{hello}{$s = 2}{a == b}"##)
             .parse()
             .unwrap();
+
+        // An empty condition is the same as an omitted one.
+        assert_eq!(
+            TextParser::new(r##"\switch{hello}{}{}"##).parse().unwrap(),
+            Text(vec![Line::Cmd(Command::Switch {
+                text: "hello".to_string(),
+                action: Program::default(),
+                enabled: None,
+                default: false,
+                timeout_ms: None,
+            })])
+        );
+    }
+
+    #[test]
+    fn switch_default() {
+        assert_eq!(
+            TextParser::new(r##"\switch{wait}{}{}{3000}"##)
+                .parse()
+                .unwrap(),
+            Text(vec![Line::Cmd(Command::Switch {
+                text: "wait".to_string(),
+                action: Program::default(),
+                enabled: None,
+                default: true,
+                timeout_ms: Some(3000),
+            })])
+        );
+        assert_eq!(
+            TextParser::new(r##"\switch{wait}{}{}{}"##).parse().unwrap(),
+            Text(vec![Line::Cmd(Command::Switch {
+                text: "wait".to_string(),
+                action: Program::default(),
+                enabled: None,
+                default: true,
+                timeout_ms: None,
+            })])
+        );
+        assert_eq!(
+            TextParser::new(r##"\switch{wait}{}{}{soon}"##)
+                .parse()
+                .unwrap_err()
+                .to_string(),
+            "Invalid timeout \"soon\"."
+        );
+    }
+
+    #[test]
+    fn when() {
+        assert_eq!(
+            TextParser::new(r##"\when{$a}Hello"##).parse().unwrap(),
+            Text(vec![
+                Line::Cmd(Command::When(Program(vec![Expr::Ref(Ref::Ctx(
+                    "a".to_string()
+                ))]))),
+                Line::Str("Hello".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn wait() {
+        assert_eq!(
+            TextParser::new(r##"Hello\wait{300} world"##)
+                .parse()
+                .unwrap(),
+            Text(vec![
+                Line::Str("Hello".to_string()),
+                Line::Cmd(Command::Wait(300)),
+                Line::Str(" world".to_string()),
+            ])
+        );
+        assert!(TextParser::new(r##"\wait{soon}"##).parse().is_err());
+        assert!(TextParser::new(r##"\wait{}{}"##).parse().is_err());
+    }
+
+    #[test]
+    fn var() {
+        assert_eq!(
+            TextParser::new(r##"Score: \var{score:05}, \var{$name}"##)
+                .parse()
+                .unwrap(),
+            Text(vec![
+                Line::Str("Score: ".to_string()),
+                Line::Cmd(Command::Var(
+                    Ref::Var("score".to_string()),
+                    Some("05".to_string())
+                )),
+                Line::Str(", ".to_string()),
+                Line::Cmd(Command::Var(Ref::Ctx("name".to_string()), None)),
+            ])
+        );
+        assert_eq!(
+            TextParser::new(r##"\var{1 + 1}"##)
+                .parse()
+                .unwrap_err()
+                .to_string(),
+            "Invalid variable \"1 + 1\"."
+        );
+    }
+
+    #[test]
+    fn voice() {
+        assert_eq!(
+            TextParser::new(r##"\voice{hello}Hello"##).parse().unwrap(),
+            Text(vec![
+                Line::Cmd(Command::Voice("hello".to_string())),
+                Line::Str("Hello".to_string()),
+            ])
+        );
+        assert!(TextParser::new(r##"\voice{a}{b}"##).parse().is_err());
+    }
+
+    #[test]
+    fn ruby() {
+        assert_eq!(
+            TextParser::new(r##"\ruby{漢字}{かんじ}です"##)
+                .parse()
+                .unwrap(),
+            Text(vec![
+                Line::Cmd(Command::Other(
+                    "ruby".to_string(),
+                    vec!["漢字".to_string(), "かんじ".to_string()]
+                )),
+                Line::Str("です".to_string()),
+            ])
+        );
+        assert_eq!(
+            TextParser::new(r##"\ruby{漢字}"##).parse().unwrap(),
+            Text(vec![Line::Cmd(Command::Other(
+                "ruby".to_string(),
+                vec!["漢字".to_string()]
+            ))])
+        );
+        assert_eq!(
+            TextParser::new(r##"\ruby{\ruby{漢}{かん}字}{じ}"##).parse(),
+            Err(ParseError::new(Loc(7, 11), ParseErrorType::CmdInCmd))
+        );
     }
 
     #[test]